@@ -0,0 +1,185 @@
+//! Brace expansion: `file{1..3}.txt` becomes `file1.txt file2.txt
+//! file3.txt` and `{a,b}c` becomes `ac bc`. It runs first, on the words as
+//! written, and only unquoted braces and commas count.
+
+use crate::lexer::{Word, WordPart};
+
+/// Piece of a word: a character of unquoted text, or a part that braces
+/// don't look into, like quoted text or a parameter.
+#[derive(Clone)]
+enum Atom<'a> {
+    Char(char),
+    Part(&'a WordPart),
+}
+
+/// The words a word expands to, in order. Words without braces give
+/// themselves.
+pub fn expand(word: &Word) -> Vec<Word> {
+    let mut atoms = Vec::new();
+    for part in &word.parts {
+        match part {
+            WordPart::Literal(text) => atoms.extend(text.chars().map(Atom::Char)),
+            part => atoms.push(Atom::Part(part)),
+        }
+    }
+    if !atoms.iter().any(|atom| matches!(atom, Atom::Char('{'))) {
+        return vec![word.clone()];
+    }
+    expand_atoms(&atoms).iter().map(|atoms| to_word(atoms)).collect()
+}
+
+fn expand_atoms<'a>(atoms: &[Atom<'a>]) -> Vec<Vec<Atom<'a>>> {
+    for (open, atom) in atoms.iter().enumerate() {
+        if !matches!(atom, Atom::Char('{')) {
+            continue;
+        }
+        let Some(alternatives) = alternatives(&atoms[open..]) else {
+            continue;
+        };
+        let close = open + alternatives.length - 1;
+        let prefix = &atoms[..open];
+        let suffixes = expand_atoms(&atoms[close + 1..]);
+
+        let mut results = Vec::new();
+        for alternative in &alternatives.items {
+            for middle in expand_atoms(alternative) {
+                for suffix in &suffixes {
+                    let mut result = prefix.to_vec();
+                    result.extend(middle.iter().cloned());
+                    result.extend(suffix.iter().cloned());
+                    results.push(result);
+                }
+            }
+        }
+        return results;
+    }
+    vec![atoms.to_vec()]
+}
+
+struct Alternatives<'a> {
+    items: Vec<Vec<Atom<'a>>>,
+    /// Number of atoms taken by the braces, both included.
+    length: usize,
+}
+
+/// Reads `{a,b}` or `{1..5}` at the start of the atoms. Gives `None` when
+/// the braces are not closed or hold neither a comma nor a sequence.
+fn alternatives<'a>(atoms: &[Atom<'a>]) -> Option<Alternatives<'a>> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    let mut close = None;
+    for (index, atom) in atoms.iter().enumerate().skip(1) {
+        match atom {
+            Atom::Char('{') => depth += 1,
+            Atom::Char('}') if depth == 0 => {
+                close = Some(index);
+                break;
+            }
+            Atom::Char('}') => depth -= 1,
+            Atom::Char(',') if depth == 0 => commas.push(index),
+            _ => (),
+        }
+    }
+    let close = close?;
+
+    if commas.is_empty() {
+        let inner: Option<String> = atoms[1..close]
+            .iter()
+            .map(|atom| match atom {
+                Atom::Char(c) => Some(*c),
+                Atom::Part(_) => None,
+            })
+            .collect();
+        let items = sequence(&inner?)?
+            .into_iter()
+            .map(|item| item.chars().map(Atom::Char).collect())
+            .collect();
+        return Some(Alternatives { items, length: close + 1 });
+    }
+
+    let mut items = Vec::new();
+    let mut start = 1;
+    for end in commas.into_iter().chain([close]) {
+        items.push(atoms[start..end].to_vec());
+        start = end + 1;
+    }
+    Some(Alternatives { items, length: close + 1 })
+}
+
+/// Expands `1..10`, `10..1..3`, `01..10` or `a..e`.
+fn sequence(text: &str) -> Option<Vec<String>> {
+    let bounds: Vec<&str> = text.split("..").collect();
+    let (first, last, step) = match bounds.as_slice() {
+        [first, last] => (*first, *last, 1),
+        [first, last, step] => (*first, *last, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+
+    if let (Ok(start), Ok(end)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        // A leading zero on either bound pads all numbers to the same width.
+        let padded = |bound: &str| bound.trim_start_matches('-').len() > 1 && bound.trim_start_matches('-').starts_with('0');
+        let width = if padded(first) || padded(last) { first.len().max(last.len()) } else { 0 };
+        return Some(steps(start, end, step).map(|number| format!("{number:0width$}")).collect());
+    }
+
+    let mut first_chars = first.chars();
+    let mut last_chars = last.chars();
+    match (first_chars.next(), first_chars.next(), last_chars.next(), last_chars.next()) {
+        (Some(start), None, Some(end), None) if start.is_ascii_alphabetic() && end.is_ascii_alphabetic() => Some(
+            steps(start as i64, end as i64, step)
+                .filter_map(|code| char::from_u32(code as u32))
+                .map(String::from)
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+fn steps(start: i64, end: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = start.abs_diff(end) / step + 1;
+    let step = if start <= end { step as i64 } else { -(step as i64) };
+    (0..count as i64).map(move |index| start + index * step)
+}
+
+fn to_word(atoms: &[Atom]) -> Word {
+    let mut word = Word::default();
+    for atom in atoms {
+        match atom {
+            Atom::Char(c) => word.push_literal(*c),
+            Atom::Part(part) => word.parts.push((*part).clone()),
+        }
+    }
+    word
+}
+
+#[cfg(test)]
+mod brace_tests {
+    use super::*;
+    use crate::lexer::{tokenize, Token};
+
+    fn expand_line(line: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        for token in tokenize(line).unwrap() {
+            if let Token::Word(word) = token {
+                words.extend(expand(&word).iter().map(Word::unquote));
+            }
+        }
+        words
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(vec!["ac", "bc"], expand_line("{a,b}c"));
+        assert_eq!(vec!["x1", "x2a", "x2b", "x"], expand_line("x{1,2{a,b},}"));
+        assert_eq!(vec!["{a}", "{a,b}", "{}"], expand_line("{a} '{a,b}' {}"));
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(vec!["f1.txt", "f2.txt", "f3.txt"], expand_line("f{1..3}.txt"));
+        assert_eq!(vec!["3", "1", "-1"], expand_line("{3..-1..2}"));
+        assert_eq!(vec!["08", "09", "10"], expand_line("{08..10}"));
+        assert_eq!(vec!["a", "b", "c"], expand_line("{a..c}"));
+        assert_eq!(vec!["{1..x}"], expand_line("{1..x}"));
+    }
+}
@@ -0,0 +1,288 @@
+//! Tab completion of the word under the cursor: command names in the first
+//! word of a command, options and file paths after it. Commands can get
+//! their own completion with the `complete` builtin.
+
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::editor::{Candidate, Completer, Completion};
+use crate::options::OptionSpec;
+use crate::shell::Shell;
+use crate::variables::{self, quote};
+use crate::{platform, BuiltinCommand};
+
+/// How the arguments of a command complete, set with `complete`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CompletionSpec {
+    /// Fixed words, like subcommands.
+    pub words: Vec<String>,
+    pub commands: bool,
+    pub directories: bool,
+    pub files: bool,
+}
+
+impl CompletionSpec {
+    /// The `complete` command setting this spec for `name`.
+    pub fn to_command(&self, name: &str) -> String {
+        let mut command = String::from("complete");
+        for (set, flag) in [(self.commands, " -c"), (self.directories, " -d"), (self.files, " -f")] {
+            if set {
+                command.push_str(flag);
+            }
+        }
+        if !self.words.is_empty() {
+            command.push_str(" -W ");
+            command.push_str(&quote(&self.words.join(" ")));
+        }
+        command.push(' ');
+        command.push_str(&quote(name));
+        command
+    }
+}
+
+/// Completes from the commands, files and specs the shell knows about.
+pub struct ShellCompleter<'a> {
+    shell: &'a Shell,
+}
+
+impl<'a> ShellCompleter<'a> {
+    pub fn new(shell: &'a Shell) -> ShellCompleter<'a> {
+        ShellCompleter { shell }
+    }
+
+    /// Aliases, builtins and executables on the search path starting with
+    /// `prefix`.
+    fn commands(&self, prefix: &str) -> Vec<Candidate> {
+        if prefix.contains('/') {
+            return self.paths(prefix, false);
+        }
+        let mut names: Vec<String> = BuiltinCommand::NAMES
+            .iter()
+            .copied()
+            .chain(self.shell.aliases.keys().map(String::as_str))
+            .chain(self.shell.functions.keys().map(String::as_str))
+            .filter(|name| name.starts_with(prefix))
+            .map(|name| name.to_string())
+            .collect();
+        let search_path = self.shell.variables.get(platform::PATH_VARIABLE).map(platform::split_path).unwrap_or_default();
+        for dir in search_path {
+            let Ok(entries) = fs::read_dir(dir) else { continue };
+            for entry in entries.flatten() {
+                let Ok(name) = entry.file_name().into_string() else { continue };
+                if name.starts_with(prefix) && platform::check_executable(&entry.path()).is_ok() {
+                    names.push(name);
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names.into_iter().map(word_candidate).collect()
+    }
+
+    fn arguments(&self, command: &str, word: &str) -> Vec<Candidate> {
+        let builtin = BuiltinCommand::from_str(command).ok();
+        if let (Some(builtin), true) = (&builtin, word.starts_with('-')) {
+            return options(builtin.options(), word);
+        }
+        let Some(spec) = self.shell.completions.get(command) else {
+            return self.paths(word, matches!(builtin, Some(BuiltinCommand::Cd)));
+        };
+        let mut candidates: Vec<Candidate> = spec
+            .words
+            .iter()
+            .filter(|candidate| candidate.starts_with(word))
+            .cloned()
+            .map(word_candidate)
+            .collect();
+        if spec.commands {
+            candidates.extend(self.commands(word));
+        }
+        if spec.files || spec.directories {
+            candidates.extend(self.paths(word, !spec.files));
+        }
+        candidates
+    }
+
+    /// Files in the directory part of `prefix` whose names start with the
+    /// rest of it. Hidden files only show up when asked for with a dot.
+    fn paths(&self, prefix: &str, directories_only: bool) -> Vec<Candidate> {
+        let (dir_part, name_prefix) = prefix.split_at(prefix.rfind('/').map_or(0, |slash| slash + 1));
+        let dir = match dir_part {
+            "" => PathBuf::from("."),
+            _ => self.expand_home(dir_part),
+        };
+        let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+        let mut candidates: Vec<Candidate> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                if !name.starts_with(name_prefix) || (name.starts_with('.') && !name_prefix.starts_with('.')) {
+                    return None;
+                }
+                let is_dir = entry.path().is_dir();
+                if directories_only && !is_dir {
+                    return None;
+                }
+                let display = if is_dir { format!("{name}/") } else { name };
+                Some(Candidate {
+                    replacement: escape(&format!("{dir_part}{display}")),
+                    display,
+                    finished: !is_dir,
+                })
+            })
+            .collect();
+        candidates.sort_by(|a, b| a.display.cmp(&b.display));
+        candidates
+    }
+
+    /// The directory a path starting with `~` or `~user` stands for.
+    fn expand_home(&self, dir: &str) -> PathBuf {
+        let Some(rest) = dir.strip_prefix('~') else { return PathBuf::from(dir) };
+        let (user, rest) = rest.split_once('/').unwrap_or((rest, ""));
+        let home = match user {
+            "" => self.shell.variables.get("HOME").map(PathBuf::from).or_else(platform::home_dir),
+            _ => platform::user_home_dir(user),
+        };
+        match home {
+            Some(home) => home.join(rest),
+            None => PathBuf::from(dir),
+        }
+    }
+}
+
+impl Completer for ShellCompleter<'_> {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let (mut words, start) = split_command(&line[..cursor]);
+        let word = words.pop().unwrap_or_default();
+        // Assignments before the command are not part of it.
+        let command = words.into_iter().find(|word| variables::split_assignment(word).is_none());
+        let candidates = match command {
+            None => self.commands(&word),
+            Some(command) => self.arguments(&command, &word),
+        };
+        Completion { start, candidates }
+    }
+}
+
+/// Short and long forms of the options starting with `prefix`. Long options
+/// taking a value complete up to the `=`.
+fn options(specs: &[OptionSpec], prefix: &str) -> Vec<Candidate> {
+    let mut names: Vec<(String, bool)> = Vec::new();
+    for spec in specs {
+        if let Some(short) = spec.short {
+            names.push((format!("-{short}"), true));
+        }
+        if let Some(long) = spec.long {
+            match spec.takes_value {
+                true => names.push((format!("--{long}="), false)),
+                false => names.push((format!("--{long}"), true)),
+            }
+        }
+    }
+    names.push(("--help".to_string(), true));
+    names
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .map(|(name, finished)| Candidate { replacement: name.clone(), display: name, finished })
+        .collect()
+}
+
+fn word_candidate(word: String) -> Candidate {
+    Candidate { replacement: escape(&word), display: word, finished: true }
+}
+
+/// Backslash before the characters the shell would otherwise treat
+/// specially. A leading `~` is left alone to still name a home directory.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_whitespace() || "'\"\\$`&|;<>()*?[]{}!#".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Splits the text before the cursor into the words of the command being
+/// typed, with quotes removed. The last word is the one under the cursor,
+/// empty after a space; its byte offset is returned too.
+fn split_command(text: &str) -> (Vec<String>, usize) {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut start = text.len();
+    let mut quote: Option<char> = None;
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        if quote.is_none() && (c.is_whitespace() || "<>;|&()".contains(c)) {
+            words.extend(word.take());
+            // A new command starts after an operator.
+            if ";|&()".contains(c) {
+                words.clear();
+            }
+            continue;
+        }
+        let current = word.get_or_insert_with(|| {
+            start = index;
+            String::new()
+        });
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('\''), c) => current.push(c),
+            (_, '\\') => current.extend(chars.next().map(|(_, next)| next)),
+            (_, c) => current.push(c),
+        }
+    }
+    if word.is_none() {
+        start = text.len();
+    }
+    words.push(word.unwrap_or_default());
+    (words, start)
+}
+
+#[cfg(test)]
+mod completion_tests {
+    use super::*;
+
+    #[test]
+    fn words_before_the_cursor() {
+        assert_eq!((vec![String::new()], 0), split_command(""));
+        assert_eq!((vec!["ls".to_string(), String::new()], 3), split_command("ls "));
+        assert_eq!((vec!["cat".to_string(), "my file".to_string()], 4), split_command("cat 'my file"));
+        assert_eq!((vec!["wc".to_string(), "-l".to_string()], 13), split_command("ls a\\ b | wc -l"));
+        assert_eq!((vec!["cat".to_string(), "out".to_string()], 5), split_command("cat >out"));
+    }
+
+    #[test]
+    fn builtin_options() {
+        let names = |prefix| -> Vec<String> {
+            options(BuiltinCommand::Touch.options(), prefix).into_iter().map(|candidate| candidate.display).collect()
+        };
+        assert_eq!(vec!["-a", "-m", "--help"], names("-"));
+        assert_eq!(vec!["--help"], names("--"));
+        assert_eq!(
+            vec!["--format="],
+            options(BuiltinCommand::History.options(), "--f").into_iter().map(|c| c.replacement).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn file_names_with_spaces() {
+        let dir = std::env::temp_dir().join(format!("completion_tests_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub dir")).unwrap();
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        fs::write(dir.join(".hidden"), "").unwrap();
+        let shell = Shell::default();
+        let completer = ShellCompleter::new(&shell);
+        let prefix = format!("{}/", dir.display());
+        let candidates = completer.paths(&prefix, false);
+        let names: Vec<&str> = candidates.iter().map(|candidate| candidate.display.as_str()).collect();
+        assert_eq!(vec!["notes.txt", "sub dir/"], names);
+        assert_eq!(escape(&format!("{prefix}sub dir/")), candidates[1].replacement);
+        assert!(candidates[1].replacement.ends_with("sub\\ dir/"));
+        assert!(!candidates[1].finished);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
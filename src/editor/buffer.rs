@@ -0,0 +1,201 @@
+//! The text being edited and the cursor position in it.
+
+/// Text of the line with the cursor kept on a character boundary.
+#[derive(Debug, Default)]
+pub struct LineBuffer {
+    text: String,
+    /// Byte offset of the cursor in `text`.
+    cursor: usize,
+}
+
+impl LineBuffer {
+    /// A buffer holding `text` with the cursor at byte `cursor`.
+    pub fn new(text: &str, cursor: usize) -> LineBuffer {
+        LineBuffer { text: text.to_string(), cursor }
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replaces the whole text and moves the cursor to its end.
+    pub fn set(&mut self, text: &str) {
+        self.text = text.to_string();
+        self.cursor = self.text.len();
+    }
+
+    pub fn insert(&mut self, c: char) {
+        self.text.insert(self.cursor, c);
+        self.cursor += c.len_utf8();
+    }
+
+    pub fn insert_str(&mut self, text: &str) {
+        self.text.insert_str(self.cursor, text);
+        self.cursor += text.len();
+    }
+
+    /// Replaces the text from `start` to the cursor, leaving the cursor after
+    /// the new text.
+    pub fn replace_before(&mut self, start: usize, text: &str) {
+        self.text.replace_range(start..self.cursor, text);
+        self.cursor = start + text.len();
+    }
+
+    /// Removes the character before the cursor, returns `false` if there is
+    /// none.
+    pub fn delete_before(&mut self) -> bool {
+        match self.previous_boundary() {
+            Some(start) => {
+                self.text.drain(start..self.cursor);
+                self.cursor = start;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the character under the cursor, returns `false` if there is
+    /// none.
+    pub fn delete_at(&mut self) -> bool {
+        match self.next_boundary() {
+            Some(end) => {
+                self.text.drain(self.cursor..end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if let Some(start) = self.previous_boundary() {
+            self.cursor = start;
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if let Some(end) = self.next_boundary() {
+            self.cursor = end;
+        }
+    }
+
+    pub fn move_home(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_end(&mut self) {
+        self.cursor = self.text.len();
+    }
+
+    /// Moves to the start of the current or previous word, words being runs
+    /// of letters and digits.
+    pub fn move_word_left(&mut self) {
+        self.cursor = self.word_start(char::is_alphanumeric);
+    }
+
+    /// Moves past the end of the current or next word.
+    pub fn move_word_right(&mut self) {
+        let rest = &self.text[self.cursor..];
+        let word = rest.find(char::is_alphanumeric).unwrap_or(rest.len());
+        let end = rest[word..].find(|c: char| !c.is_alphanumeric()).map_or(rest.len(), |end| word + end);
+        self.cursor += end;
+    }
+
+    /// Cuts the text from the cursor to the end of the line.
+    pub fn kill_to_end(&mut self) -> String {
+        self.text.split_off(self.cursor)
+    }
+
+    /// Cuts the text from the start of the line to the cursor.
+    pub fn kill_to_start(&mut self) -> String {
+        let killed = self.text.drain(..self.cursor).collect();
+        self.cursor = 0;
+        killed
+    }
+
+    /// Cuts the whitespace separated word before the cursor, like Ctrl-W.
+    pub fn kill_word_before(&mut self) -> String {
+        let start = self.word_start(|c| !c.is_whitespace());
+        let killed = self.text.drain(start..self.cursor).collect();
+        self.cursor = start;
+        killed
+    }
+
+    /// Start of the word before the cursor, skipping the separators right in
+    /// front of it.
+    fn word_start(&self, is_word: impl Fn(char) -> bool) -> usize {
+        let mut start = self.cursor;
+        let mut chars = self.text[..self.cursor].char_indices().rev().peekable();
+        while let Some((index, _)) = chars.next_if(|&(_, c)| !is_word(c)) {
+            start = index;
+        }
+        while let Some((index, _)) = chars.next_if(|&(_, c)| is_word(c)) {
+            start = index;
+        }
+        start
+    }
+
+    fn previous_boundary(&self) -> Option<usize> {
+        self.text[..self.cursor].char_indices().next_back().map(|(index, _)| index)
+    }
+
+    fn next_boundary(&self) -> Option<usize> {
+        self.text[self.cursor..].chars().next().map(|c| self.cursor + c.len_utf8())
+    }
+}
+
+#[cfg(test)]
+mod line_buffer_tests {
+    use super::*;
+
+    #[test]
+    fn word_motions() {
+        let mut line = LineBuffer::new("cd ../src-dir/ok", 16);
+        line.move_word_left();
+        assert_eq!(14, line.cursor());
+        line.move_word_left();
+        assert_eq!(10, line.cursor());
+        line.move_word_left();
+        line.move_word_left();
+        assert_eq!(0, line.cursor());
+        line.move_word_right();
+        assert_eq!(2, line.cursor());
+        line.move_word_right();
+        assert_eq!(9, line.cursor());
+    }
+
+    #[test]
+    fn killing() {
+        let mut line = LineBuffer::new("echo hello  world", 17);
+        assert_eq!("world", line.kill_word_before());
+        assert_eq!("hello  ", line.kill_word_before());
+        assert_eq!("echo ", line.text());
+        let mut line = LineBuffer::new("echo hello", 4);
+        assert_eq!(" hello", line.kill_to_end());
+        assert_eq!("echo", line.kill_to_start());
+        assert_eq!("", line.text());
+    }
+
+    #[test]
+    fn multi_byte_characters() {
+        let mut line = LineBuffer::default();
+        line.insert_str("日本");
+        line.move_left();
+        line.insert('x');
+        assert_eq!("日x本", line.text());
+        assert!(line.delete_before());
+        assert!(line.delete_at());
+        assert_eq!("日", line.text());
+    }
+
+    #[test]
+    fn replacing_the_word_before_the_cursor() {
+        let mut line = LineBuffer::new("cat sr | wc", 6);
+        line.replace_before(4, "src/");
+        assert_eq!("cat src/ | wc", line.text());
+        assert_eq!(8, line.cursor());
+    }
+}
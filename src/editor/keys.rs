@@ -0,0 +1,146 @@
+//! Decoding of the bytes a terminal sends for key presses.
+
+use std::io;
+
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    Char(char),
+    /// A letter pressed together with Ctrl, in lowercase.
+    Ctrl(char),
+    /// A character pressed together with Alt, or after Escape.
+    Alt(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    CtrlLeft,
+    CtrlRight,
+    /// A sequence this editor has no use for.
+    Unknown,
+    /// Not a key: the terminal changed size while waiting for one.
+    Resize,
+}
+
+/// Decodes one key press starting with the byte `first`. `next_byte` reads
+/// the following bytes of a multi-byte sequence.
+pub fn decode_key(first: u8, next_byte: &mut impl FnMut() -> io::Result<u8>) -> io::Result<Key> {
+    Ok(match first {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        DELETE | 0x08 => Key::Backspace,
+        ESCAPE => decode_escape(next_byte)?,
+        0x01..=0x1a => Key::Ctrl((b'a' + first - 1) as char),
+        0x00..=0x1f => Key::Unknown,
+        _ => match decode_char(first, next_byte)? {
+            Some(c) => Key::Char(c),
+            None => Key::Unknown,
+        },
+    })
+}
+
+/// Reads the rest of a UTF-8 encoded character.
+fn decode_char(first: u8, next_byte: &mut impl FnMut() -> io::Result<u8>) -> io::Result<Option<char>> {
+    let length = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(None),
+    };
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        bytes.push(next_byte()?);
+    }
+    Ok(std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()))
+}
+
+fn decode_escape(next_byte: &mut impl FnMut() -> io::Result<u8>) -> io::Result<Key> {
+    let second = next_byte()?;
+    match second {
+        b'[' => decode_csi(next_byte),
+        b'O' => Ok(match next_byte()? {
+            b'H' => Key::Home,
+            b'F' => Key::End,
+            b'C' => Key::Right,
+            b'D' => Key::Left,
+            b'A' => Key::Up,
+            b'B' => Key::Down,
+            _ => Key::Unknown,
+        }),
+        DELETE => Ok(Key::Alt('\x7f')),
+        _ => Ok(match decode_char(second, next_byte)? {
+            Some(c) => Key::Alt(c.to_ascii_lowercase()),
+            None => Key::Unknown,
+        }),
+    }
+}
+
+/// Reads `ESC [ parameters final`, like `ESC [ 3 ~` for Delete or
+/// `ESC [ 1 ; 5 D` for Ctrl-Left.
+fn decode_csi(next_byte: &mut impl FnMut() -> io::Result<u8>) -> io::Result<Key> {
+    let mut parameters = String::new();
+    let last = loop {
+        let byte = next_byte()?;
+        if (0x40..=0x7e).contains(&byte) {
+            break byte;
+        }
+        parameters.push(byte as char);
+    };
+    let ctrl = parameters.ends_with(";5");
+    Ok(match (last, parameters.as_str()) {
+        (b'A', _) => Key::Up,
+        (b'B', _) => Key::Down,
+        (b'C', _) if ctrl => Key::CtrlRight,
+        (b'D', _) if ctrl => Key::CtrlLeft,
+        (b'C', _) => Key::Right,
+        (b'D', _) => Key::Left,
+        (b'H', _) | (b'~', "1" | "7") => Key::Home,
+        (b'F', _) | (b'~', "4" | "8") => Key::End,
+        (b'~', "3") => Key::Delete,
+        _ => Key::Unknown,
+    })
+}
+
+#[cfg(test)]
+mod key_tests {
+    use super::*;
+
+    fn decode(bytes: &[u8]) -> Key {
+        let mut rest = bytes[1..].iter().copied();
+        let mut next_byte = || rest.next().ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof));
+        decode_key(bytes[0], &mut next_byte).unwrap()
+    }
+
+    #[test]
+    fn control_and_alt_keys() {
+        assert_eq!(Key::Ctrl('a'), decode(&[0x01]));
+        assert_eq!(Key::Ctrl('w'), decode(&[0x17]));
+        assert_eq!(Key::Alt('b'), decode(b"\x1bb"));
+        assert_eq!(Key::Enter, decode(b"\r"));
+        assert_eq!(Key::Backspace, decode(&[0x7f]));
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(Key::Up, decode(b"\x1b[A"));
+        assert_eq!(Key::Home, decode(b"\x1bOH"));
+        assert_eq!(Key::Delete, decode(b"\x1b[3~"));
+        assert_eq!(Key::CtrlLeft, decode(b"\x1b[1;5D"));
+    }
+
+    #[test]
+    fn multi_byte_characters() {
+        assert_eq!(Key::Char('é'), decode("é".as_bytes()));
+        assert_eq!(Key::Char('日'), decode("日".as_bytes()));
+        assert_eq!(Key::Char('🚀'), decode("🚀".as_bytes()));
+    }
+}
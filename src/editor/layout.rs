@@ -0,0 +1,126 @@
+//! Where text ends up on the terminal: wide characters take two columns,
+//! escape sequences none, and lines wrap at the terminal width.
+
+use unicode_width::UnicodeWidthChar;
+
+const ESCAPE: char = '\x1b';
+
+/// Visible characters of a text with their widths, leaving out escape
+/// sequences like colors.
+fn visible_chars(text: &str) -> impl Iterator<Item = (char, usize)> + '_ {
+    let mut chars = text.chars().peekable();
+    std::iter::from_fn(move || loop {
+        let c = chars.next()?;
+        if c == ESCAPE && chars.next_if_eq(&'[').is_some() {
+            // Parameters up to the final letter.
+            while chars.next_if(|c| !('@'..='~').contains(c)).is_some() {}
+            chars.next();
+            continue;
+        }
+        return Some((c, c.width().unwrap_or(0)));
+    })
+}
+
+/// Number of columns the text takes on one line.
+pub fn display_width(text: &str) -> usize {
+    visible_chars(text).map(|(_, width)| width).sum()
+}
+
+/// The start of the text fitting in `width` columns.
+pub fn truncate(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if c == ESCAPE && chars.next_if(|&(_, c)| c == '[').is_some() {
+            while chars.next_if(|&(_, c)| !('@'..='~').contains(&c)).is_some() {}
+            chars.next();
+            continue;
+        }
+        used += c.width().unwrap_or(0);
+        if used > width {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Lays the items out in columns filling the terminal width, going down the
+/// first column before the next one like `ls`. Gives the rows.
+pub fn columns(items: &[&str], columns: usize) -> Vec<String> {
+    const GAP: usize = 2;
+    let width = items.iter().map(|item| display_width(item)).max().unwrap_or(0) + GAP;
+    let per_row = (columns / width).max(1);
+    let rows = items.len().div_ceil(per_row);
+    (0..rows)
+        .map(|row| {
+            let mut line = String::new();
+            for item in items.iter().skip(row).step_by(rows) {
+                line.push_str(item);
+                line.extend(std::iter::repeat_n(' ', width - display_width(item)));
+            }
+            line.trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Row and column reached after writing the text from the start of a row
+/// on a terminal `columns` wide. A column equal to `columns` means the last
+/// row is full and the terminal waits for the next character to wrap.
+pub fn position_after(text: &str, columns: usize) -> (usize, usize) {
+    let columns = columns.max(1);
+    let (mut row, mut column) = (0, 0);
+    for (c, width) in visible_chars(text) {
+        if c == '\n' {
+            row += 1;
+            column = 0;
+            continue;
+        }
+        if column + width > columns {
+            row += 1;
+            column = 0;
+        }
+        column += width;
+    }
+    (row, column)
+}
+
+/// Same as `position_after`, with a full row counted as the start of the
+/// next one, which is where the cursor shows up.
+pub fn cursor_position(text: &str, columns: usize) -> (usize, usize) {
+    match position_after(text, columns) {
+        (row, column) if column >= columns => (row + 1, 0),
+        position => position,
+    }
+}
+
+#[cfg(test)]
+mod layout_tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        assert_eq!(5, display_width("\x1b[94mhello\x1b[0m"));
+        assert_eq!(4, display_width("日本"));
+        assert_eq!(3, display_width("a🚀"));
+        assert_eq!("a日", truncate("a日本", 4));
+        assert_eq!("\x1b[7mab", truncate("\x1b[7mabc", 2));
+    }
+
+    #[test]
+    fn wrapping() {
+        assert_eq!((0, 4), position_after("abcd", 10));
+        assert_eq!((0, 10), position_after("0123456789", 10));
+        assert_eq!((1, 0), cursor_position("0123456789", 10));
+        assert_eq!((1, 1), position_after("0123456789x", 10));
+        // A wide character that doesn't fit goes to the next row.
+        assert_eq!((1, 2), position_after("012345678日", 10));
+        assert_eq!((1, 3), position_after("> \x1b[1mx\x1b[0m\n$ x", 10));
+    }
+
+    #[test]
+    fn items_in_columns() {
+        let items = ["a", "bb", "日本", "d", "e"];
+        assert_eq!(vec!["a     日本  e", "bb    d"], columns(&items, 18));
+        assert_eq!(vec!["a", "bb", "日本", "d", "e"], columns(&items, 5));
+    }
+}
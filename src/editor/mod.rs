@@ -0,0 +1,341 @@
+//! Line editing at the prompt: the terminal is put in raw mode and every key
+//! press is handled here, with Emacs style keys and history navigation.
+
+mod buffer;
+mod keys;
+mod layout;
+mod search;
+
+use std::io::{self, IsTerminal, Read, Write};
+
+use crate::history::HistoryEntry;
+use crate::{input, platform, CLEAR_SCREEN};
+use buffer::LineBuffer;
+use keys::Key;
+use search::SearchEnd;
+
+/// Source of the candidates offered by Tab.
+pub trait Completer {
+    /// Candidates for the word ending at byte `cursor` of `line`.
+    fn complete(&self, line: &str, cursor: usize) -> Completion;
+}
+
+pub struct Completion {
+    /// Byte offset where the completed word starts.
+    pub start: usize,
+    pub candidates: Vec<Candidate>,
+}
+
+pub struct Candidate {
+    /// Text put in place of the word, quoted as needed.
+    pub replacement: String,
+    /// Text shown when listing the candidates.
+    pub display: String,
+    /// A space goes after the word, unlike after a directory that can be
+    /// completed further.
+    pub finished: bool,
+}
+
+/// Reads lines at the terminal, keeping what the screen looks like between
+/// key presses.
+#[derive(Default)]
+pub struct Editor {
+    /// Row of the cursor counted from the first row of the prompt.
+    cursor_row: usize,
+    /// Text last cut with Ctrl-K, Ctrl-U or Ctrl-W, pasted back by Ctrl-Y.
+    killed: String,
+    /// Ctrl-R ranks lines by fuzzy matching instead of looking for the typed
+    /// text.
+    pub fuzzy_search: bool,
+    /// Text shown at the right end of the first input row while the line
+    /// leaves room for it.
+    pub right_prompt: String,
+}
+
+/// The line being edited and where it stands in the history.
+struct EditState<'a> {
+    prompt: &'a str,
+    buffer: LineBuffer,
+    history: &'a [HistoryEntry],
+    /// Entry of `history` shown, `history.len()` for the new line.
+    history_index: usize,
+    /// The new line, kept while browsing the history.
+    draft: String,
+}
+
+impl EditState<'_> {
+    fn show_history(&mut self, index: usize) {
+        if self.history_index == self.history.len() {
+            self.draft = self.buffer.text().to_string();
+        }
+        self.history_index = index;
+        match self.history.get(index) {
+            Some(entry) => self.buffer.set(&entry.line),
+            None => self.buffer.set(&self.draft),
+        }
+    }
+}
+
+impl Editor {
+    pub fn new() -> Editor {
+        platform::watch_resize();
+        Editor::default()
+    }
+
+    /// Shows the prompt and reads one line, `None` at the end of the input.
+    /// Up and Down go through `history`, oldest entry first, and Tab asks
+    /// `completer` for the words to complete. Ctrl-C cancels the line with
+    /// an `Interrupted` error.
+    ///
+    /// Falls back to plain line reading when not at a terminal.
+    pub fn read_line(
+        &mut self,
+        prompt: &str,
+        history: &[HistoryEntry],
+        completer: &dyn Completer,
+    ) -> io::Result<Option<String>> {
+        let raw_mode = match io::stdin().is_terminal() && io::stdout().is_terminal() {
+            true => platform::enable_raw_mode().ok(),
+            false => None,
+        };
+        if raw_mode.is_none() {
+            print!("{prompt}");
+            io::stdout().flush()?;
+            return input::read_line();
+        }
+        let mut state = EditState {
+            prompt,
+            buffer: LineBuffer::default(),
+            history,
+            history_index: history.len(),
+            draft: String::new(),
+        };
+        self.cursor_row = 0;
+        self.refresh(&state)?;
+        let mut pending = None;
+        loop {
+            let key = match pending.take() {
+                Some(key) => key,
+                None => match read_key()? {
+                    Some(key) => key,
+                    None if state.buffer.text().is_empty() => return Ok(None),
+                    None => return self.finish(&mut state).map(Some),
+                },
+            };
+            let buffer = &mut state.buffer;
+            match key {
+                Key::Enter => return self.finish(&mut state).map(Some),
+                Key::Ctrl('d') if buffer.text().is_empty() => return Ok(None),
+                Key::Ctrl('c') => {
+                    write_terminal("^C\r\n")?;
+                    return Err(io::ErrorKind::Interrupted.into());
+                }
+                Key::Char(c) => buffer.insert(c),
+                Key::Tab => self.complete(&mut state, completer)?,
+                Key::Ctrl('r') => match self.search(&mut state)? {
+                    SearchEnd::Run => return self.finish(&mut state).map(Some),
+                    SearchEnd::Edit(key) => pending = key,
+                },
+                Key::Resize => self.resized(state.prompt, &state.buffer),
+                Key::Backspace | Key::Ctrl('h') => {
+                    buffer.delete_before();
+                }
+                Key::Delete | Key::Ctrl('d') => {
+                    buffer.delete_at();
+                }
+                Key::Left | Key::Ctrl('b') => buffer.move_left(),
+                Key::Right | Key::Ctrl('f') => buffer.move_right(),
+                Key::Home | Key::Ctrl('a') => buffer.move_home(),
+                Key::End | Key::Ctrl('e') => buffer.move_end(),
+                Key::CtrlLeft | Key::Alt('b') => buffer.move_word_left(),
+                Key::CtrlRight | Key::Alt('f') => buffer.move_word_right(),
+                Key::Ctrl('k') => self.killed = buffer.kill_to_end(),
+                Key::Ctrl('u') => self.killed = buffer.kill_to_start(),
+                Key::Ctrl('w') | Key::Alt('\x7f') => self.killed = buffer.kill_word_before(),
+                Key::Ctrl('y') => buffer.insert_str(&self.killed),
+                Key::Ctrl('l') => {
+                    write_terminal(CLEAR_SCREEN)?;
+                    self.cursor_row = 0;
+                }
+                Key::Up | Key::Ctrl('p') if state.history_index > 0 => state.show_history(state.history_index - 1),
+                Key::Down | Key::Ctrl('n') if state.history_index < history.len() => {
+                    state.show_history(state.history_index + 1)
+                }
+                _ => continue,
+            }
+            self.refresh(&state)?;
+        }
+    }
+
+    /// Completes the word before the cursor as far as all candidates agree,
+    /// and lists them when there are several.
+    fn complete(&mut self, state: &mut EditState, completer: &dyn Completer) -> io::Result<()> {
+        let Completion { start, candidates } = completer.complete(state.buffer.text(), state.buffer.cursor());
+        match candidates.as_slice() {
+            [] => write_terminal(BELL),
+            [candidate] => {
+                state.buffer.replace_before(start, &candidate.replacement);
+                if candidate.finished {
+                    state.buffer.insert(' ');
+                }
+                Ok(())
+            }
+            _ => {
+                let common = candidates
+                    .iter()
+                    .map(|candidate| candidate.replacement.as_str())
+                    .reduce(common_prefix)
+                    .unwrap_or_default();
+                if common.len() > state.buffer.cursor() - start {
+                    state.buffer.replace_before(start, common);
+                }
+                let names: Vec<&str> = candidates.iter().map(|candidate| candidate.display.as_str()).collect();
+                self.print_below(state, &names)
+            }
+        }
+    }
+
+    /// Prints the items in columns under the line being edited. The line is
+    /// drawn again below them by the next refresh.
+    fn print_below(&mut self, state: &EditState, items: &[&str]) -> io::Result<()> {
+        let columns = platform::terminal_width().unwrap_or(DEFAULT_COLUMNS);
+        let (end_row, _) = layout::cursor_position(&format!("{}{}", state.prompt, state.buffer.text()), columns);
+        let mut output = String::new();
+        if end_row > self.cursor_row {
+            output.push_str(&format!("\x1b[{}B", end_row - self.cursor_row));
+        }
+        output.push_str("\r\n");
+        for row in layout::columns(items, columns) {
+            output.push_str(&row);
+            output.push_str("\r\n");
+        }
+        self.cursor_row = 0;
+        write_terminal(&output)
+    }
+
+    /// Leaves the cursor below the finished line and gives the line.
+    fn finish(&mut self, state: &mut EditState) -> io::Result<String> {
+        state.buffer.move_end();
+        self.refresh(state)?;
+        write_terminal("\r\n")?;
+        Ok(state.buffer.text().to_string())
+    }
+
+    /// Finds the cursor again after the terminal got a new width, assuming it
+    /// rewrapped the lines already drawn.
+    fn resized(&mut self, prompt: &str, buffer: &LineBuffer) {
+        let columns = platform::terminal_width().unwrap_or(DEFAULT_COLUMNS);
+        let before_cursor = format!("{prompt}{}", &buffer.text()[..buffer.cursor()]);
+        self.cursor_row = layout::cursor_position(&before_cursor, columns).0;
+    }
+
+    /// Draws the prompt and the line again and puts the cursor in place.
+    fn refresh(&mut self, state: &EditState) -> io::Result<()> {
+        let right_prompt = self.right_prompt.clone();
+        self.draw(state.prompt, &state.buffer, &right_prompt, &[])
+    }
+
+    /// Draws the prompt, the line, the right prompt when it fits and the
+    /// lines `below`, which are cut to the terminal width, and puts the
+    /// cursor in place.
+    fn draw(&mut self, prompt: &str, buffer: &LineBuffer, right_prompt: &str, below: &[String]) -> io::Result<()> {
+        let columns = platform::terminal_width().unwrap_or(DEFAULT_COLUMNS);
+        let text = buffer.text();
+        let mut output = String::new();
+        if self.cursor_row > 0 {
+            output.push_str(&format!("\x1b[{}A", self.cursor_row));
+        }
+        output.push_str("\r\x1b[J");
+        output.push_str(prompt);
+        output.push_str(text);
+
+        let (mut end_row, end_column) = layout::position_after(&format!("{prompt}{text}"), columns);
+        let right_width = layout::display_width(right_prompt);
+        let input_row = layout::position_after(prompt, columns).0;
+        if right_width > 0 && end_row == input_row && end_column + right_width + 1 < columns {
+            // The last column stays empty so the terminal doesn't wrap.
+            output.push_str(&format!("\x1b[{}G{right_prompt}{RESET_STYLE}", columns - right_width));
+        }
+        if end_column >= columns {
+            // The terminal only wraps on the next character, move down now so
+            // the cursor can be placed from there.
+            output.push_str("\r\n");
+            end_row += 1;
+        }
+        for line in below {
+            output.push_str("\r\n");
+            output.push_str(layout::truncate(line, columns - 1));
+            output.push_str(RESET_STYLE);
+            end_row += 1;
+        }
+        let before_cursor = format!("{prompt}{}", &text[..buffer.cursor()]);
+        let (row, column) = layout::cursor_position(&before_cursor, columns);
+        if end_row > row {
+            output.push_str(&format!("\x1b[{}A", end_row - row));
+        }
+        output.push('\r');
+        if column > 0 {
+            output.push_str(&format!("\x1b[{column}C"));
+        }
+        self.cursor_row = row;
+        write_terminal(&output)
+    }
+}
+
+const BELL: &str = "\x07";
+
+/// Longest start the two texts share, cut on a character boundary.
+fn common_prefix<'a>(first: &'a str, second: &str) -> &'a str {
+    let length = first
+        .char_indices()
+        .zip(second.chars())
+        .find(|((_, a), b)| a != b)
+        .map_or(first.len().min(second.len()), |((index, _), _)| index);
+    &first[..length]
+}
+
+/// Width used when the terminal doesn't tell its own.
+const DEFAULT_COLUMNS: usize = 80;
+
+/// Ends colors and other styles started by a text.
+const RESET_STYLE: &str = "\x1b[0m";
+
+/// Reads the next key press, `None` at the end of the input. A change of the
+/// terminal size while waiting gives `Key::Resize`.
+fn read_key() -> io::Result<Option<Key>> {
+    let first = loop {
+        match read_byte() {
+            Ok(Some(byte)) => break byte,
+            Ok(None) => return Ok(None),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                if platform::take_resize() {
+                    return Ok(Some(Key::Resize));
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    let key = keys::decode_key(first, &mut || loop {
+        match read_byte() {
+            Ok(Some(byte)) => return Ok(byte),
+            Ok(None) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    })?;
+    Ok(Some(key))
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match io::stdin().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+fn write_terminal(text: &str) -> io::Result<()> {
+    let mut stdout = io::stdout();
+    stdout.write_all(text.as_bytes())?;
+    stdout.flush()
+}
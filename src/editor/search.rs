@@ -0,0 +1,229 @@
+//! Ctrl-R search through the history. The plain search shows the latest
+//! line containing the text typed so far, and every Ctrl-R goes to an older
+//! one. The fuzzy search ranks lines holding the typed characters in order
+//! and lists the best ones under the prompt.
+
+use std::collections::HashSet;
+use std::io;
+
+use super::buffer::LineBuffer;
+use super::keys::Key;
+use super::{read_key, write_terminal, EditState, Editor, BELL};
+use crate::history::HistoryEntry;
+
+/// Number of lines listed by the fuzzy search.
+const SHOWN_MATCHES: usize = 5;
+
+const REVERSE_VIDEO: &str = "\x1b[7m";
+
+/// How a search ended.
+pub enum SearchEnd {
+    /// Enter was pressed, the found line runs.
+    Run,
+    /// Editing goes on with the found line, starting with the key that ended
+    /// the search if any.
+    Edit(Option<Key>),
+}
+
+struct Search<'a> {
+    history: &'a [HistoryEntry],
+    fuzzy: bool,
+    query: String,
+    /// Indexes of the matching entries, the most recent or best first. A line
+    /// entered several times is there once.
+    matches: Vec<usize>,
+    /// Position in `matches` of the line shown.
+    selected: usize,
+}
+
+impl Search<'_> {
+    /// Finds the matches of the query again, staying on the line shown or an
+    /// older one if it doesn't match anymore.
+    fn update(&mut self) {
+        let shown = self.matches.get(self.selected).copied();
+        self.matches = match self.fuzzy {
+            true => fuzzy_matches(self.history, &self.query),
+            false => matches(self.history, &self.query),
+        };
+        self.selected = match (self.fuzzy, shown) {
+            (false, Some(shown)) => self.matches.iter().position(|&index| index <= shown).unwrap_or(0),
+            _ => 0,
+        };
+    }
+
+    fn selected_entry(&self) -> Option<(usize, &str)> {
+        let index = *self.matches.get(self.selected)?;
+        Some((index, &self.history[index].line))
+    }
+
+    fn prompt(&self) -> String {
+        let failed = if self.matches.is_empty() { "failed " } else { "" };
+        let kind = if self.fuzzy { "fuzzy-search" } else { "reverse-i-search" };
+        format!("({failed}{kind})`{}': ", self.query)
+    }
+
+    /// The line shown, with the cursor on the typed text in it.
+    fn shown_line(&self) -> LineBuffer {
+        match self.selected_entry() {
+            Some((_, line)) => {
+                let cursor = if self.fuzzy { 0 } else { line.find(&self.query).unwrap_or(0) };
+                LineBuffer::new(line, cursor)
+            }
+            None => LineBuffer::default(),
+        }
+    }
+
+    /// The best fuzzy matches around the selected one, for the popup.
+    fn popup(&self) -> Vec<String> {
+        if !self.fuzzy {
+            return Vec::new();
+        }
+        let first = self.selected.saturating_sub(SHOWN_MATCHES - 1);
+        self.matches
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(SHOWN_MATCHES)
+            .map(|(position, &index)| {
+                let line = self.history[index].line.replace('\n', " ");
+                match position == self.selected {
+                    true => format!("{REVERSE_VIDEO}> {line}"),
+                    false => format!("  {line}"),
+                }
+            })
+            .collect()
+    }
+}
+
+impl Editor {
+    /// Runs a search started by Ctrl-R. Enter runs the found line, Ctrl-G or
+    /// Ctrl-C go back to the line as it was, and any other key edits the
+    /// found line.
+    pub(super) fn search(&mut self, state: &mut EditState) -> io::Result<SearchEnd> {
+        let mut search = Search {
+            history: state.history,
+            fuzzy: self.fuzzy_search,
+            query: String::new(),
+            matches: Vec::new(),
+            selected: 0,
+        };
+        search.update();
+        loop {
+            self.draw(&search.prompt(), &search.shown_line(), "", &search.popup())?;
+            let key = match read_key()? {
+                Some(Key::Resize) => {
+                    self.resized(&search.prompt(), &search.shown_line());
+                    continue;
+                }
+                Some(key) => key,
+                None => Key::Ctrl('g'),
+            };
+            match key {
+                Key::Char(c) => {
+                    search.query.push(c);
+                    search.update();
+                }
+                Key::Backspace | Key::Ctrl('h') => {
+                    search.query.pop();
+                    search.update();
+                }
+                Key::Ctrl('r') | Key::Down if search.selected + 1 < search.matches.len() => search.selected += 1,
+                Key::Ctrl('s') | Key::Up if search.selected > 0 => search.selected -= 1,
+                Key::Ctrl('r') | Key::Ctrl('s') => write_terminal(BELL)?,
+                Key::Down | Key::Up if search.fuzzy => write_terminal(BELL)?,
+                Key::Ctrl('g') | Key::Ctrl('c') => {
+                    self.refresh(state)?;
+                    return Ok(SearchEnd::Edit(None));
+                }
+                key => {
+                    if let Some((index, _)) = search.selected_entry() {
+                        state.show_history(index);
+                    }
+                    return Ok(match key {
+                        Key::Enter => SearchEnd::Run,
+                        key => SearchEnd::Edit(Some(key)),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Entries containing the query, the most recent first.
+fn matches(history: &[HistoryEntry], query: &str) -> Vec<usize> {
+    let mut seen = HashSet::new();
+    history
+        .iter()
+        .enumerate()
+        .rev()
+        .filter(|(_, entry)| entry.line.contains(query) && seen.insert(entry.line.as_str()))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Entries holding the characters of the query in order, the best first.
+fn fuzzy_matches(history: &[HistoryEntry], query: &str) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = Vec::new();
+    for index in matches(history, "") {
+        if let Some(score) = fuzzy_score(query, &history[index].line) {
+            scored.push((score, index));
+        }
+    }
+    // The sort is stable, so equal scores stay the most recent first.
+    scored.sort_by_key(|&(score, _)| -score);
+    scored.into_iter().map(|(_, index)| index).collect()
+}
+
+/// How well the line matches the characters of the query, ignoring case.
+/// Characters right after each other and at the start of words count more,
+/// and shorter lines win. `None` if the line lacks some of them.
+fn fuzzy_score(query: &str, line: &str) -> Option<i64> {
+    if query.is_empty() {
+        return Some(0);
+    }
+    let line: Vec<char> = line.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous = None;
+    for wanted in query.chars() {
+        let found = (next..line.len()).find(|&position| chars_match(line[position], wanted))?;
+        score += 1;
+        if found > 0 && previous == Some(found - 1) {
+            score += 5;
+        }
+        if found == 0 || !line[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score * 16 - line.len() as i64)
+}
+
+fn chars_match(a: char, b: char) -> bool {
+    a == b || a.to_lowercase().eq(b.to_lowercase())
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> Vec<HistoryEntry> {
+        lines.iter().map(|line| HistoryEntry { line: line.to_string(), ..HistoryEntry::default() }).collect()
+    }
+
+    #[test]
+    fn latest_matches_first() {
+        let history = history(&["cargo build", "ls", "cargo test", "cargo build"]);
+        assert_eq!(vec![3, 2], matches(&history, "cargo"));
+        assert_eq!(vec![1], matches(&history, "ls"));
+    }
+
+    #[test]
+    fn fuzzy_ranking() {
+        let history = history(&["git commit -m wip", "cat main.rs", "git checkout main", "grep -c x"]);
+        assert_eq!(vec![3, 2, 0], fuzzy_matches(&history, "gc"));
+        assert_eq!(vec![1, 2], fuzzy_matches(&history, "MAIN"));
+        assert!(fuzzy_score("xyz", "git").is_none());
+    }
+}
@@ -0,0 +1,871 @@
+//! Runs parsed command lists: connects the stages of pipelines with pipes,
+//! applies redirections, starts external programs and collects exit
+//! statuses.
+
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::process::{self, Child};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread::{self, ScopedJoinHandle};
+
+use crate::expand::{expand_assignment, expand_pattern, expand_word, expand_words, ExpandError};
+use crate::jobs::{self, JobState};
+use crate::lexer::{RedirectKind, Word};
+use crate::parser::{
+    AndOrList, Assignment, CaseArm, CommandList, CompoundCommand, Connector, FunctionDefinition, Pipeline,
+    PipelineCommand, Redirect, SimpleCommand,
+};
+use crate::pattern;
+use crate::platform::{self, ProcessEvent};
+use crate::shell::{Flow, Function, Shell};
+use crate::streams::{InputStream, IoStreams, OutputStream};
+use crate::variables::Variable;
+use crate::{find_executable, process_command, BuiltinCommand, Command, ERROR_CODE, EXIT_CODE, SUCCESS_CODE};
+
+/// Exit statuses used when a program can't be started or isn't found.
+const NOT_EXECUTABLE_CODE: i32 = 126;
+pub const NOT_FOUND_CODE: i32 = 127;
+
+/// Stack of the threads running builtins in pipelines and background jobs,
+/// as large as the one of the main thread so functions can call each other
+/// as deep there.
+const STAGE_STACK_SIZE: usize = 8 * 1024 * 1024;
+
+enum Stage<'scope> {
+    Builtin(ScopedJoinHandle<'scope, i32>),
+    /// A program, waited for with the other ones of the pipeline.
+    External,
+    Failed(i32),
+}
+
+/// Runs the items of a list one after another and returns the status of the
+/// last one. `exit`, `break`, `continue` and `return` stop the list right away,
+/// and so does Ctrl-C. Every command starts out with a copy of `io`.
+pub fn run_list(list: &CommandList, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let mut status = SUCCESS_CODE;
+    for and_or in &list.items {
+        status = match and_or.background {
+            true => run_background(and_or, shell, io),
+            false => run_and_or(and_or, shell, io),
+        };
+        if status == EXIT_CODE || shell.flow.is_some() {
+            break;
+        }
+        if platform::interrupted() {
+            status = platform::INTERRUPTED_STATUS;
+            shell.last_status = status;
+            break;
+        }
+    }
+    status
+}
+
+/// Runs `a && b || c` chains, skipping a pipeline when the status of the
+/// previous one doesn't allow it to run.
+fn run_and_or(and_or: &AndOrList, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let mut status = run_pipeline(&and_or.first, shell, io);
+    for (connector, pipeline) in &and_or.rest {
+        if status == EXIT_CODE || shell.flow.is_some() || platform::interrupted() {
+            break;
+        }
+        let should_run = match connector {
+            Connector::And => status == SUCCESS_CODE,
+            Connector::Or => status != SUCCESS_CODE,
+        };
+        if should_run {
+            status = run_pipeline(pipeline, shell, io);
+        }
+    }
+    status
+}
+
+/// Starts `list &` as a job running on a thread of its own with a copy of
+/// the shell state, and gives 0 right away. Without job control it can't
+/// read the terminal, its standard input is the null device.
+fn run_background(and_or: &AndOrList, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let mut io = match io.try_clone() {
+        Ok(io) => io,
+        Err(err) => {
+            eprintln!("Could not duplicate the standard streams - {err}");
+            return ERROR_CODE;
+        }
+    };
+    if !shell.job_control && matches!(io.stdin, InputStream::Stdin) {
+        match File::open(platform::NULL_DEVICE) {
+            Ok(file) => io.stdin = InputStream::File(file),
+            Err(err) => {
+                outln!(io.stderr, "{}{}: {err}", shell.error_prefix(), platform::NULL_DEVICE);
+                return ERROR_CODE;
+            }
+        }
+    }
+    let job = shell.jobs.start(and_or.to_string());
+    shell.last_background = Some(Arc::clone(&job));
+    let mut subshell = shell.subshell();
+    subshell.job = Some(Arc::clone(&job));
+    let list = and_or.clone();
+    let thread_job = Arc::clone(&job);
+    let spawned = thread::Builder::new().stack_size(STAGE_STACK_SIZE).spawn(move || {
+        platform::ignore_interrupts();
+        let status = run_and_or(&list, &mut subshell, &io);
+        thread_job.finish(if status == EXIT_CODE { subshell.last_status } else { status });
+    });
+    if let Err(err) = spawned {
+        eprintln!("Could not start a thread - {err}");
+        shell.jobs.remove(&job);
+        return ERROR_CODE;
+    }
+    // Like a forked shell, the job is on its way once `&` returns, with
+    // its process group known.
+    job.wait_start();
+    if shell.location.is_none() && shell.job.is_none() {
+        eprintln!("[{}]", job.id);
+    }
+    SUCCESS_CODE
+}
+
+/// A simple command after expansion, ready to run.
+struct PreparedCommand {
+    /// `None` for a command made of assignments and redirections only.
+    command: Option<Command>,
+    assignments: Vec<(String, String)>,
+    io: IoStreams,
+}
+
+/// Applies the redirections of a command and expands its words and
+/// assignments. On failure the error is reported and the exit status to use
+/// is returned.
+fn prepare_command(simple_command: &SimpleCommand, shell: &mut Shell, mut io: IoStreams)
+    -> Result<PreparedCommand, i32> {
+    shell.substitution_status = None;
+    let prepared = apply_redirects(&simple_command.redirects, shell, &mut io).and_then(|()| {
+        let words = expand_words(&simple_command.words, shell)?;
+        let assignments = expand_assignments(&simple_command.assignments, shell)?;
+        Ok((words, assignments))
+    });
+    match prepared {
+        Ok((words, assignments)) => {
+            let command = if words.is_empty() { None } else { Some(Command::new(words)) };
+            Ok(PreparedCommand { command, assignments, io })
+        }
+        Err(err) => Err(expansion_failed(err, shell, &mut io)),
+    }
+}
+
+/// Reports a failed expansion and gives the exit status to go on with. A
+/// fatal error ends a shell that is not interactive with status 1.
+fn expansion_failed(err: ExpandError, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    outln!(io.stderr, "{}{err}", shell.error_prefix());
+    if err.fatal && !shell.interactive {
+        shell.last_status = ERROR_CODE;
+        return EXIT_CODE;
+    }
+    ERROR_CODE
+}
+
+fn expand_assignments(assignments: &[Assignment], shell: &mut Shell)
+    -> Result<Vec<(String, String)>, ExpandError> {
+    assignments
+        .iter()
+        .map(|assignment| Ok((assignment.name.clone(), expand_assignment(&assignment.value, shell)?)))
+        .collect()
+}
+
+/// Sets the variables assigned in front of a command for the time it runs.
+/// They are exported, so programs see them in their environment. Returns
+/// what is needed to undo it with `restore_assignments`.
+fn apply_assignments(assignments: &[(String, String)], shell: &mut Shell) -> Vec<(String, Option<Variable>)> {
+    let mut saved = Vec::new();
+    for (name, value) in assignments {
+        saved.push((name.clone(), shell.variables.get_variable(name).cloned()));
+        shell.variables.set(name, value);
+        shell.variables.export(name, true);
+    }
+    saved
+}
+
+fn restore_assignments(saved: Vec<(String, Option<Variable>)>, shell: &mut Shell) {
+    // In reverse, so the first value saved for a name is the one that stays.
+    for (name, variable) in saved.into_iter().rev() {
+        shell.variables.restore(&name, variable);
+    }
+}
+
+/// Runs a prepared command in the shell itself. Assignments without a
+/// command set shell variables.
+fn run_prepared(prepared: PreparedCommand, shell: &mut Shell) -> i32 {
+    let PreparedCommand { command, assignments, io } = prepared;
+    match command {
+        Some(command) => {
+            let saved = apply_assignments(&assignments, shell);
+            let status = process_command(command, shell, io);
+            restore_assignments(saved, shell);
+            status
+        }
+        None => {
+            for (name, value) in &assignments {
+                shell.variables.set(name, value);
+            }
+            shell.substitution_status.take().unwrap_or(SUCCESS_CODE)
+        }
+    }
+}
+
+/// Applies redirections from left to right, so `>file 2>&1` sends both
+/// streams to the file while `2>&1 >file` only sends standard output there.
+fn apply_redirects(redirects: &[Redirect], shell: &mut Shell, io: &mut IoStreams) -> Result<(), ExpandError> {
+    for redirect in redirects {
+        let target = expand_word(&redirect.target, shell)?;
+        match redirect.kind {
+            RedirectKind::Input => {
+                if redirect.fd != 0 {
+                    return Err(format!("{}: unsupported file descriptor", redirect.fd).into());
+                }
+                let file = File::open(shell.resolve(&target)).map_err(|err| format!("{target}: {err}"))?;
+                io.stdin = InputStream::File(file);
+            }
+            RedirectKind::Output | RedirectKind::Append => {
+                let file = open_output(&target, redirect.kind == RedirectKind::Append, shell)?;
+                set_output(io, redirect.fd, OutputStream::File(file))?;
+            }
+            RedirectKind::OutputAll | RedirectKind::AppendAll => {
+                let file = open_output(&target, redirect.kind == RedirectKind::AppendAll, shell)?;
+                let copy = file.try_clone().map_err(|err| format!("{target}: {err}"))?;
+                io.stdout = OutputStream::File(file);
+                io.stderr = OutputStream::File(copy);
+            }
+            RedirectKind::Duplicate => {
+                let source = match target.as_str() {
+                    "1" => io.stdout.try_clone(),
+                    "2" => io.stderr.try_clone(),
+                    _ => return Err(format!("{target}: bad file descriptor").into()),
+                };
+                let source = source.map_err(|err| format!("{target}: {err}"))?;
+                set_output(io, redirect.fd, source)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn open_output(target: &str, append: bool, shell: &Shell) -> Result<File, String> {
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(shell.resolve(target))
+        .map_err(|err| format!("{target}: {err}"))
+}
+
+fn set_output(io: &mut IoStreams, fd: u32, stream: OutputStream) -> Result<(), String> {
+    match fd {
+        1 => io.stdout = stream,
+        2 => io.stderr = stream,
+        _ => return Err(format!("{fd}: unsupported file descriptor")),
+    }
+    Ok(())
+}
+
+/// Runs a pipeline, records its exit status as `$?` and returns it. A single
+/// command runs in the shell itself, so builtins like `cd` affect the
+/// session. In longer pipelines every builtin runs on its own thread with a
+/// copy of the shell state, like a subshell.
+fn run_pipeline(pipeline: &Pipeline, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let status = pipeline_status(pipeline, shell, io);
+    if status != EXIT_CODE {
+        shell.last_status = status;
+    }
+    status
+}
+
+fn pipeline_status(pipeline: &Pipeline, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let io = match io.try_clone() {
+        Ok(io) => io,
+        Err(err) => {
+            eprintln!("Could not duplicate the standard streams - {err}");
+            return ERROR_CODE;
+        }
+    };
+    // A job running no program has no process group for `$!`, and `&` need
+    // not wait for one.
+    if let Some(job) = &shell.job {
+        if !pipeline.commands.iter().any(|command| runs_program(command, shell)) {
+            job.start_without_group();
+        }
+    }
+    match pipeline.commands.as_slice() {
+        [PipelineCommand::Simple(simple_command)] => {
+            return match prepare_command(simple_command, shell, io) {
+                Ok(prepared) => run_prepared(prepared, shell),
+                Err(code) => code,
+            };
+        }
+        [PipelineCommand::Compound(compound, redirects)] => return run_compound(compound, redirects, shell, io),
+        [PipelineCommand::Function(definition)] => return define_function(definition, shell),
+        _ => (),
+    }
+
+    // In the foreground the programs only get a process group and the
+    // terminal when the whole pipeline is made of them: the other stages run
+    // in the shell, which has to keep the terminal for them.
+    let grouped = shell.job_control
+        && (shell.job.is_some() || pipeline.commands.iter().all(|command| runs_program(command, shell)));
+    let IoStreams { stdin, stdout, stderr } = io;
+    let statuses: Vec<i32> = thread::scope(|scope| {
+        let mut stages = Vec::new();
+        let mut children = Vec::new();
+        // Programs of the pipeline share the process group of the first one.
+        let mut group = None;
+        let mut first_input = Some(stdin);
+        let mut last_output = Some(stdout);
+        let mut previous_output: Option<io::PipeReader> = None;
+        let last = pipeline.commands.len() - 1;
+
+        for (index, command) in pipeline.commands.iter().enumerate() {
+            let stdin = match (previous_output.take(), first_input.take()) {
+                (Some(reader), _) => InputStream::Pipe(reader),
+                (None, Some(stdin)) => stdin,
+                (None, None) => InputStream::Stdin,
+            };
+            let stdout = if index == last {
+                last_output.take().unwrap_or(OutputStream::Stdout)
+            } else {
+                match io::pipe() {
+                    Ok((reader, writer)) => {
+                        previous_output = Some(reader);
+                        OutputStream::Pipe(writer)
+                    }
+                    Err(err) => {
+                        eprintln!("Could not create a pipe - {err}");
+                        stages.push(Stage::Failed(ERROR_CODE));
+                        break;
+                    }
+                }
+            };
+            let stderr = match stderr.try_clone() {
+                Ok(stderr) => stderr,
+                Err(err) => {
+                    eprintln!("Could not duplicate the standard error - {err}");
+                    stages.push(Stage::Failed(ERROR_CODE));
+                    break;
+                }
+            };
+            let io = IoStreams { stdin, stdout, stderr };
+
+            // Every stage expands its words and sets its variables apart.
+            let mut subshell = shell.subshell();
+            subshell.job_control = grouped;
+            let simple_command = match command {
+                PipelineCommand::Simple(simple_command) => simple_command,
+                PipelineCommand::Compound(compound, redirects) => {
+                    let run = move || run_compound(compound, redirects, &mut subshell, io);
+                    stages.push(spawn_stage(scope, run));
+                    continue;
+                }
+                // Only the copy of the shell would know the function.
+                PipelineCommand::Function(_) => {
+                    stages.push(Stage::Failed(SUCCESS_CODE));
+                    continue;
+                }
+            };
+            let stage = match prepare_command(simple_command, &mut subshell, io) {
+                Ok(PreparedCommand { command: Some(command), assignments, io }) => {
+                    apply_assignments(&assignments, &mut subshell);
+                    if BuiltinCommand::from_str(&command.keyword).is_ok()
+                        || subshell.functions.contains_key(&command.keyword) {
+                        spawn_stage(scope, move || process_command(command, &mut subshell, io))
+                    } else {
+                        match spawn_external(command, &subshell, io, group) {
+                            Ok(child) => {
+                                group.get_or_insert(child.id());
+                                children.push(child);
+                                Stage::External
+                            }
+                            Err(code) => Stage::Failed(code),
+                        }
+                    }
+                }
+                Ok(PreparedCommand { command: None, .. }) => Stage::Failed(SUCCESS_CODE),
+                Err(code) => Stage::Failed(code),
+            };
+            stages.push(stage);
+        }
+
+        // The programs are waited for together, as they stop together.
+        let mut program_statuses = wait_programs(children, grouped, || pipeline.to_string(), shell).into_iter();
+        stages
+            .into_iter()
+            .map(|stage| match stage {
+                Stage::Builtin(handle) => match handle.join() {
+                    Ok(EXIT_CODE) => SUCCESS_CODE,
+                    Ok(code) => code,
+                    Err(_) => ERROR_CODE,
+                },
+                Stage::External => program_statuses.next().unwrap_or(ERROR_CODE),
+                Stage::Failed(code) => code,
+            })
+            .collect()
+    });
+
+    if shell.options.pipefail {
+        statuses.iter().rev().copied().find(|&code| code != SUCCESS_CODE).unwrap_or(SUCCESS_CODE)
+    } else {
+        statuses.last().copied().unwrap_or(SUCCESS_CODE)
+    }
+}
+
+/// Whether a stage of a pipeline runs a program, as far as can be told
+/// before it is expanded: compound commands, builtins and functions run in
+/// the shell.
+fn runs_program(command: &PipelineCommand, shell: &Shell) -> bool {
+    let PipelineCommand::Simple(simple_command) = command else {
+        return false;
+    };
+    simple_command.words.first().is_none_or(|word| {
+        let name = word.unquote();
+        BuiltinCommand::from_str(&name).is_err() && !shell.functions.contains_key(&name)
+    })
+}
+
+/// Starts a stage of a pipeline that runs in the shell, on a thread of its
+/// own.
+fn spawn_stage<'scope, F>(scope: &'scope thread::Scope<'scope, '_>, run: F) -> Stage<'scope>
+where
+    F: FnOnce() -> i32 + Send + 'scope,
+{
+    // A stage of a background job keeps ignoring Ctrl-C.
+    let ignores_interrupts = platform::ignores_interrupts();
+    let run = move || {
+        if ignores_interrupts {
+            platform::ignore_interrupts();
+        }
+        run()
+    };
+    match thread::Builder::new().stack_size(STAGE_STACK_SIZE).spawn_scoped(scope, run) {
+        Ok(handle) => Stage::Builtin(handle),
+        Err(err) => {
+            eprintln!("Could not start a thread - {err}");
+            Stage::Failed(ERROR_CODE)
+        }
+    }
+}
+
+/// Runs `if`, a loop, `case` or a `{ list; }` group with its redirections
+/// applied.
+fn run_compound(compound: &CompoundCommand, redirects: &[Redirect], shell: &mut Shell, mut io: IoStreams) -> i32 {
+    if let Err(err) = apply_redirects(redirects, shell, &mut io) {
+        return expansion_failed(err, shell, &mut io);
+    }
+    match compound {
+        CompoundCommand::Group(body) => run_list(body, shell, &io),
+        CompoundCommand::If { branches, otherwise } => run_if(branches, otherwise.as_ref(), shell, &io),
+        CompoundCommand::While { condition, body, until } => {
+            shell.loop_depth += 1;
+            let status = run_while(condition, body, *until, shell, &io);
+            shell.loop_depth -= 1;
+            status
+        }
+        CompoundCommand::For { variable, words, body } => {
+            shell.loop_depth += 1;
+            let status = run_for(variable, words.as_deref(), body, shell, &mut io);
+            shell.loop_depth -= 1;
+            status
+        }
+        CompoundCommand::Case { word, arms } => run_case(word, arms, shell, &mut io),
+    }
+}
+
+/// Runs the list of the first branch whose condition succeeds. Gives 0 when
+/// none does and there is no `else`.
+fn run_if(branches: &[(CommandList, CommandList)], otherwise: Option<&CommandList>, shell: &mut Shell, io: &IoStreams)
+    -> i32 {
+    for (condition, body) in branches {
+        let status = run_list(condition, shell, io);
+        if status == EXIT_CODE || shell.flow.is_some() {
+            return status;
+        }
+        if status == SUCCESS_CODE {
+            return run_list(body, shell, io);
+        }
+    }
+    match otherwise {
+        Some(body) => run_list(body, shell, io),
+        None => SUCCESS_CODE,
+    }
+}
+
+/// Gives the status of the last round of the body, 0 if it never ran.
+fn run_while(condition: &CommandList, body: &CommandList, until: bool, shell: &mut Shell, io: &IoStreams) -> i32 {
+    let mut status = SUCCESS_CODE;
+    loop {
+        let condition_status = run_list(condition, shell, io);
+        if condition_status == EXIT_CODE {
+            return EXIT_CODE;
+        }
+        if leaves_loop(shell) {
+            break;
+        }
+        if (condition_status == SUCCESS_CODE) == until {
+            break;
+        }
+        status = run_list(body, shell, io);
+        if status == EXIT_CODE {
+            return EXIT_CODE;
+        }
+        if leaves_loop(shell) {
+            break;
+        }
+    }
+    status
+}
+
+fn run_for(variable: &str, words: Option<&[Word]>, body: &CommandList, shell: &mut Shell, io: &mut IoStreams)
+    -> i32 {
+    let values = match words {
+        Some(words) => match expand_words(words, shell) {
+            Ok(values) => values,
+            Err(err) => return expansion_failed(err, shell, io),
+        },
+        None => shell.positional.clone(),
+    };
+    let mut status = SUCCESS_CODE;
+    for value in values {
+        shell.variables.set(variable, &value);
+        status = run_list(body, shell, io);
+        if status == EXIT_CODE {
+            return EXIT_CODE;
+        }
+        if leaves_loop(shell) {
+            break;
+        }
+    }
+    status
+}
+
+/// Takes a pending `break` or `continue` after a part of a loop ran. Returns
+/// `true` when the loop has to end, leaving what is left of the flow to the
+/// loops around it. A `return` and Ctrl-C leave every loop on their way.
+fn leaves_loop(shell: &mut Shell) -> bool {
+    if platform::interrupted() {
+        return true;
+    }
+    match shell.flow.take() {
+        None | Some(Flow::Continue(1)) => false,
+        Some(Flow::Return(status)) => {
+            shell.flow = Some(Flow::Return(status));
+            true
+        }
+        Some(Flow::Break(1)) => true,
+        Some(Flow::Break(count)) => {
+            shell.flow = Some(Flow::Break(count - 1));
+            true
+        }
+        Some(Flow::Continue(count)) => {
+            shell.flow = Some(Flow::Continue(count - 1));
+            true
+        }
+    }
+}
+
+/// Runs the list of the first branch with a pattern matching the word. Gives
+/// 0 when none matches.
+fn run_case(word: &Word, arms: &[CaseArm], shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let expanded = match expand_word(word, shell) {
+        Ok(expanded) => expanded,
+        Err(err) => return expansion_failed(err, shell, io),
+    };
+    for arm in arms {
+        for pattern in &arm.patterns {
+            match expand_pattern(pattern, shell) {
+                Ok(pattern) if pattern::matches(&pattern, &expanded) => return run_list(&arm.body, shell, io),
+                Ok(_) => (),
+                Err(err) => return expansion_failed(err, shell, io),
+            }
+        }
+    }
+    SUCCESS_CODE
+}
+
+/// Functions calling each other deeper than this fail, rather than running
+/// out of stack.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Runs `name() { ... }`: the function replaces any earlier one of the same
+/// name, and stays exported if that one was.
+fn define_function(definition: &Arc<FunctionDefinition>, shell: &mut Shell) -> i32 {
+    let exported = shell.functions.get(&definition.name).is_some_and(|function| function.exported);
+    let function = Function { definition: Arc::clone(definition), exported };
+    Arc::make_mut(&mut shell.functions).insert(definition.name.clone(), function);
+    SUCCESS_CODE
+}
+
+/// Runs a function with the arguments as its positional parameters. The
+/// positional parameters and variables made `local` are put back when it
+/// returns, and a `return` inside it ends here.
+pub fn call_function(function: &FunctionDefinition, arguments: Vec<String>, shell: &mut Shell, mut io: IoStreams)
+    -> i32 {
+    if shell.locals.len() >= MAX_CALL_DEPTH {
+        outln!(io.stderr, "{}{}: maximum function nesting level exceeded ({MAX_CALL_DEPTH})",
+            shell.error_prefix(), function.name);
+        return ERROR_CODE;
+    }
+    let saved_positional = mem::replace(&mut shell.positional, arguments);
+    // `break` and `continue` don't reach the loops of the caller.
+    let saved_loop_depth = mem::take(&mut shell.loop_depth);
+    shell.locals.push(Vec::new());
+    shell.call_depth += 1;
+    let mut status = run_compound(&function.body, &function.redirects, shell, io);
+    shell.call_depth -= 1;
+    if let Some(Flow::Return(code)) = shell.flow {
+        shell.flow = None;
+        status = code;
+    }
+    let locals = shell.locals.pop().unwrap_or_default();
+    restore_assignments(locals, shell);
+    shell.loop_depth = saved_loop_depth;
+    shell.positional = saved_positional;
+    status
+}
+
+/// Runs a command list with its standard output captured, for `$(...)`.
+/// It works on a copy of the shell state, like a subshell. Gives the output
+/// without trailing newlines and the exit status.
+pub fn capture_output(list: &CommandList, shell: &Shell) -> (String, i32) {
+    let (mut reader, writer) = match io::pipe() {
+        Ok(pipe) => pipe,
+        Err(err) => {
+            eprintln!("Could not create a pipe - {err}");
+            return (String::new(), ERROR_CODE);
+        }
+    };
+    let mut subshell = shell.subshell();
+    let (output, status) = thread::scope(|scope| {
+        // Read while the commands run, so a full pipe can't block them.
+        let reading = scope.spawn(move || {
+            let mut output = Vec::new();
+            let _ = reader.read_to_end(&mut output);
+            output
+        });
+        let io = IoStreams { stdin: InputStream::Stdin, stdout: OutputStream::Pipe(writer), stderr: OutputStream::Stderr };
+        let status = run_list(list, &mut subshell, &io);
+        // The last writer has to go for the reader to see the end.
+        drop(io);
+        (reading.join().unwrap_or_default(), status)
+    });
+    let status = if status == EXIT_CODE { SUCCESS_CODE } else { status };
+    let output = String::from_utf8_lossy(&output);
+    (output.trim_end_matches(['\n', '\r']).to_string(), status)
+}
+
+/// Runs an external program and waits for it.
+pub fn run_external(command: Command, shell: &mut Shell, io: IoStreams) -> i32 {
+    let text = [command.keyword.as_str()].into_iter().chain(command.arguments.iter().map(String::as_str))
+        .collect::<Vec<_>>()
+        .join(" ");
+    match spawn_external(command, shell, io, None) {
+        Ok(child) => wait_programs(vec![child], shell.job_control, || text, shell).pop().unwrap_or(ERROR_CODE),
+        Err(code) => code,
+    }
+}
+
+/// Starts an external program with the given streams and the exported
+/// variables of the shell as its environment. With job control it joins
+/// `group`, or the one of the job running it, and leads a new one if there is
+/// none yet. On failure the error is reported and the exit status to use is
+/// returned.
+pub fn spawn_external(mut command: Command, shell: &Shell, mut io: IoStreams, group: Option<u32>)
+    -> Result<Child, i32> {
+    command.keyword = platform::executable_name(&command.keyword);
+    let path = match find_executable(&command, shell) {
+        Ok(path) => path,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            outln!(io.stderr, "{}Permission denied - {}", shell.error_prefix(), command.keyword);
+            return Err(NOT_EXECUTABLE_CODE);
+        }
+        Err(_) => {
+            outln!(io.stderr, "{}Command not found - {}", shell.error_prefix(), command.keyword);
+            return Err(NOT_FOUND_CODE);
+        }
+    };
+    // Output of builtins may still sit in the buffer of the shell's stdout.
+    let _ = io::stdout().flush();
+    // Kept to run the file as a script if it turns out not to be a program.
+    #[cfg(unix)]
+    let script_io = io.try_clone().ok();
+    let group = group.or_else(|| shell.job.as_ref().and_then(|job| job.group()));
+    let spawned = process_builder(&path, &command.arguments, shell, io, group).spawn();
+    #[cfg(unix)]
+    let spawned = match (spawned, script_io) {
+        (Err(err), Some(script_io)) if err.raw_os_error() == Some(libc::ENOEXEC) => {
+            spawn_script(&path, &command.arguments, shell, script_io, group)
+        }
+        (spawned, _) => spawned,
+    };
+    let child = spawned.map_err(|err| {
+        eprintln!("Command didn't start - {err}");
+        NOT_EXECUTABLE_CODE
+    })?;
+    if let Some(job) = &shell.job {
+        job.lead_group(child.id());
+    }
+    Ok(child)
+}
+
+fn process_builder(program: &Path, arguments: &[String], shell: &Shell, io: IoStreams, group: Option<u32>)
+    -> process::Command {
+    let mut process = process::Command::new(program);
+    process
+        .args(arguments)
+        .env_clear()
+        .envs(shell.variables.environment())
+        .envs(shell.function_environment())
+        .stdin(io.stdin.into_stdio())
+        .stdout(io.stdout.into_stdio())
+        .stderr(io.stderr.into_stdio());
+    if let Some(dir) = &shell.dir {
+        process.current_dir(dir);
+    }
+    if shell.job_control {
+        platform::set_process_group(&mut process, group);
+    }
+    platform::reset_signals(&mut process, shell.job_control);
+    process
+}
+
+/// Runs an executable file without a `#!` line, which the system can't
+/// start itself, with this shell, like other Unix shells do.
+#[cfg(unix)]
+fn spawn_script(path: &Path, arguments: &[String], shell: &Shell, io: IoStreams, group: Option<u32>)
+    -> io::Result<Child> {
+    let mut script_arguments = vec![path.to_string_lossy().into_owned()];
+    script_arguments.extend(arguments.iter().cloned());
+    process_builder(&env::current_exe()?, &script_arguments, shell, io, group).spawn()
+}
+
+/// Waits for the programs of a pipeline and gives their exit statuses.
+/// Only programs `grouped` in a process group of their own can be stopped.
+///
+/// In a background job a stopped program only marks the job stopped, and
+/// waiting goes on until it is continued and ends. In the foreground the
+/// programs get the terminal while they run, and if one stops they are left
+/// running as a new stopped job, `command` being its text.
+pub fn wait_programs(children: Vec<Child>, grouped: bool, command: impl FnOnce() -> String, shell: &mut Shell)
+    -> Vec<i32> {
+    let group = children.first().map(Child::id);
+    let foreground = grouped && shell.job.is_none();
+    if let (true, Some(group)) = (foreground, group) {
+        platform::give_terminal(group);
+    }
+    let mut statuses = Vec::new();
+    let mut children = children.into_iter().peekable();
+    while let Some(child) = children.peek_mut() {
+        match platform::wait_process(child, grouped) {
+            Ok(ProcessEvent::Exited(code)) => statuses.push(code),
+            Ok(ProcessEvent::Stopped) => match &shell.job {
+                Some(job) => {
+                    job.stopped();
+                    continue;
+                }
+                None => break,
+            },
+            Err(err) => {
+                eprintln!("Command wasn't running - {err}");
+                statuses.push(ERROR_CODE);
+            }
+        }
+        children.next();
+    }
+    if foreground {
+        platform::take_terminal();
+        // Ctrl-C went to the programs, the commands after them stop as well.
+        if statuses.contains(&platform::INTERRUPTED_STATUS) {
+            platform::interrupt();
+        }
+    }
+    let children: Vec<Child> = children.collect();
+    if let (false, Some(group)) = (children.is_empty(), group) {
+        statuses.resize(statuses.len() + children.len(), platform::STOPPED_STATUS);
+        let job = shell.jobs.add_stopped(command(), group, children);
+        eprintln!();
+        eprintln!("{}", jobs::describe(&job, '+', JobState::Stopped));
+    }
+    statuses
+}
+
+#[cfg(test)]
+mod exec_tests {
+    use super::*;
+    use std::fs;
+    use crate::parser;
+
+    fn parse(line: &str) -> CommandList {
+        parser::parse(line).unwrap().expect("line is blank")
+    }
+
+    #[test]
+    fn stages_keep_their_directory() {
+        let start = env::current_dir().unwrap();
+        let target = env::temp_dir().canonicalize().unwrap();
+        let target = target.to_str().unwrap();
+        let mut shell = Shell::default();
+        assert_eq!(SUCCESS_CODE, run_list(&parse(&format!("cd '{target}' | cat")), &mut shell, &IoStreams::inherit()));
+        assert_eq!(start, env::current_dir().unwrap());
+        assert_eq!(None, shell.dir);
+
+        let list = parse(&format!("{{ cd '{target}'; pwd; }} | cat; pwd"));
+        let (output, status) = capture_output(&list, &shell);
+        assert_eq!((format!("{target}\n{}", start.display()), SUCCESS_CODE), (output, status));
+        assert_eq!(start, env::current_dir().unwrap());
+    }
+
+    #[test]
+    fn jobs_keep_their_directory() {
+        let start = env::current_dir().unwrap();
+        let target = env::temp_dir().canonicalize().unwrap();
+        let mut shell = Shell::default();
+        let list = parse(&format!("cd '{}' & wait", target.display()));
+        assert_eq!(SUCCESS_CODE, run_list(&list, &mut shell, &IoStreams::inherit()));
+        assert_eq!(start, env::current_dir().unwrap());
+        assert_eq!(None, shell.dir);
+    }
+
+    #[test]
+    fn unset_parameter_error_ends_scripts() {
+        let list = parse("echo ${Z:?unset}; echo after");
+        let mut shell = Shell::default();
+        assert_eq!(EXIT_CODE, run_list(&list, &mut shell, &IoStreams::inherit()));
+        assert_eq!(ERROR_CODE, shell.last_status);
+        assert_eq!(String::new(), capture_output(&list, &shell).0);
+
+        shell.interactive = true;
+        assert_eq!((String::from("after"), SUCCESS_CODE), capture_output(&list, &shell));
+    }
+
+    #[test]
+    fn builtin_job_has_no_pid() {
+        let dir = env::temp_dir().join(format!("exec_tests_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut shell = Shell::default();
+        let list = parse(&format!("while cd '{}'; do cd .; done &", dir.display()));
+        assert_eq!(SUCCESS_CODE, run_list(&list, &mut shell, &IoStreams::inherit()));
+        assert_eq!((String::new(), SUCCESS_CODE), capture_output(&parse("echo $!"), &shell));
+        // The loop ends once the directory is gone.
+        fs::remove_dir(&dir).unwrap();
+        assert_eq!(SUCCESS_CODE, run_list(&parse("wait"), &mut shell, &IoStreams::inherit()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn last_job_pid() {
+        let mut shell = Shell::new();
+        assert_eq!(SUCCESS_CODE, run_list(&parse("sleep 0 &"), &mut shell, &IoStreams::inherit()));
+        let (pid, _) = capture_output(&parse("echo $!"), &shell);
+        assert!(pid.parse::<u32>().is_ok(), "{pid:?}");
+        assert_eq!(SUCCESS_CODE, run_list(&parse(&format!("wait {pid}")), &mut shell, &IoStreams::inherit()));
+    }
+}
@@ -0,0 +1,464 @@
+//! Word expansion: turns the words of a parsed command into the strings the
+//! command receives, expanding braces and `~`, replacing parameters,
+//! splitting the results of unquoted expansions into fields, replacing
+//! patterns with the matching file names and removing quotes.
+
+use std::fmt;
+use std::process;
+
+use crate::brace;
+use crate::exec;
+use crate::glob;
+use crate::lexer::{Parameter, ParameterOp, Word, WordPart};
+use crate::parser;
+use crate::pattern;
+use crate::platform;
+use crate::shell::Shell;
+use crate::variables;
+
+/// Characters separating fields when `IFS` is not set.
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Why a word could not be expanded.
+#[derive(Debug, PartialEq)]
+pub struct ExpandError {
+    pub message: String,
+    /// Raised by `${name:?message}`, which ends a shell that is not
+    /// interactive.
+    pub fatal: bool,
+}
+
+impl From<String> for ExpandError {
+    fn from(message: String) -> ExpandError {
+        ExpandError { message, fatal: false }
+    }
+}
+
+impl fmt::Display for ExpandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// A piece of an expanded word.
+struct Segment {
+    text: String,
+    /// Written inside quotes, so the text stands for itself.
+    quoted: bool,
+    /// Result of an unquoted expansion, subject to field splitting.
+    split: bool,
+    /// Starts a field of its own, as every parameter of `"$@"` does.
+    new_field: bool,
+}
+
+/// Expands words into the fields a command receives. One word may give no
+/// field at all, like an unquoted empty variable, or several.
+pub fn expand_words(words: &[Word], shell: &mut Shell) -> Result<Vec<String>, ExpandError> {
+    let mut fields = Vec::new();
+    for word in words.iter().flat_map(brace::expand) {
+        let segments = expand_segments(&expand_tilde(&word, shell, false), shell)?;
+        for field in split_fields(segments, shell) {
+            expand_filenames(field, shell, &mut fields)?;
+        }
+    }
+    Ok(fields)
+}
+
+/// Replaces a field holding unquoted wildcards with the matching paths.
+/// Without matches the field stays as it is, unless `nullglob` or
+/// `failglob` is set.
+fn expand_filenames(field: Vec<Segment>, shell: &Shell, fields: &mut Vec<String>) -> Result<(), ExpandError> {
+    let pattern = to_pattern(&field);
+    if !pattern::has_wildcards(&pattern) {
+        fields.push(join(field));
+        return Ok(());
+    }
+    let matches = glob::glob(&pattern, shell.base_dir(), shell.options.dotglob);
+    if !matches.is_empty() {
+        fields.extend(matches);
+    } else if shell.options.failglob {
+        return Err(format!("no match: {}", join(field)).into());
+    } else if !shell.options.nullglob {
+        fields.push(join(field));
+    }
+    Ok(())
+}
+
+/// Expands a word into a single string, without field splitting. Used where
+/// exactly one value is expected, like assignments and redirection targets.
+pub fn expand_word(word: &Word, shell: &mut Shell) -> Result<String, ExpandError> {
+    Ok(join(expand_segments(&expand_tilde(word, shell, false), shell)?))
+}
+
+/// Expands the value of `NAME=value`, where `~` is also replaced after
+/// every `:`, as in `PATH=~/bin:~/.local/bin`.
+pub fn expand_assignment(word: &Word, shell: &mut Shell) -> Result<String, ExpandError> {
+    Ok(join(expand_segments(&expand_tilde(word, shell, true), shell)?))
+}
+
+/// Replaces `~` with the home directory and `~user` with the one of `user`
+/// at the start of a word, up to the first `/`. Quoted tildes and unknown
+/// users are left alone.
+fn expand_tilde(word: &Word, shell: &Shell, assignment: bool) -> Word {
+    let mut expanded = Word::default();
+    for (index, part) in word.parts.iter().enumerate() {
+        let text = match part {
+            WordPart::Literal(text) if index == 0 || assignment => text,
+            part => {
+                expanded.parts.push(part.clone());
+                continue;
+            }
+        };
+        let is_last = index == word.parts.len() - 1;
+        let mut rest = text.as_str();
+        // Text before a possible tilde prefix, `None` once none can follow.
+        let mut start = if index == 0 { Some(0) } else { None };
+        let mut literal = String::new();
+        while !rest.is_empty() {
+            if start == Some(literal.len()) && rest.starts_with('~') {
+                let end = rest.find(|c| c == '/' || (assignment && c == ':'));
+                // `~"name"` is not a tilde prefix.
+                if end.is_some() || is_last {
+                    let end = end.unwrap_or(rest.len());
+                    if let Some(home) = home_dir(&rest[1..end], shell) {
+                        if !literal.is_empty() {
+                            expanded.parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+                        }
+                        expanded.parts.push(WordPart::Quoted(home));
+                        rest = &rest[end..];
+                        start = None;
+                        continue;
+                    }
+                }
+            }
+            let c = rest.chars().next().unwrap_or_default();
+            literal.push(c);
+            rest = &rest[c.len_utf8()..];
+            if assignment && c == ':' {
+                start = Some(literal.len());
+            }
+        }
+        if !literal.is_empty() {
+            expanded.parts.push(WordPart::Literal(literal));
+        }
+    }
+    expanded
+}
+
+fn home_dir(user: &str, shell: &Shell) -> Option<String> {
+    let home = if user.is_empty() {
+        match shell.variables.get("HOME") {
+            Some(home) => return Some(home.to_string()),
+            None => platform::home_dir(),
+        }
+    } else {
+        platform::user_home_dir(user)
+    };
+    home.map(|home| home.to_string_lossy().into_owned())
+}
+
+fn expand_segments(word: &Word, shell: &mut Shell) -> Result<Vec<Segment>, ExpandError> {
+    let mut segments = Vec::new();
+    expand_parts(&word.parts, shell, false, false, &mut segments)?;
+    Ok(segments)
+}
+
+fn join(segments: Vec<Segment>) -> String {
+    segments.into_iter().map(|segment| segment.text).collect()
+}
+
+/// `quoted` is set inside double quotes, `expanded` inside the operand of a
+/// `${...}` operation, whose text gets split like the value of a variable.
+fn expand_parts(parts: &[WordPart], shell: &mut Shell, quoted: bool, expanded: bool, segments: &mut Vec<Segment>)
+    -> Result<(), ExpandError> {
+    for part in parts {
+        match part {
+            WordPart::Literal(text) => segments.push(Segment {
+                text: text.clone(),
+                quoted,
+                split: expanded && !quoted,
+                new_field: false,
+            }),
+            WordPart::Quoted(text) => segments.push(Segment { text: text.clone(), quoted: true, split: false, new_field: false }),
+            WordPart::DoubleQuoted(inner) => {
+                // `"$@"` without positional parameters makes no field at all.
+                let all_parameters = matches!(inner.as_slice(),
+                    [WordPart::Parameter(Parameter { name, operation: None })] if name == "@");
+                if all_parameters && shell.positional.is_empty() {
+                    continue;
+                }
+                // `""` still makes a field, even though it adds no text.
+                segments.push(Segment { text: String::new(), quoted: true, split: false, new_field: false });
+                expand_parts(inner, shell, true, expanded, segments)?;
+            }
+            WordPart::Parameter(parameter) => expand_parameter(parameter, shell, quoted, segments)?,
+            WordPart::CommandSubstitution(command) => {
+                let text = substitute_command(command, shell)?;
+                segments.push(Segment { text, quoted, split: !quoted, new_field: false });
+            }
+        }
+    }
+    Ok(())
+}
+
+fn expand_parameter(parameter: &Parameter, shell: &mut Shell, quoted: bool, segments: &mut Vec<Segment>)
+    -> Result<(), ExpandError> {
+    let name = &parameter.name;
+    // `"$@"` gives one field for every positional parameter, quotes or not.
+    if name == "@" && quoted && parameter.operation.is_none() {
+        for (index, value) in shell.positional.iter().enumerate() {
+            segments.push(Segment { text: value.clone(), quoted: true, split: false, new_field: index > 0 });
+        }
+        return Ok(());
+    }
+    let value = parameter_value(name, shell);
+    let is_unset = |colon: bool| match &value {
+        Some(value) => colon && value.is_empty(),
+        None => true,
+    };
+
+    let text = match &parameter.operation {
+        None => value.unwrap_or_default(),
+        Some(ParameterOp::Default { word, colon }) => {
+            if is_unset(*colon) {
+                return expand_parts(&word.parts, shell, quoted, true, segments);
+            }
+            value.unwrap_or_default()
+        }
+        Some(ParameterOp::Alternative { word, colon }) => {
+            if !is_unset(*colon) {
+                return expand_parts(&word.parts, shell, quoted, true, segments);
+            }
+            String::new()
+        }
+        Some(ParameterOp::Assign { word, colon }) => {
+            if is_unset(*colon) {
+                if !variables::is_valid_name(name) {
+                    return Err(format!("${name}: cannot assign in this way").into());
+                }
+                let assigned = expand_word(word, shell)?;
+                shell.variables.set(name, &assigned);
+                assigned
+            } else {
+                value.unwrap_or_default()
+            }
+        }
+        Some(ParameterOp::Error { word, colon }) => {
+            if is_unset(*colon) {
+                let message = expand_word(word, shell)?;
+                let message = if message.is_empty() { String::from("parameter null or not set") } else { message };
+                return Err(ExpandError { message: format!("{name}: {message}"), fatal: true });
+            }
+            value.unwrap_or_default()
+        }
+        Some(ParameterOp::Length) => value.unwrap_or_default().chars().count().to_string(),
+        Some(ParameterOp::RemovePrefix { pattern, longest }) => {
+            let pattern = expand_pattern(pattern, shell)?;
+            remove_prefix(&value.unwrap_or_default(), &pattern, *longest)
+        }
+        Some(ParameterOp::RemoveSuffix { pattern, longest }) => {
+            let pattern = expand_pattern(pattern, shell)?;
+            remove_suffix(&value.unwrap_or_default(), &pattern, *longest)
+        }
+    };
+    segments.push(Segment { text, quoted, split: !quoted, new_field: false });
+    Ok(())
+}
+
+/// Runs the command of `$(...)` and gives its output.
+fn substitute_command(command: &str, shell: &mut Shell) -> Result<String, ExpandError> {
+    let list = match parser::parse_with_aliases(command, &shell.aliases) {
+        Ok(Some(list)) => list,
+        Ok(None) => return Ok(String::new()),
+        Err(err) => return Err(format!("$({command}): {err}").into()),
+    };
+    let (output, status) = exec::capture_output(&list, shell);
+    shell.substitution_status = Some(status);
+    Ok(output)
+}
+
+/// Value of a parameter, `None` when it is unset.
+fn parameter_value(name: &str, shell: &Shell) -> Option<String> {
+    match name {
+        "?" => Some(shell.last_status.to_string()),
+        "$" => Some(process::id().to_string()),
+        "!" => shell.last_background.as_ref().and_then(|job| job.group()).map(|pid| pid.to_string()),
+        "#" => Some(shell.positional.len().to_string()),
+        "0" => Some(shell.name.clone()),
+        "@" => Some(shell.positional.join(" ")),
+        // Joined with the first character of `IFS`, or nothing when it is
+        // empty.
+        "*" => {
+            let ifs = shell.variables.get("IFS").unwrap_or(DEFAULT_IFS);
+            Some(shell.positional.join(&ifs.chars().next().map(String::from).unwrap_or_default()))
+        }
+        _ if name.starts_with(|c: char| c.is_ascii_digit()) => {
+            let index: usize = name.parse().ok()?;
+            shell.positional.get(index.checked_sub(1)?).cloned()
+        }
+        _ => shell.variables.get(name).map(str::to_string),
+    }
+}
+
+/// Expands a pattern word so that quoted characters match only themselves.
+pub fn expand_pattern(word: &Word, shell: &mut Shell) -> Result<String, ExpandError> {
+    Ok(to_pattern(&expand_segments(word, shell)?))
+}
+
+fn to_pattern(segments: &[Segment]) -> String {
+    segments
+        .iter()
+        .map(|segment| if segment.quoted { pattern::escape(&segment.text) } else { segment.text.clone() })
+        .collect()
+}
+
+fn remove_prefix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut ends: Vec<usize> = value.char_indices().map(|(index, _)| index).chain([value.len()]).collect();
+    if longest {
+        ends.reverse();
+    }
+    match ends.into_iter().find(|&end| pattern::matches(pattern, &value[..end])) {
+        Some(end) => value[end..].to_string(),
+        None => value.to_string(),
+    }
+}
+
+fn remove_suffix(value: &str, pattern: &str, longest: bool) -> String {
+    let mut starts: Vec<usize> = value.char_indices().map(|(index, _)| index).chain([value.len()]).collect();
+    if !longest {
+        starts.reverse();
+    }
+    match starts.into_iter().find(|&start| pattern::matches(pattern, &value[start..])) {
+        Some(start) => value[..start].to_string(),
+        None => value.to_string(),
+    }
+}
+
+/// Splits the results of unquoted expansions on the characters of `IFS`.
+/// Whitespace separators are merged and trimmed, other separators each end a
+/// field.
+fn split_fields(segments: Vec<Segment>, shell: &Shell) -> Vec<Vec<Segment>> {
+    let ifs = shell.variables.get("IFS").unwrap_or(DEFAULT_IFS).to_string();
+    let mut fields = Vec::new();
+    let mut field: Vec<Segment> = Vec::new();
+    // Whether the current field has content, even if only `""`.
+    let mut started = false;
+
+    for segment in segments {
+        if segment.new_field {
+            fields.push(std::mem::take(&mut field));
+            started = false;
+        }
+        if !segment.split {
+            started |= segment.quoted || !segment.text.is_empty();
+            field.push(segment);
+            continue;
+        }
+        let mut text = String::new();
+        for c in segment.text.chars() {
+            if !ifs.contains(c) {
+                text.push(c);
+                started = true;
+                continue;
+            }
+            if !text.is_empty() {
+                field.push(Segment { text: std::mem::take(&mut text), quoted: false, split: true, new_field: false });
+            }
+            if started || !c.is_whitespace() {
+                fields.push(std::mem::take(&mut field));
+                started = false;
+            }
+        }
+        if !text.is_empty() {
+            field.push(Segment { text, quoted: false, split: true, new_field: false });
+        }
+    }
+    if started {
+        fields.push(field);
+    }
+    fields
+}
+
+#[cfg(test)]
+mod expansion_tests {
+    use super::*;
+    use crate::parser::PipelineCommand;
+
+    fn expand(line: &str, shell: &mut Shell) -> Result<Vec<String>, ExpandError> {
+        let list = parser::parse(line).unwrap().expect("line is blank");
+        let PipelineCommand::Simple(command) = &list.items[0].first.commands[0] else { panic!("not a simple command") };
+        expand_words(&command.words, shell)
+    }
+
+    fn shell_with(variables: &[(&str, &str)]) -> Shell {
+        let mut shell = Shell::default();
+        for (name, value) in variables {
+            shell.variables.set(name, value);
+        }
+        shell
+    }
+
+    #[test]
+    fn variables_and_defaults() {
+        let mut shell = shell_with(&[("NAME", "world"), ("EMPTY", "")]);
+        assert_eq!(vec!["echo", "hello", "world", "world!"], expand("echo hello $NAME ${NAME}!", &mut shell).unwrap());
+        assert_eq!(vec!["echo", "d", "x"], expand("echo ${EMPTY:-d} ${EMPTY-d} ${MISSING+y}x", &mut shell).unwrap());
+        assert_eq!(vec!["echo", "5"], expand("echo ${#NAME}", &mut shell).unwrap());
+    }
+
+    #[test]
+    fn assign_and_error() {
+        let mut shell = shell_with(&[]);
+        assert_eq!(vec!["echo", "v"], expand("echo ${X:=v}", &mut shell).unwrap());
+        assert_eq!(Some("v"), shell.variables.get("X"));
+        let error = ExpandError { message: String::from("Y: missing"), fatal: true };
+        assert_eq!(Err(error), expand("echo ${Y:?missing}", &mut shell));
+        assert_eq!(Err(ExpandError::from(String::from("$1: cannot assign in this way"))), expand("${1:=x}", &mut shell));
+    }
+
+    #[test]
+    fn prefix_and_suffix_removal() {
+        let mut shell = shell_with(&[("FILE", "src/main.tar.gz")]);
+        assert_eq!(vec!["main.tar.gz", "main.tar.gz"], expand("${FILE#*/} ${FILE##*/}", &mut shell).unwrap());
+        assert_eq!(vec!["src/main.tar", "src/main"], expand("${FILE%.*} ${FILE%%.*}", &mut shell).unwrap());
+        assert_eq!(vec!["src/main.tar.gz"], expand("${FILE%'.*'}", &mut shell).unwrap());
+    }
+
+    #[test]
+    fn tilde_and_braces() {
+        let mut shell = shell_with(&[("HOME", "/home/me")]);
+        assert_eq!(vec!["/home/me", "/home/me/src", "~/x", "a~"], expand("~ ~/src \\~/x a~", &mut shell).unwrap());
+        assert_eq!(vec!["~nobody-here/x", "~", "a/b", "a/c"], expand("~nobody-here/x '~' a/{b,c}", &mut shell).unwrap());
+        let list = parser::parse("~:~/b").unwrap().unwrap();
+        let PipelineCommand::Simple(command) = &list.items[0].first.commands[0] else { panic!("not a simple command") };
+        let word = &command.words[0];
+        assert_eq!("/home/me:/home/me/b", expand_assignment(word, &mut shell).unwrap());
+    }
+
+    #[test]
+    fn command_substitution() {
+        let mut shell = shell_with(&[("IFS", " ")]);
+        assert_eq!(vec!["x", "a", "b", "a b", "c"], expand("x $(echo a b) \"`echo 'a b'`\" $(echo $(echo c))", &mut shell).unwrap());
+        assert_eq!(vec!["1"], expand("$(echo 1; echo)", &mut shell).unwrap());
+    }
+
+    #[test]
+    fn field_splitting() {
+        let mut shell = shell_with(&[("LIST", "  a b\tc "), ("PAIR", "a b"), ("EMPTY", "")]);
+        assert_eq!(vec!["x", "a", "b", "c"], expand("x $LIST $EMPTY", &mut shell).unwrap());
+        assert_eq!(vec!["x", "  a b\tc ", ""], expand("x \"$LIST\" \"$EMPTY\"", &mut shell).unwrap());
+        assert_eq!(vec!["x", "pa", "bq"], expand("x p${PAIR}q", &mut shell).unwrap());
+        assert_eq!(vec!["x", "1", "2"], expand("x ${EMPTY:-1 2}", &mut shell).unwrap());
+    }
+
+    #[test]
+    fn positional_parameters() {
+        let mut shell = shell_with(&[]);
+        shell.positional = ["a b", "c", "", "d", "e", "f", "g", "h", "i", "ten"].map(String::from).to_vec();
+        assert_eq!(vec!["10", "a", "b", "ten", "a", "b0"], expand("$# $1 ${10} $10", &mut shell).unwrap());
+        assert_eq!(vec!["xa b", "c", "", "d"], expand("\"x$@\" $#", &mut shell).unwrap()[..4].to_vec());
+        assert_eq!(vec!["a b c  d e f g h i ten"], expand("\"$*\"", &mut shell).unwrap());
+        shell.positional.clear();
+        assert_eq!(vec!["x", ""], expand("x \"$@\" \"$*\"", &mut shell).unwrap());
+    }
+}
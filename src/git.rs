@@ -0,0 +1,207 @@
+//! The branch and state of a git work tree, read from the files in `.git`
+//! without running `git`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+#[derive(Debug, PartialEq)]
+pub struct GitStatus {
+    /// Name of the checked out branch, or the short commit hash when
+    /// detached.
+    pub branch: String,
+    /// Some tracked file differs from the index: changed, removed, or in
+    /// conflict.
+    pub dirty: bool,
+}
+
+/// Status of the repository holding `dir`, `None` outside of one.
+pub fn status(dir: &Path) -> Option<GitStatus> {
+    let (work_tree, git_dir) = find_repository(dir)?;
+    let head = fs::read_to_string(git_dir.join("HEAD")).ok()?;
+    let index = fs::read(git_dir.join("index")).unwrap_or_default();
+    let dirty = match parse_index(&index) {
+        Some(entries) => entries.iter().any(|entry| entry.differs(&work_tree)),
+        None => false,
+    };
+    Some(GitStatus { branch: branch_name(&head)?, dirty })
+}
+
+/// The work tree and the git directory of the repository holding `dir`. A
+/// `.git` file, as in linked work trees, points to the git directory.
+fn find_repository(dir: &Path) -> Option<(PathBuf, PathBuf)> {
+    for work_tree in dir.ancestors() {
+        let dot_git = work_tree.join(".git");
+        if dot_git.is_dir() {
+            return Some((work_tree.to_path_buf(), dot_git));
+        }
+        if dot_git.is_file() {
+            let content = fs::read_to_string(&dot_git).ok()?;
+            let git_dir = content.strip_prefix("gitdir:")?.trim();
+            return Some((work_tree.to_path_buf(), work_tree.join(git_dir)));
+        }
+    }
+    None
+}
+
+fn branch_name(head: &str) -> Option<String> {
+    let head = head.trim();
+    match head.strip_prefix("ref:") {
+        Some(reference) => {
+            let reference = reference.trim();
+            Some(reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string())
+        }
+        None => head.get(..7).map(String::from),
+    }
+}
+
+/// What the index records about a tracked file.
+#[derive(Debug, PartialEq)]
+struct IndexEntry {
+    path: String,
+    mtime: (u32, u32),
+    size: u32,
+    /// Merge stage, not 0 during a conflict.
+    stage: u16,
+    /// Submodules and files marked with `--assume-unchanged` are left alone.
+    skip: bool,
+}
+
+impl IndexEntry {
+    /// Whether the file in the work tree looks changed since it was added:
+    /// gone, another size, or another modification time.
+    fn differs(&self, work_tree: &Path) -> bool {
+        if self.skip {
+            return false;
+        }
+        if self.stage != 0 {
+            return true;
+        }
+        let Ok(metadata) = fs::symlink_metadata(work_tree.join(&self.path)) else { return true };
+        let mtime = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok());
+        let same_time = mtime.is_some_and(|time| {
+            time.as_secs() as u32 == self.mtime.0 && (self.mtime.1 == 0 || time.subsec_nanos() == self.mtime.1)
+        });
+        metadata.len() as u32 != self.size || !same_time
+    }
+}
+
+/// Size of the fixed part of an entry: times, device, inode, mode, owner,
+/// size, hash and flags.
+const ENTRY_HEADER: usize = 62;
+const ASSUME_VALID: u16 = 0x8000;
+const EXTENDED: u16 = 0x4000;
+const GITLINK_MODE: u32 = 0o160000;
+
+/// Reads the entries of an index file, versions 2 to 4.
+fn parse_index(data: &[u8]) -> Option<Vec<IndexEntry>> {
+    let u32_at = |offset: usize| data.get(offset..offset + 4).map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()));
+    let u16_at = |offset: usize| data.get(offset..offset + 2).map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()));
+    if data.get(..4)? != b"DIRC" {
+        return None;
+    }
+    let version = u32_at(4)?;
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+    let count = u32_at(8)?;
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut offset = 12;
+    let mut previous_path: Vec<u8> = Vec::new();
+    for _ in 0..count {
+        let start = offset;
+        let mtime = (u32_at(start + 8)?, u32_at(start + 12)?);
+        let mode = u32_at(start + 24)?;
+        let size = u32_at(start + 36)?;
+        let flags = u16_at(start + 60)?;
+        offset += ENTRY_HEADER;
+        if version >= 3 && flags & EXTENDED != 0 {
+            offset += 2;
+        }
+        let path = if version == 4 {
+            // The path drops the end of the previous one and adds a suffix.
+            let (strip, length) = read_varint(data.get(offset..)?)?;
+            offset += length;
+            let suffix_length = data.get(offset..)?.iter().position(|&byte| byte == 0)?;
+            let keep = previous_path.len().checked_sub(strip)?;
+            let mut path = previous_path[..keep].to_vec();
+            path.extend_from_slice(&data[offset..offset + suffix_length]);
+            offset += suffix_length + 1;
+            path
+        } else {
+            let length = data.get(offset..)?.iter().position(|&byte| byte == 0)?;
+            let path = data[offset..offset + length].to_vec();
+            // Entries are padded with zeros to a multiple of 8 bytes.
+            offset = start + (offset + length - start + 8) / 8 * 8;
+            path
+        };
+        entries.push(IndexEntry {
+            path: String::from_utf8_lossy(&path).into_owned(),
+            mtime,
+            size,
+            stage: (flags >> 12) & 0x3,
+            skip: flags & ASSUME_VALID != 0 || mode & 0o170000 == GITLINK_MODE,
+        });
+        previous_path = path;
+    }
+    Some(entries)
+}
+
+/// Reads the variable length number of version 4 indexes, giving it with
+/// the number of bytes used.
+fn read_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (index, &byte) in data.iter().enumerate() {
+        value = if index == 0 { usize::from(byte & 0x7f) } else { ((value + 1) << 7) | usize::from(byte & 0x7f) };
+        if byte & 0x80 == 0 {
+            return Some((value, index + 1));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod git_tests {
+    use super::*;
+
+    fn index_entry(path: &str, size: u32, flags: u16) -> Vec<u8> {
+        let mut entry = vec![0u8; ENTRY_HEADER];
+        entry[8..12].copy_from_slice(&5u32.to_be_bytes());
+        entry[24..28].copy_from_slice(&0o100644u32.to_be_bytes());
+        entry[36..40].copy_from_slice(&size.to_be_bytes());
+        entry[60..62].copy_from_slice(&(flags | path.len() as u16).to_be_bytes());
+        entry.extend_from_slice(path.as_bytes());
+        entry.push(0);
+        while !entry.len().is_multiple_of(8) {
+            entry.push(0);
+        }
+        entry
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(Some("main".to_string()), branch_name("ref: refs/heads/main\n"));
+        assert_eq!(Some("feature/x".to_string()), branch_name("ref: refs/heads/feature/x"));
+        assert_eq!(Some("3d98cdc".to_string()), branch_name("3d98cdc1e2f3a4b5c6d7e8f9a0b1c2d3e4f5a6b7\n"));
+    }
+
+    #[test]
+    fn index_entries() {
+        let mut index = b"DIRC".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend(index_entry("Cargo.toml", 120, 0));
+        index.extend(index_entry("src/main.rs", 7, 0x2000));
+        let entries = parse_index(&index).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!("src/main.rs", entries[1].path);
+        assert_eq!((120, (5, 0)), (entries[0].size, entries[0].mtime));
+        assert_eq!(2, entries[1].stage);
+    }
+
+    #[test]
+    fn varints() {
+        assert_eq!(Some((5, 1)), read_varint(&[5]));
+        assert_eq!(Some((128, 2)), read_varint(&[0x80, 0x00]));
+    }
+}
@@ -0,0 +1,161 @@
+//! Filename generation: finds the paths matching a pattern like `src/*.rs`
+//! or `**/*.txt`. Each part between slashes is matched against the entries
+//! of one directory, `**` stands for any number of directories.
+
+use std::fs;
+use std::path::Path;
+
+use crate::pattern;
+
+const RECURSIVE: &str = "**";
+
+/// Paths matching the pattern, sorted. Names starting with a dot are only
+/// matched by a part starting with a dot, unless `dotglob` is set. Relative
+/// patterns are looked up from `base`, empty for the current directory, and
+/// give relative paths.
+pub fn glob(pattern: &str, base: &Path, dotglob: bool) -> Vec<String> {
+    let mut matches = Vec::new();
+    match pattern.strip_prefix('/') {
+        Some(rest) => {
+            let parts: Vec<&str> = rest.split('/').collect();
+            expand("/", &parts, base, dotglob, &mut matches);
+        }
+        None => {
+            let parts: Vec<&str> = pattern.split('/').collect();
+            expand("", &parts, base, dotglob, &mut matches);
+        }
+    }
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+/// Matches `parts` against the directory `prefix`, which is written the way
+/// the results should start; empty for the current directory.
+fn expand(prefix: &str, parts: &[&str], base: &Path, dotglob: bool, matches: &mut Vec<String>) {
+    let Some((&part, rest)) = parts.split_first() else {
+        if !prefix.is_empty() && fs::symlink_metadata(base.join(prefix)).is_ok() {
+            matches.push(prefix.to_string());
+        }
+        return;
+    };
+
+    if part.is_empty() {
+        // A trailing slash only keeps directories, `a//b` is the same as `a/b`.
+        if rest.is_empty() {
+            if base.join(prefix).is_dir() {
+                matches.push(format!("{}/", prefix.trim_end_matches('/')));
+            }
+        } else {
+            expand(prefix, rest, base, dotglob, matches);
+        }
+        return;
+    }
+
+    if part == RECURSIVE {
+        if rest.is_empty() {
+            // A final `**` matches everything below the directory.
+            for (name, is_dir) in entries(prefix, base, dotglob, false) {
+                let path = join(prefix, &name);
+                if is_dir {
+                    expand(&path, parts, base, dotglob, matches);
+                }
+                matches.push(path);
+            }
+        } else {
+            expand(prefix, rest, base, dotglob, matches);
+            for (name, is_dir) in entries(prefix, base, dotglob, false) {
+                if is_dir {
+                    expand(&join(prefix, &name), parts, base, dotglob, matches);
+                }
+            }
+        }
+        return;
+    }
+
+    if !pattern::has_wildcards(part) {
+        expand(&join(prefix, &pattern::unescape(part)), rest, base, dotglob, matches);
+        return;
+    }
+
+    let show_hidden = dotglob || part.starts_with('.') || part.starts_with("\\.");
+    for (name, is_dir) in entries(prefix, base, show_hidden, true) {
+        if !pattern::matches(part, &name) {
+            continue;
+        }
+        let path = join(prefix, &name);
+        if rest.is_empty() {
+            matches.push(path);
+        } else if is_dir {
+            expand(&path, rest, base, dotglob, matches);
+        }
+    }
+}
+
+/// Names of the entries of a directory and whether each one is a directory.
+/// With `follow_links` unset, links to directories are not reported as
+/// directories, which keeps `**` from looping.
+fn entries(prefix: &str, base: &Path, show_hidden: bool, follow_links: bool) -> Vec<(String, bool)> {
+    let dir = if prefix.is_empty() { "." } else { prefix };
+    let Ok(read_dir) = fs::read_dir(base.join(dir)) else {
+        return Vec::new();
+    };
+    read_dir
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if name.starts_with('.') && !show_hidden {
+                return None;
+            }
+            let is_dir = if follow_links {
+                entry.path().is_dir()
+            } else {
+                entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+            };
+            Some((name, is_dir))
+        })
+        .collect()
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{prefix}{name}")
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+#[cfg(test)]
+mod glob_tests {
+    use super::*;
+    use std::env;
+    use std::fs::File;
+
+    #[test]
+    fn matches_files_in_a_tree() {
+        let root = env::temp_dir().join(format!("glob_tests_{}", std::process::id()));
+        for dir in ["src/nested", ".hidden"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in ["a.rs", "b.txt", ".dot.rs", "src/main.rs", "src/nested/deep.rs", ".hidden/x.rs"] {
+            File::create(root.join(file)).unwrap();
+        }
+        let root_pattern = root.to_str().unwrap();
+        let relative = |paths: Vec<String>| -> Vec<String> {
+            paths.iter().map(|path| path[root_pattern.len() + 1..].to_string()).collect()
+        };
+
+        assert_eq!(vec!["a.rs"], relative(glob(&format!("{root_pattern}/*.rs"), Path::new(""), false)));
+        assert_eq!(vec![".dot.rs", "a.rs"], relative(glob(&format!("{root_pattern}/*.rs"), Path::new(""), true)));
+        assert_eq!(vec![".dot.rs"], relative(glob(&format!("{root_pattern}/.*.rs"), Path::new(""), false)));
+        assert_eq!(vec!["a.rs", "b.txt"], relative(glob(&format!("{root_pattern}/[a-b].??*"), Path::new(""), false)));
+        assert_eq!(vec!["src/"], relative(glob(&format!("{root_pattern}/*/"), Path::new(""), false)));
+        assert_eq!(vec!["a.rs", "src/main.rs", "src/nested/deep.rs"],
+                   relative(glob(&format!("{root_pattern}/**/*.rs"), Path::new(""), false)));
+        assert!(glob(&format!("{root_pattern}/*.md"), Path::new(""), false).is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
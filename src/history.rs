@@ -0,0 +1,366 @@
+//! Lines typed at the prompt, kept in a file shared by the sessions of the
+//! user.
+//!
+//! Every entry goes in the file with a comment line holding when it ran,
+//! its exit status and the directory it ran in:
+//!
+//! ```text
+//! #1760781600;0;/home/user/src
+//! cargo build
+//! ```
+//!
+//! Sessions append their entries as the commands finish, holding a lock on
+//! the file so lines of concurrent sessions don't mix.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::{env, time};
+
+use crate::platform;
+use crate::variables::Variables;
+
+/// Number of entries kept when `HISTSIZE` is not set.
+const DEFAULT_SIZE: usize = 1000;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HistoryEntry {
+    pub line: String,
+    /// Seconds since the Unix epoch when the line was entered.
+    pub time: Option<i64>,
+    /// Directory the line was entered in.
+    pub cwd: Option<String>,
+    /// Exit status of the command, once it finished.
+    pub status: Option<i32>,
+}
+
+#[derive(Clone, Default)]
+pub struct History {
+    entries: Vec<HistoryEntry>,
+    /// File finished entries are appended to, only for interactive shells.
+    file: Option<PathBuf>,
+    /// Index of the first entry entered in this session.
+    session_start: usize,
+    /// The last entry waits for the exit status of its command.
+    running: bool,
+}
+
+impl History {
+    pub fn entries(&self) -> &[HistoryEntry] {
+        &self.entries
+    }
+
+    /// Reads the history file and keeps saving to it. The file is cut down to
+    /// the last `HISTFILESIZE` entries, and the last `HISTSIZE` are kept.
+    pub fn load(&mut self, path: PathBuf, variables: &Variables) -> io::Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(&path)?;
+        file.lock()?;
+        let mut text = Vec::new();
+        file.read_to_end(&mut text)?;
+        let mut entries = parse_file(&String::from_utf8_lossy(&text));
+
+        let file_size = limit(variables, "HISTFILESIZE").unwrap_or_else(|| size(variables));
+        if entries.len() > file_size {
+            entries.drain(..entries.len() - file_size);
+            rewrite(&mut file, &entries)?;
+        }
+        self.session_start = entries.len();
+        entries.append(&mut self.entries);
+        self.entries = entries;
+        self.truncate(size(variables));
+        self.file = Some(path);
+        Ok(())
+    }
+
+    /// The file the entries are saved to, if any.
+    pub fn file(&self) -> Option<&Path> {
+        self.file.as_deref()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.session_start = 0;
+        self.running = false;
+    }
+
+    /// Removes the entries with the given indexes, returns `false` when some
+    /// are out of range.
+    pub fn delete(&mut self, indexes: RangeInclusive<usize>) -> bool {
+        if indexes.is_empty() || *indexes.end() >= self.entries.len() {
+            return false;
+        }
+        if *indexes.end() == self.entries.len() - 1 {
+            self.running = false;
+        }
+        self.session_start -= self.session_start.min(indexes.end() + 1).saturating_sub(*indexes.start());
+        self.entries.drain(indexes);
+        true
+    }
+
+    /// Replaces the content of a file by all the entries.
+    pub fn write_file(&self, path: &Path) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(path)?;
+        file.lock()?;
+        rewrite(&mut file, &self.entries)
+    }
+
+    /// Adds the entries of a file after the ones there are.
+    pub fn read_file(&mut self, path: &Path, variables: &Variables) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        file.lock_shared()?;
+        let mut text = Vec::new();
+        file.read_to_end(&mut text)?;
+        self.entries.extend(parse_file(&String::from_utf8_lossy(&text)));
+        self.truncate(size(variables));
+        Ok(())
+    }
+
+    /// Appends the entries of this session to a file. The history file gets
+    /// them as the commands finish already.
+    pub fn append_file(&self, path: &Path) -> io::Result<()> {
+        if self.file.as_deref() == Some(path) {
+            return Ok(());
+        }
+        let text: String = self.entries[self.session_start..].iter().map(format_entry).collect();
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        file.lock()?;
+        file.write_all(text.as_bytes())
+    }
+
+    /// Adds a line typed at the prompt, unless it is blank or `HISTCONTROL`
+    /// leaves it out. Returns `true` when it was added.
+    pub fn add(&mut self, line: &str, variables: &Variables) -> bool {
+        let control = variables.get("HISTCONTROL").unwrap_or_default();
+        let has_option = |option: &str| {
+            control.split(':').any(|name| {
+                name == option || (name == "ignoreboth" && matches!(option, "ignorespace" | "ignoredups"))
+            })
+        };
+        if line.trim().is_empty() || (has_option("ignorespace") && line.starts_with(' ')) {
+            return false;
+        }
+        if has_option("ignoredups") && self.entries.last().is_some_and(|entry| entry.line == line) {
+            return false;
+        }
+        if has_option("erasedups") {
+            let before_session = self.entries[..self.session_start].iter().filter(|entry| entry.line == line).count();
+            self.entries.retain(|entry| entry.line != line);
+            self.session_start -= before_session;
+        }
+        let time = time::SystemTime::now().duration_since(time::UNIX_EPOCH).ok();
+        self.entries.push(HistoryEntry {
+            line: line.to_string(),
+            time: time.and_then(|time| i64::try_from(time.as_secs()).ok()),
+            cwd: env::current_dir().ok().map(|dir| dir.to_string_lossy().into_owned()),
+            status: None,
+        });
+        self.running = true;
+        self.truncate(size(variables));
+        true
+    }
+
+    /// Records the exit status of the command of the last entry and appends
+    /// the entry to the history file.
+    pub fn finish(&mut self, status: i32) -> io::Result<()> {
+        if !std::mem::take(&mut self.running) {
+            return Ok(());
+        }
+        let Some(entry) = self.entries.last_mut() else { return Ok(()) };
+        entry.status = Some(status);
+        let Some(path) = &self.file else { return Ok(()) };
+        let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+        file.lock()?;
+        file.write_all(format_entry(entry).as_bytes())
+    }
+
+    fn truncate(&mut self, size: usize) {
+        if self.entries.len() > size {
+            let removed = self.entries.len() - size;
+            self.entries.drain(..removed);
+            self.session_start = self.session_start.saturating_sub(removed);
+            if size == 0 {
+                self.running = false;
+            }
+        }
+    }
+}
+
+/// The file history is saved to: `$HISTFILE`, by default
+/// `$XDG_STATE_HOME/shell/history`. `None` when `HISTFILE` is empty.
+pub fn default_file(variables: &Variables) -> Option<PathBuf> {
+    if let Some(file) = variables.get("HISTFILE") {
+        return (!file.is_empty()).then(|| PathBuf::from(file));
+    }
+    let state_dir = match variables.get("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => {
+            let home = variables.get("HOME").map(PathBuf::from).or_else(platform::home_dir)?;
+            home.join(".local").join("state")
+        }
+    };
+    Some(state_dir.join("shell").join("history"))
+}
+
+/// Number of entries kept in memory.
+fn size(variables: &Variables) -> usize {
+    limit(variables, "HISTSIZE").unwrap_or(DEFAULT_SIZE)
+}
+
+/// A number of entries set in a variable, a negative one meaning no limit.
+fn limit(variables: &Variables, name: &str) -> Option<usize> {
+    let value: i64 = variables.get(name)?.trim().parse().ok()?;
+    Some(usize::try_from(value).unwrap_or(usize::MAX))
+}
+
+fn rewrite(file: &mut File, entries: &[HistoryEntry]) -> io::Result<()> {
+    let text: String = entries.iter().map(format_entry).collect();
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(text.as_bytes())
+}
+
+fn format_entry(entry: &HistoryEntry) -> String {
+    let field = |value: Option<String>| value.unwrap_or_default();
+    format!(
+        "#{};{};{}\n{}\n",
+        field(entry.time.map(|time| time.to_string())),
+        field(entry.status.map(|status| status.to_string())),
+        field(entry.cwd.clone()),
+        escape(&entry.line)
+    )
+}
+
+/// Entries of a history file. Lines without a comment line before them, like
+/// the ones of other shells, have no details.
+fn parse_file(text: &str) -> Vec<HistoryEntry> {
+    let mut entries = Vec::new();
+    let mut details = None;
+    for line in text.lines() {
+        if let Some(comment) = line.strip_prefix('#') {
+            details = Some(comment);
+            continue;
+        }
+        let mut entry = HistoryEntry { line: unescape(line), ..HistoryEntry::default() };
+        if let Some(details) = details.take() {
+            let mut fields = details.splitn(3, ';');
+            entry.time = fields.next().and_then(|time| time.parse().ok());
+            entry.status = fields.next().and_then(|status| status.parse().ok());
+            entry.cwd = fields.next().filter(|cwd| !cwd.is_empty()).map(String::from);
+        }
+        entries.push(entry);
+    }
+    entries
+}
+
+/// Keeps an entry on one line of the file: newlines and backslashes are
+/// escaped, and so is a `#` starting it.
+fn escape(line: &str) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    if line.starts_with('#') {
+        escaped.push('\\');
+    }
+    for c in line.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn unescape(line: &str) -> String {
+    let mut unescaped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => unescaped.push('\n'),
+                Some(next) => unescaped.push(next),
+                None => unescaped.push('\\'),
+            },
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+#[cfg(test)]
+mod history_tests {
+    use super::*;
+
+    fn variables(settings: &[(&str, &str)]) -> Variables {
+        let mut variables = Variables::default();
+        for (name, value) in settings {
+            variables.set(name, value);
+        }
+        variables
+    }
+
+    fn lines(history: &History) -> Vec<&str> {
+        history.entries().iter().map(|entry| entry.line.as_str()).collect()
+    }
+
+    #[test]
+    fn entries_survive_the_file() {
+        let entry = HistoryEntry {
+            line: "#not a comment\nprintf 'a\\n'".to_string(),
+            time: Some(1760781600),
+            cwd: Some("/tmp/a;b".to_string()),
+            status: Some(1),
+        };
+        let plain = HistoryEntry { line: "ls".to_string(), ..HistoryEntry::default() };
+        let text = format_entry(&entry) + "ls\n";
+        assert_eq!(vec![entry, plain], parse_file(&text));
+    }
+
+    #[test]
+    fn control_and_size() {
+        let mut history = History::default();
+        let settings = variables(&[("HISTCONTROL", "ignoreboth:erasedups"), ("HISTSIZE", "3")]);
+        for line in ["ls", "ls", " secret", "pwd", "", "ls", "cd", "echo"] {
+            history.add(line, &settings);
+        }
+        assert_eq!(vec!["ls", "cd", "echo"], lines(&history));
+    }
+
+    #[test]
+    fn loading_cuts_the_file() {
+        let path = env::temp_dir().join(format!("history_tests_{}", std::process::id())).join("history");
+        let mut history = History::default();
+        let settings = variables(&[("HISTFILESIZE", "2")]);
+        history.load(path.clone(), &settings).unwrap();
+        for line in ["one", "two", "three"] {
+            history.add(line, &settings);
+            history.finish(0).unwrap();
+        }
+        let mut history = History::default();
+        history.load(path.clone(), &settings).unwrap();
+        assert_eq!(vec!["two", "three"], lines(&history));
+        assert_eq!(Some(0), history.entries()[1].status);
+        assert_eq!(2, parse_file(&fs::read_to_string(&path).unwrap()).len());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn erased_duplicates_keep_the_session() {
+        let dir = env::temp_dir().join(format!("history_tests_erasedups_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("history"), "ls\npwd\n").unwrap();
+        let mut history = History::default();
+        let settings = variables(&[("HISTCONTROL", "erasedups")]);
+        history.load(dir.join("history"), &settings).unwrap();
+        for line in ["ls", "cd"] {
+            history.add(line, &settings);
+        }
+        assert_eq!(vec!["pwd", "ls", "cd"], lines(&history));
+        history.append_file(&dir.join("appended")).unwrap();
+        let appended = parse_file(&fs::read_to_string(dir.join("appended")).unwrap());
+        assert_eq!(vec!["ls", "cd"], appended.iter().map(|entry| entry.line.as_str()).collect::<Vec<_>>());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,296 @@
+//! History expansion of lines typed at the prompt, as in csh and bash.
+//!
+//! An event picks an earlier line: `!!` the last one, `!n` line `n` of
+//! `history`, `!-n` the n-th last, `!prefix` the last one starting with
+//! `prefix` and `!?text?` the last one containing `text`. A word designator
+//! after a `:` picks words of it: `n`, `^` (the first argument), `$` (the
+//! last), `x-y`, `x*` and `*` (all arguments); the `:` can be left out before
+//! `^`, `$` and `*`, and `!$` alone is `!!:$`. `^old^new` at the start of a
+//! line repeats the last line with `old` replaced by `new`.
+//!
+//! A `!` is kept when followed by a blank, `=`, an operator character like
+//! `;`, `|` or `(`, a quote or the end of the line, when it is part of `$!` or
+//! `${!name}`, and also in single quotes or after a backslash.
+
+use crate::history::HistoryEntry;
+
+/// Expands the history references of a line, giving `None` if it has none.
+pub fn expand(line: &str, history: &[HistoryEntry]) -> Result<Option<String>, String> {
+    if let Some(substitution) = line.strip_prefix('^') {
+        return quick_substitution(substitution, history).map(Some);
+    }
+    let mut expanded = String::with_capacity(line.len());
+    let mut changed = false;
+    let mut quote = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        let after = &rest[c.len_utf8()..];
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('\''), _) => (),
+            (_, '\\') => {
+                if let Some(escaped) = after.chars().next() {
+                    expanded.push(c);
+                    expanded.push(escaped);
+                    rest = &after[escaped.len_utf8()..];
+                    continue;
+                }
+            }
+            (_, '!') if !expanded.ends_with('$') && !expanded.ends_with("${") && starts_reference(after) => {
+                let (text, length) = reference(after, history)?;
+                expanded.push_str(&text);
+                changed = true;
+                rest = &after[length..];
+                continue;
+            }
+            _ => (),
+        }
+        expanded.push(c);
+        rest = after;
+    }
+    Ok(changed.then_some(expanded))
+}
+
+/// Whether the text after a `!` makes a reference. What would end a
+/// `!prefix` right away doesn't: an empty prefix would pick the last line.
+fn starts_reference(after: &str) -> bool {
+    match after.chars().next() {
+        None => false,
+        Some(c) => !(c.is_whitespace() || "=;&|<>()\"'".contains(c)),
+    }
+}
+
+/// Expands the reference after a `!`, giving the text and the length of the
+/// reference.
+fn reference(text: &str, history: &[HistoryEntry]) -> Result<(String, usize), String> {
+    let (line, event_length) = event(text, history)?;
+    let designator = &text[event_length..];
+    let designator = match designator.strip_prefix(':') {
+        Some(after_colon) if after_colon.starts_with(|c: char| c.is_ascii_digit() || "^$*-".contains(c)) => {
+            Some((after_colon, 1))
+        }
+        _ if designator.starts_with(['^', '$', '*']) => Some((designator, 0)),
+        _ => None,
+    };
+    match designator {
+        None => Ok((line.to_string(), event_length)),
+        Some((designator, colon)) => {
+            let start = event_length + colon;
+            let (words, length) = select_words(line, designator)
+                .ok_or_else(|| format!("!{}: bad word specifier", &text[..start + designator_length(designator)]))?;
+            Ok((words, start + length))
+        }
+    }
+}
+
+/// The line an event refers to and the length of the event. `$`, `^`, `*`
+/// and `:` start a word designator of the last line.
+fn event<'a>(text: &str, history: &'a [HistoryEntry]) -> Result<(&'a str, usize), String> {
+    let not_found = |event: &str| format!("!{event}: event not found");
+    let last = |length| history.last().map(|entry| (entry.line.as_str(), length)).ok_or_else(|| not_found(&text[..length]));
+    if text.starts_with('!') {
+        return last(1);
+    }
+    if text.starts_with(['$', '^', '*', ':']) {
+        return last(0);
+    }
+    if let Some(search) = text.strip_prefix('?') {
+        let (needle, length) = match search.find('?') {
+            Some(end) => (&search[..end], end + 2),
+            None => (search, text.len()),
+        };
+        return history
+            .iter()
+            .rev()
+            .find(|entry| entry.line.contains(needle))
+            .map(|entry| (entry.line.as_str(), length))
+            .ok_or_else(|| not_found(&text[..length]));
+    }
+    let minus = text.starts_with('-') as usize;
+    let digits = text[minus..].find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len() - minus);
+    if digits > 0 {
+        let length = minus + digits;
+        let number: usize = text[minus..length].parse().map_err(|_| not_found(&text[..length]))?;
+        let index = match minus {
+            1 => history.len().checked_sub(number),
+            _ => number.checked_sub(1),
+        };
+        return index
+            .and_then(|index| history.get(index))
+            .map(|entry| (entry.line.as_str(), length))
+            .ok_or_else(|| not_found(&text[..length]));
+    }
+    let length = text.find(|c: char| c.is_whitespace() || ":;&|<>()\"'".contains(c)).unwrap_or(text.len());
+    let prefix = &text[..length];
+    history
+        .iter()
+        .rev()
+        .find(|entry| entry.line.starts_with(prefix))
+        .map(|entry| (entry.line.as_str(), length))
+        .ok_or_else(|| not_found(prefix))
+}
+
+/// Length of a word designator, up to where a valid one would end.
+fn designator_length(designator: &str) -> usize {
+    designator.find(|c: char| !(c.is_ascii_digit() || "^$*-".contains(c))).unwrap_or(designator.len())
+}
+
+/// Words of the line picked by the designator and its length, `None` if it
+/// asks for words the line doesn't have.
+fn select_words(line: &str, designator: &str) -> Option<(String, usize)> {
+    let words = split_words(line);
+    let last = words.len().checked_sub(1)?;
+    let mut chars = designator.char_indices().peekable();
+    let bound = |chars: &mut std::iter::Peekable<std::str::CharIndices>| -> Option<usize> {
+        match chars.peek().copied() {
+            Some((_, '^')) => {
+                chars.next();
+                Some(1)
+            }
+            Some((_, '$')) => {
+                chars.next();
+                Some(last)
+            }
+            Some((start, c)) if c.is_ascii_digit() => {
+                let mut end = start;
+                while let Some((index, _)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = index + 1;
+                }
+                designator[start..end].parse().ok()
+            }
+            _ => None,
+        }
+    };
+    let (first, end) = if chars.next_if(|&(_, c)| c == '*').is_some() {
+        // All arguments, none for a command without any.
+        (1, last)
+    } else if chars.next_if(|&(_, c)| c == '-').is_some() {
+        (0, bound(&mut chars)?)
+    } else {
+        let first = bound(&mut chars)?;
+        if chars.next_if(|&(_, c)| c == '*').is_some() {
+            (first, last)
+        } else if chars.next_if(|&(_, c)| c == '-').is_some() {
+            // `x-` leaves out the last word.
+            (first, bound(&mut chars).unwrap_or(last.saturating_sub(1)))
+        } else {
+            (first, first)
+        }
+    };
+    let length = chars.peek().map_or(designator.len(), |&(index, _)| index);
+    if end > last || (first > end && !(first == end + 1 && designator.contains('*'))) {
+        return None;
+    }
+    Some((words.get(first..=end).unwrap_or_default().join(" "), length))
+}
+
+/// `^old^new^`: the last line with the first `old` replaced by `new`.
+fn quick_substitution(substitution: &str, history: &[HistoryEntry]) -> Result<String, String> {
+    let failed = || format!("^{substitution}: substitution failed");
+    let (old, new) = substitution.split_once('^').unwrap_or((substitution, ""));
+    let new = new.strip_suffix('^').unwrap_or(new);
+    let line = &history.last().ok_or_else(|| format!("^{substitution}: event not found"))?.line;
+    if old.is_empty() || !line.contains(old) {
+        return Err(failed());
+    }
+    Ok(line.replacen(old, new, 1))
+}
+
+/// Words of a history line: quoted text stays in its word and operators
+/// are words of their own.
+fn split_words(line: &str) -> Vec<&str> {
+    let mut words = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, c)) = chars.next() {
+        if let Some(open) = quote {
+            if c == open {
+                quote = None;
+            } else if c == '\\' && open == '"' {
+                chars.next();
+            }
+            continue;
+        }
+        if c.is_whitespace() || "|&;<>()".contains(c) {
+            if let Some(start) = start.take() {
+                words.push(&line[start..index]);
+            }
+            if !c.is_whitespace() {
+                let doubled = chars.next_if(|&(_, next)| next == c && "|&;<>".contains(c)).is_some();
+                words.push(&line[index..index + 1 + doubled as usize]);
+            }
+            continue;
+        }
+        start.get_or_insert(index);
+        match c {
+            '\'' | '"' => quote = Some(c),
+            '\\' => {
+                chars.next();
+            }
+            _ => (),
+        }
+    }
+    if let Some(start) = start {
+        words.push(&line[start..]);
+    }
+    words
+}
+
+#[cfg(test)]
+mod history_expansion_tests {
+    use super::*;
+
+    fn history(lines: &[&str]) -> Vec<HistoryEntry> {
+        lines.iter().map(|line| HistoryEntry { line: line.to_string(), ..HistoryEntry::default() }).collect()
+    }
+
+    fn expanded(line: &str) -> Result<Option<String>, String> {
+        expand(line, &history(&["ls -l src", "cat 'a b' c > out", "echo one two three"]))
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(Ok(Some("echo one two three | wc".to_string())), expanded("!! | wc"));
+        assert_eq!(Ok(Some("ls -l src".to_string())), expanded("!1"));
+        assert_eq!(Ok(Some("cat 'a b' c > out".to_string())), expanded("!-2"));
+        assert_eq!(Ok(Some("ls -l src".to_string())), expanded("!ls"));
+        assert_eq!(Ok(Some("cat 'a b' c > out".to_string())), expanded("!?b'?"));
+        assert_eq!(Err("!4: event not found".to_string()), expanded("!4"));
+        assert_eq!(Err("!rm: event not found".to_string()), expanded("!rm -r"));
+    }
+
+    #[test]
+    fn references_need_an_event() {
+        assert_eq!(Ok(None), expanded("echo done!; ls"));
+        assert_eq!(Ok(None), expanded("true!|cat!&"));
+        assert_eq!(Ok(None), expanded("echo \"wow!\""));
+        assert_eq!(Ok(None), expanded("kill $!&& wait ${!name}"));
+    }
+
+    #[test]
+    fn word_designators() {
+        assert_eq!(Ok(Some("vi three".to_string())), expanded("vi !$"));
+        assert_eq!(Ok(Some("echo one two three".to_string())), expanded("echo !*"));
+        assert_eq!(Ok(Some("two".to_string())), expanded("!:2"));
+        assert_eq!(Ok(Some("'a b' c".to_string())), expanded("!cat:1-2"));
+        assert_eq!(Ok(Some("c > out".to_string())), expanded("!-2:2*"));
+        assert_eq!(Ok(Some("-l".to_string())), expanded("!ls:^"));
+        assert_eq!(Err("!:5: bad word specifier".to_string()), expanded("!:5"));
+    }
+
+    #[test]
+    fn quick_substitutions() {
+        assert_eq!(Ok(Some("echo one 2 three".to_string())), expanded("^two^2"));
+        assert_eq!(Ok(Some("echo one three".to_string())), expanded("^two ^^"));
+        assert!(expanded("^four^4").is_err());
+    }
+
+    #[test]
+    fn lines_without_references() {
+        for line in ["echo hi!", "echo '!!'", "echo \\!!", "[ ! -f x ]", "a!=b", "echo \"hi!\""] {
+            assert_eq!(Ok(None), expanded(line), "{line}");
+        }
+    }
+}
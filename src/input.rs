@@ -0,0 +1,49 @@
+//! Reading the lines typed at the prompt.
+
+use std::io;
+
+use crate::platform;
+
+/// Number of end of file in a row ignored with `ignoreeof` before the shell
+/// exits anyway, so a closed input can't keep it running forever.
+pub const MAX_IGNORED_EOF: u32 = 10;
+
+/// Reads one line from standard input without its `\n` or `\r\n` ending.
+/// Gives `None` at the end of the input, and an `Interrupted` error after
+/// Ctrl-C.
+pub fn read_line() -> io::Result<Option<String>> {
+    let mut line = String::new();
+    loop {
+        match io::stdin().read_line(&mut line) {
+            Ok(0) if line.is_empty() => return Ok(None),
+            Ok(_) => break,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted && !platform::interrupted() => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    strip_line_ending(&mut line);
+    Ok(Some(line))
+}
+
+pub fn strip_line_ending(line: &mut String) {
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod input_tests {
+    use super::*;
+
+    #[test]
+    fn line_endings_are_removed() {
+        for (input, expected) in [("ls\n", "ls"), ("ls\r\n", "ls"), ("ls", "ls"), ("a\r\n\n", "a\r\n"), ("\n", "")] {
+            let mut line = input.to_string();
+            strip_line_ending(&mut line);
+            assert_eq!(expected, line);
+        }
+    }
+}
@@ -0,0 +1,366 @@
+//! Jobs: command lists started in the background with `&`, and foreground
+//! programs stopped with Ctrl-Z. Each job has a number, used as `%n` by
+//! `fg`, `bg`, `wait` and `disown`.
+
+use std::mem;
+use std::process::Child;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use crate::platform::{self, ProcessEvent};
+
+/// How often waiting for a job looks for Ctrl-C.
+const INTERRUPT_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JobState {
+    Running,
+    Stopped,
+    /// Ended with this exit status.
+    Done(i32),
+}
+
+pub struct Job {
+    pub id: usize,
+    /// The commands as written, shown in listings.
+    pub command: String,
+    status: Mutex<JobStatus>,
+    changed: Condvar,
+}
+
+struct JobStatus {
+    state: JobState,
+    /// Process group of the programs of the job, led by the first one.
+    group: Option<u32>,
+    /// Programs of a job stopped in the foreground with their exit status
+    /// once they ended, waited for by whoever continues the job. A job
+    /// started with `&` has none: the thread running it waits for its
+    /// programs.
+    children: Vec<(Child, Option<i32>)>,
+    /// The state changed in the background and nobody was told yet.
+    unreported: bool,
+    /// The first pipeline of the job started its program, or runs without
+    /// one.
+    started: bool,
+}
+
+impl Job {
+    fn lock(&self) -> MutexGuard<'_, JobStatus> {
+        self.status.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn state(&self) -> JobState {
+        self.lock().state
+    }
+
+    pub fn group(&self) -> Option<u32> {
+        self.lock().group
+    }
+
+    /// Makes the program the leader of the process group of the job, unless
+    /// it has one already.
+    pub fn lead_group(&self, pid: u32) {
+        let mut status = self.lock();
+        status.group.get_or_insert(pid);
+        status.started = true;
+        self.changed.notify_all();
+    }
+
+    /// Records that the first pipeline of the job runs without a program, so
+    /// it has no process group yet.
+    pub fn start_without_group(&self) {
+        self.lock().started = true;
+        self.changed.notify_all();
+    }
+
+    /// Waits until the first pipeline of the job started, so its process
+    /// group, `$!`, is known when there is one. Ctrl-C or the job ending or
+    /// stopping stop the wait too.
+    pub fn wait_start(&self) {
+        let mut status = self.lock();
+        while !status.started && status.state == JobState::Running && !platform::interrupted() {
+            status = self.changed.wait_timeout(status, INTERRUPT_CHECK_INTERVAL)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Records that a program of the job was stopped, from the thread
+    /// running the job.
+    pub fn stopped(&self) {
+        self.set_state(JobState::Stopped, true);
+    }
+
+    /// Records the exit status of a job started with `&`, from the thread
+    /// running it.
+    pub fn finish(&self, status: i32) {
+        self.set_state(JobState::Done(status), true);
+    }
+
+    fn set_state(&self, state: JobState, unreported: bool) {
+        let mut status = self.lock();
+        status.state = state;
+        status.unreported = unreported;
+        self.changed.notify_all();
+    }
+
+    /// Continues the stopped programs of the job.
+    pub fn resume(&self) {
+        let group = {
+            let mut status = self.lock();
+            if status.state != JobState::Stopped {
+                return;
+            }
+            status.state = JobState::Running;
+            status.unreported = false;
+            status.group
+        };
+        if let Some(group) = group {
+            platform::continue_group(group);
+        }
+    }
+
+    /// Waits until the job ends or stops, and gives its state then. Ctrl-C
+    /// stops waiting for a job started with `&`, which is still running then.
+    pub fn wait(&self) -> JobState {
+        let mut status = self.lock();
+        loop {
+            if status.state != JobState::Running {
+                status.unreported = false;
+                return status.state;
+            }
+            if status.children.is_empty() {
+                if platform::interrupted() {
+                    return JobState::Running;
+                }
+                status = self.changed.wait_timeout(status, INTERRUPT_CHECK_INTERVAL)
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .0;
+                continue;
+            }
+            // The lock is not held while waiting, so the job can still be
+            // listed.
+            let mut children = mem::take(&mut status.children);
+            drop(status);
+            let state = wait_children(&mut children, false);
+            status = self.lock();
+            status.children = children;
+            status.state = state;
+        }
+    }
+
+    /// Checks without waiting whether the programs of a job stopped in the
+    /// foreground and continued with `bg` ended or stopped again.
+    fn poll(&self) {
+        let mut status = self.lock();
+        if status.state != JobState::Running || status.children.is_empty() {
+            return;
+        }
+        let state = wait_children(&mut status.children, true);
+        if state != JobState::Running {
+            status.state = state;
+            status.unreported = true;
+        }
+    }
+}
+
+/// Waits for the programs that have not ended yet, until they all end or
+/// one stops. With `poll` set only the ones already ended or stopped are
+/// seen, and the job may still be running.
+fn wait_children(children: &mut [(Child, Option<i32>)], poll: bool) -> JobState {
+    for (child, exit_status) in children.iter_mut().filter(|(_, exit_status)| exit_status.is_none()) {
+        let event = match poll {
+            true => platform::poll_process(child),
+            false => platform::wait_process(child, true).map(Some),
+        };
+        match event {
+            Ok(Some(ProcessEvent::Exited(code))) => *exit_status = Some(code),
+            Ok(Some(ProcessEvent::Stopped)) => return JobState::Stopped,
+            Ok(None) => return JobState::Running,
+            // Waited for elsewhere already.
+            Err(_) => *exit_status = Some(1),
+        }
+    }
+    JobState::Done(children.last().and_then(|(_, exit_status)| *exit_status).unwrap_or(0))
+}
+
+/// The jobs of a shell, shared with the copies of it running pipelines and
+/// background lists.
+#[derive(Clone, Default)]
+pub struct Jobs {
+    /// Ordered by use: the last one is the current job, `%+`, and the one
+    /// before it the previous job, `%-`.
+    table: Arc<Mutex<Vec<Arc<Job>>>>,
+}
+
+impl Jobs {
+    fn lock(&self) -> MutexGuard<'_, Vec<Arc<Job>>> {
+        self.table.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// A job started with `&`, running on a thread that reports its state.
+    pub fn start(&self, command: String) -> Arc<Job> {
+        self.add(command, JobState::Running, None, Vec::new())
+    }
+
+    /// Programs stopped in the foreground, made a job of their own.
+    pub fn add_stopped(&self, command: String, group: u32, children: Vec<Child>) -> Arc<Job> {
+        let children = children.into_iter().map(|child| (child, None)).collect();
+        self.add(command, JobState::Stopped, Some(group), children)
+    }
+
+    fn add(&self, command: String, state: JobState, group: Option<u32>, children: Vec<(Child, Option<i32>)>)
+        -> Arc<Job> {
+        let mut table = self.lock();
+        let id = table.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+        let status = JobStatus { state, group, children, unreported: false, started: group.is_some() };
+        let job = Arc::new(Job { id, command, status: Mutex::new(status), changed: Condvar::new() });
+        table.push(Arc::clone(&job));
+        job
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// The jobs by number, with `+` marking the current job and `-` the
+    /// previous one.
+    pub fn list(&self) -> Vec<(Arc<Job>, char)> {
+        let table = self.lock();
+        let count = table.len();
+        let mut jobs: Vec<(Arc<Job>, char)> = table
+            .iter()
+            .enumerate()
+            .map(|(index, job)| {
+                let marker = match count - index {
+                    1 => '+',
+                    2 => '-',
+                    _ => ' ',
+                };
+                (Arc::clone(job), marker)
+            })
+            .collect();
+        jobs.sort_by_key(|(job, _)| job.id);
+        jobs
+    }
+
+    /// Finds the job of a `%` spec: `%n`, `%+` or `%%` for the current job,
+    /// `%-` for the previous one, `%text` for the one whose command starts
+    /// with `text` and `%?text` for the one containing it. Without a spec
+    /// it is the current job.
+    pub fn find(&self, spec: Option<&str>) -> Result<Arc<Job>, String> {
+        let table = self.lock();
+        let no_such_job = || format!("{}: no such job", spec.unwrap_or("current"));
+        let spec_text = spec.map(|spec| spec.strip_prefix('%').unwrap_or(spec));
+        let found = match spec_text {
+            None | Some("" | "+" | "%") => table.last(),
+            Some("-") => table.len().checked_sub(2).map(|index| &table[index]),
+            Some(number) if number.chars().all(|c| c.is_ascii_digit()) => {
+                table.iter().find(|job| number.parse() == Ok(job.id))
+            }
+            Some(text) => {
+                let matching: Vec<&Arc<Job>> = match text.strip_prefix('?') {
+                    Some(text) => table.iter().filter(|job| job.command.contains(text)).collect(),
+                    None => table.iter().filter(|job| job.command.starts_with(text)).collect(),
+                };
+                if matching.len() > 1 {
+                    return Err(format!("{}: ambiguous job spec", spec.unwrap_or_default()));
+                }
+                matching.first().copied()
+            }
+        };
+        found.cloned().ok_or_else(no_such_job)
+    }
+
+    /// The job whose process group is `pid`.
+    pub fn find_pid(&self, pid: u32) -> Option<Arc<Job>> {
+        self.lock().iter().find(|job| job.group() == Some(pid)).cloned()
+    }
+
+    /// Makes the job the current one.
+    pub fn make_current(&self, job: &Arc<Job>) {
+        let mut table = self.lock();
+        if let Some(index) = table.iter().position(|other| Arc::ptr_eq(other, job)) {
+            let job = table.remove(index);
+            table.push(job);
+        }
+    }
+
+    pub fn remove(&self, job: &Arc<Job>) {
+        self.lock().retain(|other| !Arc::ptr_eq(other, job));
+    }
+
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    /// Lines telling about the jobs that ended or stopped in the background
+    /// since the last call. The jobs that ended are forgotten.
+    pub fn take_notices(&self) -> Vec<String> {
+        let mut notices = Vec::new();
+        for (job, marker) in self.list() {
+            job.poll();
+            let mut status = job.lock();
+            if status.unreported {
+                status.unreported = false;
+                notices.push(describe(&job, marker, status.state));
+            }
+        }
+        self.lock().retain(|job| !matches!(job.state(), JobState::Done(_)));
+        notices
+    }
+
+    /// Lines of the `jobs` listing. The jobs listed are not told about again,
+    /// and the ones that ended are forgotten.
+    pub fn describe_all(&self) -> Vec<(Arc<Job>, String)> {
+        let lines = self
+            .list()
+            .into_iter()
+            .map(|(job, marker)| {
+                job.poll();
+                let mut status = job.lock();
+                status.unreported = false;
+                let line = describe(&job, marker, status.state);
+                drop(status);
+                (job, line)
+            })
+            .collect();
+        self.lock().retain(|job| !matches!(job.state(), JobState::Done(_)));
+        lines
+    }
+}
+
+/// `[1]+  Running                 sleep 10 &`
+pub fn describe(job: &Job, marker: char, state: JobState) -> String {
+    let (state, suffix) = match state {
+        JobState::Running => (String::from("Running"), " &"),
+        JobState::Stopped => (String::from("Stopped"), ""),
+        JobState::Done(0) => (String::from("Done"), ""),
+        JobState::Done(code) => (format!("Exit {code}"), ""),
+    };
+    format!("[{}]{marker}  {state:<24}{}{suffix}", job.id, job.command)
+}
+
+#[cfg(test)]
+mod jobs_tests {
+    use super::*;
+
+    #[test]
+    fn job_specs() {
+        let jobs = Jobs::default();
+        let build = jobs.start(String::from("cargo build"));
+        let sleep = jobs.start(String::from("sleep 10"));
+        assert_eq!(2, jobs.find(None).unwrap().id);
+        assert_eq!(1, jobs.find(Some("%-")).unwrap().id);
+        assert_eq!(1, jobs.find(Some("%1")).unwrap().id);
+        assert_eq!(1, jobs.find(Some("%car")).unwrap().id);
+        assert_eq!(2, jobs.find(Some("%?10")).unwrap().id);
+        assert_eq!(Err(String::from("%3: no such job")), jobs.find(Some("%3")).map(|job| job.id));
+        jobs.make_current(&build);
+        assert_eq!(vec!['+', '-'], jobs.list().iter().map(|(_, marker)| *marker).collect::<Vec<_>>());
+        sleep.finish(0);
+        assert_eq!(vec!["[2]-  Done                    sleep 10"], jobs.take_notices());
+        assert_eq!(1, jobs.len());
+        assert_eq!(2, jobs.start(String::from("ls")).id);
+    }
+}
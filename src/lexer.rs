@@ -0,0 +1,647 @@
+//! Splits a command line into words and operators while keeping track of
+//! quoting.
+//!
+//! Every word is stored as a list of parts so later phases know which text
+//! was quoted: single quoted text and backslash escapes are taken literally,
+//! double quoted text is still subject to expansion.
+
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WordPart {
+    /// Unquoted text.
+    Literal(String),
+    /// Text from single quotes or a backslash escape, never expanded.
+    Quoted(String),
+    /// Contents of double quotes.
+    DoubleQuoted(Vec<WordPart>),
+    /// `$name` or `${...}`.
+    Parameter(Parameter),
+    /// `$(command)` or `` `command` ``, holding the command as written.
+    CommandSubstitution(String),
+}
+
+/// Operations of `${name<operation>}`. With `colon` set an empty value is
+/// treated like an unset one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParameterOp {
+    /// `${name:-word}`: use `word` if the parameter is unset.
+    Default { word: Word, colon: bool },
+    /// `${name:=word}`: assign `word` if the parameter is unset.
+    Assign { word: Word, colon: bool },
+    /// `${name:?word}`: fail with the message `word` if the parameter is unset.
+    Error { word: Word, colon: bool },
+    /// `${name:+word}`: use `word` if the parameter is set.
+    Alternative { word: Word, colon: bool },
+    /// `${#name}`
+    Length,
+    /// `${name#pattern}`, or `${name##pattern}` for the longest match.
+    RemovePrefix { pattern: Word, longest: bool },
+    /// `${name%pattern}`, or `${name%%pattern}` for the longest match.
+    RemoveSuffix { pattern: Word, longest: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Parameter {
+    pub name: String,
+    pub operation: Option<ParameterOp>,
+}
+
+impl Parameter {
+    fn simple(name: String) -> Parameter {
+        Parameter { name, operation: None }
+    }
+
+    /// The parameter as it was written.
+    fn source(&self) -> String {
+        let name = &self.name;
+        let with_word = |operator: &str, word: &Word, colon: bool| {
+            let colon = if colon { ":" } else { "" };
+            format!("${{{name}{colon}{operator}{}}}", word.source())
+        };
+        match &self.operation {
+            None if name.len() > 1 && name.starts_with(|c: char| c.is_ascii_digit()) => format!("${{{name}}}"),
+            None => format!("${name}"),
+            Some(ParameterOp::Default { word, colon }) => with_word("-", word, *colon),
+            Some(ParameterOp::Assign { word, colon }) => with_word("=", word, *colon),
+            Some(ParameterOp::Error { word, colon }) => with_word("?", word, *colon),
+            Some(ParameterOp::Alternative { word, colon }) => with_word("+", word, *colon),
+            Some(ParameterOp::Length) => format!("${{#{name}}}"),
+            Some(ParameterOp::RemovePrefix { pattern, longest }) => {
+                with_word(if *longest { "##" } else { "#" }, pattern, false)
+            }
+            Some(ParameterOp::RemoveSuffix { pattern, longest }) => {
+                with_word(if *longest { "%%" } else { "%" }, pattern, false)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Word {
+    pub parts: Vec<WordPart>,
+}
+
+impl Word {
+    /// Joins all parts together with the quotes removed and without any
+    /// expansion, as the word was written.
+    pub fn unquote(&self) -> String {
+        fn push_parts(parts: &[WordPart], text: &mut String) {
+            for part in parts {
+                match part {
+                    WordPart::Literal(s) | WordPart::Quoted(s) => text.push_str(s),
+                    WordPart::DoubleQuoted(inner) => push_parts(inner, text),
+                    WordPart::Parameter(parameter) => text.push_str(&parameter.source()),
+                    WordPart::CommandSubstitution(command) => {
+                        text.push_str("$(");
+                        text.push_str(command);
+                        text.push(')');
+                    }
+                }
+            }
+        }
+        let mut text = String::new();
+        push_parts(&self.parts, &mut text);
+        text
+    }
+
+    /// The word written back with its quotes, so that reading it again
+    /// gives the same word.
+    pub fn source(&self) -> String {
+        let mut text = String::new();
+        for part in &self.parts {
+            match part {
+                WordPart::Literal(s) => text.push_str(s),
+                WordPart::Quoted(s) => {
+                    text.push('\'');
+                    text.push_str(&s.replace('\'', "'\\''"));
+                    text.push('\'');
+                }
+                WordPart::DoubleQuoted(inner) => {
+                    text.push('"');
+                    for part in inner {
+                        match part {
+                            WordPart::Literal(s) => {
+                                for c in s.chars() {
+                                    if matches!(c, '"' | '\\' | '$' | '`') {
+                                        text.push('\\');
+                                    }
+                                    text.push(c);
+                                }
+                            }
+                            other => text.push_str(&Word { parts: vec![other.clone()] }.source()),
+                        }
+                    }
+                    text.push('"');
+                }
+                WordPart::Parameter(parameter) => text.push_str(&parameter.source()),
+                WordPart::CommandSubstitution(command) => {
+                    text.push_str("$(");
+                    text.push_str(command);
+                    text.push(')');
+                }
+            }
+        }
+        text
+    }
+
+    /// The descriptor number if the word is made of unquoted digits only.
+    fn descriptor_number(&self) -> Option<u32> {
+        match self.parts.as_slice() {
+            [WordPart::Literal(digits)] if digits.chars().all(|c| c.is_ascii_digit()) => digits.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub fn push_literal(&mut self, c: char) {
+        if let Some(WordPart::Literal(s)) = self.parts.last_mut() {
+            s.push(c);
+        } else {
+            self.parts.push(WordPart::Literal(c.to_string()));
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RedirectKind {
+    /// `>`
+    Output,
+    /// `>>`
+    Append,
+    /// `<`
+    Input,
+    /// `>&`, the target is another descriptor.
+    Duplicate,
+    /// `&>`, both standard output and standard error.
+    OutputAll,
+    /// `&>>`
+    AppendAll,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operator {
+    Pipe,
+    And,
+    Or,
+    Semicolon,
+    /// `&`, running the commands before it in the background.
+    Background,
+    /// `;;`, ending a branch of `case`.
+    DoubleSemicolon,
+    LeftParen,
+    RightParen,
+    /// The end of a line, which ends a command like `;`.
+    Newline,
+    /// A redirection with the descriptor written in front of it, if any.
+    Redirect(Option<u32>, RedirectKind),
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operator::Pipe => write!(f, "|"),
+            Operator::And => write!(f, "&&"),
+            Operator::Or => write!(f, "||"),
+            Operator::Semicolon => write!(f, ";"),
+            Operator::Background => write!(f, "&"),
+            Operator::DoubleSemicolon => write!(f, ";;"),
+            Operator::LeftParen => write!(f, "("),
+            Operator::RightParen => write!(f, ")"),
+            Operator::Newline => write!(f, "newline"),
+            Operator::Redirect(fd, kind) => {
+                if let Some(fd) = fd {
+                    write!(f, "{fd}")?;
+                }
+                let operator = match kind {
+                    RedirectKind::Output => ">",
+                    RedirectKind::Append => ">>",
+                    RedirectKind::Input => "<",
+                    RedirectKind::Duplicate => ">&",
+                    RedirectKind::OutputAll => "&>",
+                    RedirectKind::AppendAll => "&>>",
+                };
+                write!(f, "{operator}")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(Word),
+    Operator(Operator),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnterminatedQuote(char),
+    TrailingBackslash,
+    UnexpectedToken(String),
+    UnexpectedEnd,
+    UnterminatedParameter,
+    BadSubstitution(String),
+    UnterminatedSubstitution,
+}
+
+impl ParseError {
+    /// True for input that ends in the middle of a command, which more
+    /// lines can complete.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParseError::UnterminatedQuote(_)
+                | ParseError::TrailingBackslash
+                | ParseError::UnexpectedEnd
+                | ParseError::UnterminatedParameter
+                | ParseError::UnterminatedSubstitution
+        )
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnterminatedQuote(quote) => write!(f, "unterminated {quote} quote"),
+            ParseError::TrailingBackslash => write!(f, "unexpected end of input after '\\'"),
+            ParseError::UnexpectedToken(token) => write!(f, "syntax error near unexpected token '{token}'"),
+            ParseError::UnexpectedEnd => write!(f, "syntax error: unexpected end of input"),
+            ParseError::UnterminatedParameter => write!(f, "missing '}}' in parameter expansion"),
+            ParseError::BadSubstitution(text) => write!(f, "bad substitution: '{text}'"),
+            ParseError::UnterminatedSubstitution => write!(f, "missing ')' in command substitution"),
+        }
+    }
+}
+
+pub fn tokenize(input: &str) -> Result<Vec<Token>, ParseError> {
+    let mut chars = input.chars().peekable();
+    let mut tokens = Vec::new();
+    let mut word = Word::default();
+    let mut in_word = false;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Operator(Operator::Newline));
+            }
+            c if c.is_whitespace() => finish_word(&mut tokens, &mut word, &mut in_word),
+            // A comment runs to the end of the line.
+            '#' if !in_word => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+            }
+            '|' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                let operator = if chars.next_if_eq(&'|').is_some() { Operator::Or } else { Operator::Pipe };
+                tokens.push(Token::Operator(operator));
+            }
+            '&' if chars.peek() == Some(&'&') => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                chars.next();
+                tokens.push(Token::Operator(Operator::And));
+            }
+            ';' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                let operator =
+                    if chars.next_if_eq(&';').is_some() { Operator::DoubleSemicolon } else { Operator::Semicolon };
+                tokens.push(Token::Operator(operator));
+            }
+            '(' | ')' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Operator(if c == '(' { Operator::LeftParen } else { Operator::RightParen }));
+            }
+            '>' | '<' => {
+                // Digits right in front of the operator name the descriptor.
+                let fd = word.descriptor_number();
+                if fd.is_some() {
+                    word = Word::default();
+                } else {
+                    finish_word(&mut tokens, &mut word, &mut in_word);
+                }
+                in_word = false;
+                let kind = if c == '<' {
+                    RedirectKind::Input
+                } else if chars.next_if_eq(&'>').is_some() {
+                    RedirectKind::Append
+                } else if chars.next_if_eq(&'&').is_some() {
+                    RedirectKind::Duplicate
+                } else {
+                    RedirectKind::Output
+                };
+                tokens.push(Token::Operator(Operator::Redirect(fd, kind)));
+            }
+            '&' if chars.peek() == Some(&'>') => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                chars.next();
+                let kind = if chars.next_if_eq(&'>').is_some() {
+                    RedirectKind::AppendAll
+                } else {
+                    RedirectKind::OutputAll
+                };
+                tokens.push(Token::Operator(Operator::Redirect(None, kind)));
+            }
+            '&' => {
+                finish_word(&mut tokens, &mut word, &mut in_word);
+                tokens.push(Token::Operator(Operator::Background));
+            }
+            '\\' => match chars.next() {
+                // A backslash-newline pair joins two lines, so the command
+                // goes on on the next one.
+                Some('\n') if chars.peek().is_none() => return Err(ParseError::TrailingBackslash),
+                Some('\n') => (),
+                Some(escaped) => {
+                    word.parts.push(WordPart::Quoted(escaped.to_string()));
+                    in_word = true;
+                }
+                None => return Err(ParseError::TrailingBackslash),
+            },
+            '\'' => {
+                word.parts.push(WordPart::Quoted(read_single_quoted(&mut chars)?));
+                in_word = true;
+            }
+            '"' => {
+                word.parts.push(WordPart::DoubleQuoted(read_double_quoted(&mut chars)?));
+                in_word = true;
+            }
+            '`' => {
+                word.parts.push(WordPart::CommandSubstitution(read_backquoted(&mut chars)?));
+                in_word = true;
+            }
+            '$' => {
+                match read_dollar(&mut chars)? {
+                    Some(part) => word.parts.push(part),
+                    None => word.push_literal('$'),
+                }
+                in_word = true;
+            }
+            c => {
+                word.push_literal(c);
+                in_word = true;
+            }
+        }
+    }
+    finish_word(&mut tokens, &mut word, &mut in_word);
+    Ok(tokens)
+}
+
+/// Ends the word being read, if there is one.
+fn finish_word(tokens: &mut Vec<Token>, word: &mut Word, in_word: &mut bool) {
+    if *in_word {
+        tokens.push(Token::Word(std::mem::take(word)));
+        *in_word = false;
+    }
+}
+
+fn read_single_quoted(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut text = String::new();
+    for c in chars.by_ref() {
+        if c == '\'' {
+            return Ok(text);
+        }
+        text.push(c);
+    }
+    Err(ParseError::UnterminatedQuote('\''))
+}
+
+fn read_double_quoted(chars: &mut Peekable<Chars>) -> Result<Vec<WordPart>, ParseError> {
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                if !text.is_empty() || parts.is_empty() {
+                    parts.push(WordPart::Literal(text));
+                }
+                return Ok(parts);
+            }
+            '$' => match read_dollar(chars)? {
+                Some(part) => {
+                    if !text.is_empty() {
+                        parts.push(WordPart::Literal(std::mem::take(&mut text)));
+                    }
+                    parts.push(part);
+                }
+                None => text.push('$'),
+            },
+            '`' => {
+                if !text.is_empty() {
+                    parts.push(WordPart::Literal(std::mem::take(&mut text)));
+                }
+                parts.push(WordPart::CommandSubstitution(read_backquoted(chars)?));
+            }
+            // Inside double quotes a backslash only escapes characters that
+            // are special there, otherwise it is kept as is.
+            '\\' => match chars.next() {
+                Some('\n') => (),
+                Some(escaped @ ('"' | '\\' | '$' | '`')) => text.push(escaped),
+                Some(other) => {
+                    text.push('\\');
+                    text.push(other);
+                }
+                None => break,
+            },
+            c => text.push(c),
+        }
+    }
+    Err(ParseError::UnterminatedQuote('"'))
+}
+
+/// Reads the command of `$(...)` after the opening parenthesis, up to the
+/// matching closing one. Parentheses inside quotes don't count.
+fn read_command_substitution(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut command = String::new();
+    let mut depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            ')' if depth == 0 => return Ok(command),
+            ')' => depth -= 1,
+            '(' => depth += 1,
+            '\\' => {
+                command.push(c);
+                match chars.next() {
+                    Some(escaped) => command.push(escaped),
+                    None => break,
+                }
+                continue;
+            }
+            '\'' | '"' => {
+                command.push(c);
+                let mut escaped = false;
+                for quoted in chars.by_ref() {
+                    command.push(quoted);
+                    if quoted == c && !escaped {
+                        break;
+                    }
+                    escaped = c == '"' && quoted == '\\' && !escaped;
+                }
+                continue;
+            }
+            _ => (),
+        }
+        command.push(c);
+    }
+    Err(ParseError::UnterminatedSubstitution)
+}
+
+/// Reads the command of `` `...` `` after the opening backquote. A backslash
+/// in front of `$`, `` ` `` or `\` is removed, others are kept.
+fn read_backquoted(chars: &mut Peekable<Chars>) -> Result<String, ParseError> {
+    let mut command = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Ok(command),
+            '\\' => match chars.next() {
+                Some(escaped @ ('$' | '`' | '\\')) => command.push(escaped),
+                Some(other) => {
+                    command.push('\\');
+                    command.push(other);
+                }
+                None => break,
+            },
+            c => command.push(c),
+        }
+    }
+    Err(ParseError::UnterminatedQuote('`'))
+}
+
+/// Parameters with a one character name, like `$?` or `$1`.
+fn is_special_parameter(c: char) -> bool {
+    c.is_ascii_digit() || "?$#@*!".contains(c)
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Reads what follows a `$`. Gives `None` when the `$` is just a character.
+fn read_dollar(chars: &mut Peekable<Chars>) -> Result<Option<WordPart>, ParseError> {
+    match chars.peek() {
+        Some('{') => {
+            chars.next();
+            Ok(Some(WordPart::Parameter(read_braced_parameter(chars)?)))
+        }
+        Some('(') => {
+            chars.next();
+            Ok(Some(WordPart::CommandSubstitution(read_command_substitution(chars)?)))
+        }
+        Some(&c) if is_special_parameter(c) => {
+            chars.next();
+            Ok(Some(WordPart::Parameter(Parameter::simple(c.to_string()))))
+        }
+        Some(&c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+                name.push(c);
+            }
+            Ok(Some(WordPart::Parameter(Parameter::simple(name))))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reads `${...}` after the opening brace.
+fn read_braced_parameter(chars: &mut Peekable<Chars>) -> Result<Parameter, ParseError> {
+    // `${#}` is the `#` parameter, `${#name}` the length of `name`.
+    let length = chars.peek() == Some(&'#') && {
+        let mut lookahead = chars.clone();
+        lookahead.next();
+        lookahead.peek().is_some_and(|&c| c != '}')
+    };
+    if length {
+        chars.next();
+    }
+
+    let mut name = String::new();
+    match chars.peek() {
+        // `${10}` and above, positional parameters of several digits.
+        Some(c) if c.is_ascii_digit() => {
+            while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                name.push(c);
+            }
+        }
+        Some(&c) if is_special_parameter(c) => {
+            name.push(c);
+            chars.next();
+        }
+        _ => {
+            while let Some(c) = chars.next_if(|&c| is_name_char(c)) {
+                name.push(c);
+            }
+        }
+    }
+    if name.is_empty() {
+        return Err(bad_substitution(&name, chars));
+    }
+    if length {
+        return match chars.next() {
+            Some('}') => Ok(Parameter { name, operation: Some(ParameterOp::Length) }),
+            Some(_) => Err(bad_substitution(&name, chars)),
+            None => Err(ParseError::UnterminatedParameter),
+        };
+    }
+
+    let operation = match chars.next() {
+        Some('}') => return Ok(Parameter::simple(name)),
+        Some(':') => match chars.next() {
+            Some(operator @ ('-' | '=' | '?' | '+')) => with_operand(operator, true, read_operand(chars)?),
+            Some(_) => return Err(bad_substitution(&name, chars)),
+            None => return Err(ParseError::UnterminatedParameter),
+        },
+        Some(operator @ ('-' | '=' | '?' | '+')) => with_operand(operator, false, read_operand(chars)?),
+        Some('#') => {
+            let longest = chars.next_if_eq(&'#').is_some();
+            ParameterOp::RemovePrefix { pattern: read_operand(chars)?, longest }
+        }
+        Some('%') => {
+            let longest = chars.next_if_eq(&'%').is_some();
+            ParameterOp::RemoveSuffix { pattern: read_operand(chars)?, longest }
+        }
+        Some(_) => return Err(bad_substitution(&name, chars)),
+        None => return Err(ParseError::UnterminatedParameter),
+    };
+    Ok(Parameter { name, operation: Some(operation) })
+}
+
+fn with_operand(operator: char, colon: bool, word: Word) -> ParameterOp {
+    match operator {
+        '-' => ParameterOp::Default { word, colon },
+        '=' => ParameterOp::Assign { word, colon },
+        '?' => ParameterOp::Error { word, colon },
+        _ => ParameterOp::Alternative { word, colon },
+    }
+}
+
+/// Skips the rest of a malformed `${...}` and describes it.
+fn bad_substitution(name: &str, chars: &mut Peekable<Chars>) -> ParseError {
+    let mut text = format!("${{{name}");
+    for c in chars.by_ref() {
+        text.push(c);
+        if c == '}' {
+            break;
+        }
+    }
+    ParseError::BadSubstitution(text)
+}
+
+/// Reads the word of a `${...}` operation, up to the closing brace.
+fn read_operand(chars: &mut Peekable<Chars>) -> Result<Word, ParseError> {
+    let mut word = Word::default();
+    while let Some(c) = chars.next() {
+        match c {
+            '}' => return Ok(word),
+            '\\' => match chars.next() {
+                Some(escaped) => word.parts.push(WordPart::Quoted(escaped.to_string())),
+                None => break,
+            },
+            '\'' => word.parts.push(WordPart::Quoted(read_single_quoted(chars)?)),
+            '"' => word.parts.push(WordPart::DoubleQuoted(read_double_quoted(chars)?)),
+            '$' => match read_dollar(chars)? {
+                Some(part) => word.parts.push(part),
+                None => word.push_literal('$'),
+            },
+            c => word.push_literal(c),
+        }
+    }
+    Err(ParseError::UnterminatedParameter)
+}
@@ -2,49 +2,267 @@ use colored::Colorize;
 use chrono::prelude::*;
 use filetime::{FileTime, set_file_atime, set_file_mtime};
 use std::{
+    collections::BTreeMap,
     time::{Duration, UNIX_EPOCH},
-    collections::HashMap,
-    io::{self, Write},
+    io::{self, IsTerminal, Read, Write},
+    process,
     str::FromStr,
     env,
     path::{Path, PathBuf},
     fs::{self, File, Metadata, Permissions, DirEntry},
+    sync::Arc,
 };
 
-mod username;
+#[macro_use]
+mod streams;
+mod brace;
+mod complete;
+mod editor;
+mod exec;
+mod expand;
+mod git;
+mod glob;
+mod history;
+mod history_expansion;
+mod input;
+mod jobs;
+mod lexer;
+mod options;
+mod parser;
+mod pattern;
+mod platform;
+mod prompt;
+mod script;
+mod shell;
+mod variables;
+
+use complete::{CompletionSpec, ShellCompleter};
+use editor::Editor;
+use history::{History, HistoryEntry};
+use jobs::JobState;
+use options::{OptionSpec, ParsedOptions};
+use script::RcFile;
+use shell::{Flow, Shell, ShellOptions};
+use variables::Variables;
+use streams::IoStreams;
 
 const SUCCESS_CODE: i32 = 0;
 const ERROR_CODE  : i32 = 1;
 const EXIT_CODE   : i32 = -1;
-const CRLF        : &str = "\r\n";
-const HELP_FILE_INFO_NAME: &str = "C:\\code\\rust\\shell\\help.txt";
+const SYNTAX_ERROR_CODE: i32 = 2;
+/// Clears the terminal and moves the cursor to the top left corner.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[1;1H";
+/// Text printed by `help`, built into the shell.
+const HELP_TEXT: &str = include_str!("../help.txt");
+
+
+/// How the shell was started.
+enum Mode {
+    /// Reading commands typed at a terminal.
+    Interactive,
+    /// `shell -c COMMANDS`
+    Command(String),
+    /// `shell SCRIPT`
+    Script(PathBuf),
+    /// Commands piped into standard input.
+    Stdin,
+}
+
+struct Arguments {
+    mode: Mode,
+    /// Started with `-l`, or by `login` with a `-` in front of its name.
+    login: bool,
+    rc_file: RcFile,
+    /// What follows the script or the `-c` commands: `$1`, `$2`... For `-c`
+    /// the first one is `$0`.
+    parameters: Vec<String>,
+}
 
+fn parse_arguments() -> Result<Arguments, String> {
+    let mut login = env::args().next().is_some_and(|name| name.starts_with('-'));
+    let mut rc_file = RcFile::Default;
+    let mut args = env::args().skip(1).peekable();
+    while let Some(flag) = args.next_if(|arg| matches!(arg.as_str(), "-l" | "--login" | "--norc" | "--rcfile")) {
+        match flag.as_str() {
+            "--norc" => rc_file = RcFile::Skipped,
+            "--rcfile" => match args.next() {
+                Some(file) => rc_file = RcFile::File(PathBuf::from(file)),
+                None => return Err(String::from("--rcfile: option requires an argument")),
+            },
+            _ => login = true,
+        }
+    }
+    let mode = match args.next() {
+        Some(flag) if flag == "-c" => match args.next() {
+            Some(commands) => Mode::Command(commands),
+            None => return Err(String::from("-c: option requires an argument")),
+        },
+        Some(flag) if flag.starts_with('-') && flag != "-" => return Err(format!("{flag}: unknown option")),
+        Some(script) if script != "-" => Mode::Script(PathBuf::from(script)),
+        _ if io::stdin().is_terminal() => Mode::Interactive,
+        _ => Mode::Stdin,
+    };
+    Ok(Arguments { mode, login, rc_file, parameters: args.collect() })
+}
 
 fn main() {
-    let mut commands_vector: Vec<String> = Vec::new();
-    let clear_escape_sequence = "\x1b[2J\x1b[1;1H";
-    print!("{}", clear_escape_sequence);
-    let prompt_char = '🚀';
+    let arguments = match parse_arguments() {
+        Ok(arguments) => arguments,
+        Err(err) => {
+            eprintln!("shell: {err}");
+            eprintln!("Usage: shell [-l] [--norc | --rcfile FILE] [-c COMMANDS [NAME] | SCRIPT] [ARGUMENT]...");
+            process::exit(SYNTAX_ERROR_CODE);
+        }
+    };
+    let mut shell = Shell::new();
+    let mut parameters = arguments.parameters;
+    if matches!(arguments.mode, Mode::Command(_)) && !parameters.is_empty() {
+        shell.name = parameters.remove(0);
+    }
+    shell.positional = parameters;
+    let mut io = IoStreams::inherit();
+    let mut status = SUCCESS_CODE;
+    if arguments.login {
+        if let Some(home) = shell.home_dir() {
+            status = script::run_startup_file(&home.join(script::PROFILE_FILE), false, &mut shell);
+        }
+    }
+    let status = match arguments.mode {
+        _ if status == EXIT_CODE => status,
+        Mode::Interactive => {
+            let rc_file = arguments.rc_file.startup_file(&shell);
+            run_interactive(&mut shell, rc_file);
+            EXIT_CODE
+        }
+        Mode::Command(commands) => script::run_string(&commands, "-c", &mut shell, &mut io),
+        Mode::Script(path) => {
+            shell.name = path.to_string_lossy().into_owned();
+            script::run_file(&path, &mut shell, &mut io)
+        }
+        Mode::Stdin => script::run_stdin(&mut shell, &mut io),
+    };
+    // After `exit` the status is the one it recorded.
+    let status = if status == EXIT_CODE { shell.last_status } else { status };
+    let _ = io::stdout().flush();
+    process::exit(status);
+}
 
-    let username = username::get_username();
+/// Reads and runs commands typed at the terminal until `exit` or the end of
+/// the input, after the startup file if any.
+fn run_interactive(shell: &mut Shell, rc_file: Option<(PathBuf, bool)>) {
+    print!("{CLEAR_SCREEN}");
+    let mut editor = Editor::new();
+    shell.interactive = true;
+    shell.options.histexpand = true;
+    shell.job_control = platform::init_job_control();
+    platform::watch_interrupts();
+    if let Some((path, required)) = rc_file {
+        if script::run_startup_file(&path, required, shell) == EXIT_CODE {
+            return;
+        }
+    }
+    // After the startup file, which may set HISTFILE or HISTSIZE.
+    if let Some(path) = history::default_file(&shell.variables) {
+        if let Err(err) = Arc::make_mut(&mut shell.history).load(path.clone(), &shell.variables) {
+            eprintln!("history: {}: {err}", path.display());
+        }
+    }
+    let mut ignored_eof = 0;
     loop {
-        let current_dir = env::current_dir().unwrap();
-        print!("\x1b[94m{username}\x1b[0m{}:\x1b[94m{}\x1b[0m ", prompt_char, current_dir.to_str().unwrap());
-
-        io::stdout().flush().unwrap();
-        let mut command_input = String::new();
-        io::stdin().read_line(&mut command_input).expect("Failed to read in command");
-        if command_input == CRLF {
+        // The terminal showed `^C` where the command was stopped.
+        if platform::take_interrupt() {
+            println!();
+        }
+        for notice in shell.jobs.take_notices() {
+            eprintln!("{notice}");
+        }
+        let prompt = prompt::render(shell.variables.get("PS1").unwrap_or(prompt::DEFAULT_PS1), shell);
+        editor.right_prompt = prompt::render(shell.variables.get("RPS1").unwrap_or_default(), shell);
+        editor.fuzzy_search = shell.options.fuzzysearch;
+        let mut command_input = match editor.read_line(&prompt, shell.history.entries(), &ShellCompleter::new(shell)) {
+            Ok(Some(line)) => line,
+            Ok(None) if shell.options.ignoreeof && ignored_eof < input::MAX_IGNORED_EOF => {
+                ignored_eof += 1;
+                println!();
+                println!("Use \"exit\" to leave the shell.");
+                continue;
+            }
+            Ok(None) => {
+                println!();
+                break;
+            }
+            // Ctrl-C cancelled the line.
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                shell.last_status = platform::INTERRUPTED_STATUS;
+                continue;
+            }
+            Err(err) if err.kind() == io::ErrorKind::InvalidData => {
+                eprintln!("Could not read the command - {err}");
+                continue;
+            }
+            Err(err) => {
+                eprintln!("Could not read the command - {err}");
+                shell.last_status = ERROR_CODE;
+                break;
+            }
+        };
+        ignored_eof = 0;
+        // An unfinished command goes on with more lines, read with `PS2`. The
+        // parse error is reported if the input ends first.
+        editor.right_prompt.clear();
+        let mut cancelled = false;
+        while parser::parse(&command_input).is_err_and(|err| err.is_incomplete()) {
+            let prompt = prompt::render(shell.variables.get("PS2").unwrap_or(prompt::DEFAULT_PS2), shell);
+            match editor.read_line(&prompt, shell.history.entries(), &ShellCompleter::new(shell)) {
+                Ok(Some(line)) => {
+                    command_input.push('\n');
+                    command_input.push_str(&line);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {
+                    cancelled = true;
+                    break;
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+        if cancelled {
+            shell.last_status = platform::INTERRUPTED_STATUS;
             continue;
         }
+        if command_input.trim().is_empty() {
+            continue;
+        }
+        let command_input = match shell.options.histexpand {
+            true => match history_expansion::expand(&command_input, shell.history.entries()) {
+                Ok(Some(expanded)) => {
+                    println!("{expanded}");
+                    expanded
+                }
+                Ok(None) => command_input,
+                Err(err) => {
+                    eprintln!("{err}");
+                    shell.last_status = ERROR_CODE;
+                    continue;
+                }
+            },
+            false => command_input,
+        };
+        let recorded = Arc::make_mut(&mut shell.history).add(&command_input, &shell.variables);
 
-        let mut command_input_clone = command_input.clone();
-        command_input_clone.pop();
-        command_input_clone.pop();
-        commands_vector.push(command_input_clone);
-
-        let command = tokenize_command(command_input);
-        let return_code = process_command(command, &commands_vector);
+        let return_code = match parser::parse_with_aliases(&command_input, &shell.aliases) {
+            Ok(Some(list)) => exec::run_list(&list, shell, &IoStreams::inherit()),
+            Ok(None) => SUCCESS_CODE,
+            Err(err) => {
+                eprintln!("Parse error: {err}");
+                shell.last_status = SYNTAX_ERROR_CODE;
+                SYNTAX_ERROR_CODE
+            }
+        };
+        if recorded {
+            if let Err(err) = Arc::make_mut(&mut shell.history).finish(shell.last_status) {
+                eprintln!("history: {err}");
+            }
+        }
         if return_code == EXIT_CODE {
             break;
         }
@@ -55,8 +273,6 @@ fn main() {
 struct Command {
     keyword: String,
     arguments: Vec<String>,
-    options: Vec<String>,
-    plain_command_input: String,
 }
 
 enum BuiltinCommand {
@@ -74,6 +290,53 @@ enum BuiltinCommand {
     Mkdir,
     Cat,
     Help,
+    Set,
+    Export,
+    Unset,
+    Env,
+    Source,
+    Complete,
+    Alias,
+    Unalias,
+    Break,
+    Continue,
+    Shift,
+    Local,
+    Return,
+    Type,
+    Jobs,
+    Fg,
+    Bg,
+    Wait,
+    Disown,
+}
+
+impl BuiltinCommand {
+    /// Every name a builtin can be called by.
+    const NAMES: &'static [&'static str] = &[
+        "echo", "history", "cd", "pwd", "ls", "clear", "exit", "cp", "rm", "mv", "touch", "mkdir", "cat", "help",
+        "set", "export", "unset", "env", "source", ".", "complete", "alias", "unalias",
+        "break", "continue", "shift", "local", "return", "type", "jobs", "fg", "bg", "wait", "disown",
+    ];
+
+    /// Options understood besides `--help`, offered by completion.
+    fn options(&self) -> &'static [OptionSpec] {
+        match self {
+            BuiltinCommand::History => HISTORY_OPTIONS,
+            BuiltinCommand::Ls => LS_OPTIONS,
+            BuiltinCommand::Rm => RM_OPTIONS,
+            BuiltinCommand::Touch => TOUCH_OPTIONS,
+            BuiltinCommand::Export => EXPORT_OPTIONS,
+            BuiltinCommand::Complete => COMPLETE_OPTIONS,
+            BuiltinCommand::Alias => ALIAS_OPTIONS,
+            BuiltinCommand::Unalias => UNALIAS_OPTIONS,
+            BuiltinCommand::Unset => UNSET_OPTIONS,
+            BuiltinCommand::Type => TYPE_OPTIONS,
+            BuiltinCommand::Jobs => JOBS_OPTIONS,
+            BuiltinCommand::Disown => DISOWN_OPTIONS,
+            _ => &[],
+        }
+    }
 }
 
 impl FromStr for BuiltinCommand {
@@ -95,170 +358,372 @@ impl FromStr for BuiltinCommand {
             "mkdir" => Ok(BuiltinCommand::Mkdir),
             "cat" => Ok(BuiltinCommand::Cat),
             "help" => Ok(BuiltinCommand::Help),
+            "set" => Ok(BuiltinCommand::Set),
+            "export" => Ok(BuiltinCommand::Export),
+            "unset" => Ok(BuiltinCommand::Unset),
+            "env" => Ok(BuiltinCommand::Env),
+            "source" | "." => Ok(BuiltinCommand::Source),
+            "complete" => Ok(BuiltinCommand::Complete),
+            "alias" => Ok(BuiltinCommand::Alias),
+            "unalias" => Ok(BuiltinCommand::Unalias),
+            "break" => Ok(BuiltinCommand::Break),
+            "continue" => Ok(BuiltinCommand::Continue),
+            "shift" => Ok(BuiltinCommand::Shift),
+            "local" => Ok(BuiltinCommand::Local),
+            "return" => Ok(BuiltinCommand::Return),
+            "type" => Ok(BuiltinCommand::Type),
+            "jobs" => Ok(BuiltinCommand::Jobs),
+            "fg" => Ok(BuiltinCommand::Fg),
+            "bg" => Ok(BuiltinCommand::Bg),
+            "wait" => Ok(BuiltinCommand::Wait),
+            "disown" => Ok(BuiltinCommand::Disown),
             _ => Err(()),
         }
     }
 }
 
-fn tokenize_command(command: String) -> Command {
-    let mut tokens: Vec<String> = command.split_whitespace().map(|s| s.to_string()).collect();
-    let keyword = tokens.remove(0);
-    let plain_command_input = tokens.join(" ");
-    let arguments: Vec<String> = tokens.clone().into_iter().filter(|arg| !arg.starts_with("-")).collect();
-    let options: Vec<String> = tokens.into_iter().filter(|arg| arg.starts_with("-")).collect();
-   
-    Command { 
-        keyword,
-        arguments,
-        options,
-        plain_command_input,
+impl Command {
+    /// Splits expanded words into the keyword and its arguments.
+    fn new(mut words: Vec<String>) -> Command {
+        let keyword = words.remove(0);
+        Command {
+            keyword,
+            arguments: words,
+        }
     }
 }
 
-fn process_command(mut command: Command, commands_vector: &Vec<String>) -> i32 {
-    match BuiltinCommand::from_str(&command.keyword) {
-        Ok(BuiltinCommand::Echo) => builtin_echo(command),
-        Ok(BuiltinCommand::History) => builtin_history(command, commands_vector),
-        Ok(BuiltinCommand::Cd) => builtin_cd(command),
-        Ok(BuiltinCommand::Pwd) => builtin_pwd(command),
-        Ok(BuiltinCommand::Ls) => builtin_ls(command),
-        Ok(BuiltinCommand::Clear) => builtin_clear(command),
-        Ok(BuiltinCommand::Cp) => builtin_cp(command),
-        Ok(BuiltinCommand::Rm) => builtin_rm(command),
-        Ok(BuiltinCommand::Mv) => builtin_mv(command),
-        Ok(BuiltinCommand::Touch) => builtin_touch(command),
-        Ok(BuiltinCommand::Mkdir) => builtin_mkdir(command),
-        Ok(BuiltinCommand::Cat) => builtin_cat(command),
-        Ok(BuiltinCommand::Help) => builtin_help(command),
-        Ok(BuiltinCommand::Exit) => EXIT_CODE,
-        Err(()) => {
-            let args = command.arguments.clone();
-            if !command.keyword.contains(&String::from(".exe")) {
-                command.keyword.push_str(".exe");
-            }
-            match find_executable(command) {
-                Ok(path) => {
-                    let mut process = std::process::Command::new(path);
-                    process.args(args);
-                    if let Ok(mut child) = process.spawn() {
-                        child.wait().expect("Command wasn't running");
-                        SUCCESS_CODE
-                    } else {
-                        println!("Command didn't start");
-                        ERROR_CODE
-                    }
-                }
-                Err(_) => {
-                    println!("Command not found");
-                    ERROR_CODE
-                }
-            }
+/// Parses the options of a builtin, reporting unknown or malformed ones.
+fn parse_options(command: &Command, specs: &[OptionSpec], io: &mut IoStreams) -> Option<ParsedOptions> {
+    match options::parse(specs, &command.arguments) {
+        Ok(options) => Some(options),
+        Err(err) => {
+            outln!(io.stderr, "{}: {err}", command.keyword);
+            None
         }
     }
 }
 
-fn find_executable(command: Command) -> Result<PathBuf, std::io::Error> {
-    fn search(keyword: &str, dir: &Path) -> Result<(), std::io::Error> {
-        for entry in fs::read_dir(dir)? {
-            if let Ok(entry) = entry {
-                if let Ok(metadata) = entry.metadata() {
-                    if metadata.is_file() || metadata.is_symlink() {
-                        if let Some(filename) = entry.path().file_name() {
-                            if filename == keyword {
-                                if metadata.is_symlink() {
-                                    println!("It's a symbolic link");
-                                    return Err(std::io::ErrorKind::InvalidData.into());
-                                }
-                                return Ok(());
-                            }
-                        }
-                    }
-                }
+fn process_command(command: Command, shell: &mut Shell, mut io: IoStreams) -> i32 {
+    match BuiltinCommand::from_str(&command.keyword) {
+        Ok(BuiltinCommand::Echo) => builtin_echo(command, &mut io),
+        Ok(BuiltinCommand::History) => builtin_history(command, Arc::make_mut(&mut shell.history), &shell.variables, &mut io),
+        Ok(BuiltinCommand::Cd) => builtin_cd(command, shell, &mut io),
+        Ok(BuiltinCommand::Pwd) => builtin_pwd(command, shell, &mut io),
+        Ok(BuiltinCommand::Ls) => builtin_ls(command, shell, &mut io),
+        Ok(BuiltinCommand::Clear) => builtin_clear(command, &mut io),
+        Ok(BuiltinCommand::Cp) => builtin_cp(command, shell, &mut io),
+        Ok(BuiltinCommand::Rm) => builtin_rm(command, shell, &mut io),
+        Ok(BuiltinCommand::Mv) => builtin_mv(command, shell, &mut io),
+        Ok(BuiltinCommand::Touch) => builtin_touch(command, shell, &mut io),
+        Ok(BuiltinCommand::Mkdir) => builtin_mkdir(command, shell, &mut io),
+        Ok(BuiltinCommand::Cat) => builtin_cat(command, shell, &mut io),
+        Ok(BuiltinCommand::Help) => builtin_help(command, &mut io),
+        Ok(BuiltinCommand::Set) => builtin_set(command, shell, &mut io),
+        Ok(BuiltinCommand::Export) => builtin_export(command, shell, &mut io),
+        Ok(BuiltinCommand::Unset) => builtin_unset(command, shell, &mut io),
+        Ok(BuiltinCommand::Env) => builtin_env(command, shell, io),
+        Ok(BuiltinCommand::Source) => builtin_source(command, shell, &mut io),
+        Ok(BuiltinCommand::Exit) => builtin_exit(command, shell, &mut io),
+        Ok(BuiltinCommand::Complete) => builtin_complete(command, shell, &mut io),
+        Ok(BuiltinCommand::Alias) => builtin_alias(command, Arc::make_mut(&mut shell.aliases), &mut io),
+        Ok(BuiltinCommand::Unalias) => builtin_unalias(command, Arc::make_mut(&mut shell.aliases), &mut io),
+        Ok(BuiltinCommand::Break) | Ok(BuiltinCommand::Continue) => builtin_loop_control(command, shell, &mut io),
+        Ok(BuiltinCommand::Shift) => builtin_shift(command, &mut shell.positional, &mut io),
+        Ok(BuiltinCommand::Local) => builtin_local(command, shell, &mut io),
+        Ok(BuiltinCommand::Return) => builtin_return(command, shell, &mut io),
+        Ok(BuiltinCommand::Type) => builtin_type(command, shell, &mut io),
+        Ok(BuiltinCommand::Jobs) => builtin_jobs(command, shell, &mut io),
+        Ok(BuiltinCommand::Fg) => builtin_fg(command, shell, &mut io),
+        Ok(BuiltinCommand::Bg) => builtin_bg(command, shell, &mut io),
+        Ok(BuiltinCommand::Wait) => builtin_wait(command, shell, &mut io),
+        Ok(BuiltinCommand::Disown) => builtin_disown(command, shell, &mut io),
+        Err(()) => match shell.functions.get(&command.keyword) {
+            Some(function) => {
+                let definition = Arc::clone(&function.definition);
+                exec::call_function(&definition, command.arguments, shell, io)
             }
-        }
-        Err(std::io::ErrorKind::NotFound.into())
+            None => exec::run_external(command, shell, io),
+        },
     }
+}
 
-    if let Ok(mut dir) = env::current_dir() {
-        if let Ok(()) = search(&command.keyword, &dir) {
-            let exec_name = command.keyword;
-            dir.push(exec_name);
-            return Ok(dir);
-        }
+fn find_executable(command: &Command, shell: &Shell) -> Result<PathBuf, std::io::Error> {
+    if command.keyword.contains(std::path::MAIN_SEPARATOR) || command.keyword.contains('/') {
+        let path = shell.resolve(&command.keyword);
+        platform::check_executable(&path)?;
+        return Ok(path);
     }
 
-    let vars: HashMap<String, String> = env::vars().into_iter().collect();
-    let values: &Vec<&str> = &vars["Path"].split(";").collect();
+    if platform::SEARCH_CURRENT_DIR {
+        if let Ok(dir) = shell.current_dir() {
+            let path = dir.join(&command.keyword);
+            if platform::check_executable(&path).is_ok() {
+                return Ok(path);
+            }
+        }
+    }
 
-    for entry in values {
-        if let Ok(()) = search(&command.keyword, Path::new(entry)) {
-            let mut path = PathBuf::from(entry);
-            let exec_name = command.keyword;
-            path.push(exec_name);
+    let search_path = shell.variables.get(platform::PATH_VARIABLE).map(platform::split_path).unwrap_or_default();
+    for dir in search_path {
+        let path = shell.resolve(dir.join(&command.keyword));
+        if platform::check_executable(&path).is_ok() {
             return Ok(path);
         }
     }
-    return Err(std::io::ErrorKind::NotFound.into());
+    Err(std::io::ErrorKind::NotFound.into())
 }
 
-fn builtin_echo(command: Command) -> i32 {
-    println!("{}", command.plain_command_input);
+fn builtin_echo(command: Command, io: &mut IoStreams) -> i32 {
+    outln!(io.stdout, "{}", command.arguments.join(" "));
     SUCCESS_CODE
 }
 
-fn builtin_history(command: Command, commands_vector: &Vec<String>) -> i32 {
-    if command.options.contains(&String::from("--help")) {
-        println!("history:\n\
+const HISTORY_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("verbose", Some('v'), Some("verbose")),
+    OptionSpec::flag("clear", Some('c'), None),
+    OptionSpec::value("delete", Some('d'), None),
+    OptionSpec::flag("write", Some('w'), None),
+    OptionSpec::flag("read", Some('r'), None),
+    OptionSpec::flag("append", Some('a'), None),
+    OptionSpec::value("grep", None, Some("grep")),
+    OptionSpec::value("format", None, Some("format")),
+];
+
+fn builtin_history(command: Command, history: &mut History, variables: &Variables, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, HISTORY_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "history:\n\
                   Display a history of entered commands.\n\
-                  The list of commands contains their index number from 1.");
+                  The list of commands contains their index number from 1.\n\
+                  history [N] - show all commands, or the last N;\n\
+                  -v, --verbose - also show when, where and with which exit status each command ran;\n\
+                  --grep PATTERN - show the commands matching PATTERN, wildcards can be used;\n\
+                  --format json - show the commands as a JSON array;\n\
+                  -c - clear the history;\n\
+                  -d N, -d A-B - delete command N, negative counting from the end, or commands A to B;\n\
+                  -w [FILE] - write the history to FILE, by default the history file;\n\
+                  -r [FILE] - add the commands of FILE to the history;\n\
+                  -a [FILE] - append the commands of this session to FILE.");
         return SUCCESS_CODE;
     }
-    for i in 0..commands_vector.len() {
-        println!("{} {}", i + 1, commands_vector[i]);
+    if options.is_set("clear") {
+        history.clear();
+    }
+    if let Some(positions) = options.value("delete") {
+        let count = history.entries().len();
+        let indexes = match positions.split_once('-').filter(|(start, _)| !start.is_empty()) {
+            Some((start, end)) => history_index(start, count).zip(history_index(end, count)).map(|(start, end)| start..=end),
+            None => history_index(positions, count).map(|index| index..=index),
+        };
+        if !indexes.is_some_and(|indexes| history.delete(indexes)) {
+            outln!(io.stderr, "history: {positions}: history position out of range");
+            return ERROR_CODE;
+        }
+    }
+    let file_action = ["write", "read", "append"].into_iter().find(|action| options.is_set(action));
+    if let Some(action) = file_action {
+        let path = match options.arguments.first() {
+            Some(path) => PathBuf::from(path),
+            None => match history.file().map(Path::to_path_buf).or_else(|| history::default_file(variables)) {
+                Some(path) => path,
+                None => {
+                    outln!(io.stderr, "history: no history file");
+                    return ERROR_CODE;
+                }
+            },
+        };
+        let result = match action {
+            "write" => history.write_file(&path),
+            "read" => history.read_file(&path, variables),
+            _ => history.append_file(&path),
+        };
+        if let Err(err) = result {
+            outln!(io.stderr, "history: {}: {err}", path.display());
+            return ERROR_CODE;
+        }
+        return SUCCESS_CODE;
+    }
+    if options.is_set("clear") || options.is_set("delete") {
+        return SUCCESS_CODE;
+    }
+
+    let json = match options.value("format") {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(format) => {
+            outln!(io.stderr, "history: {format}: unknown format, use text or json");
+            return ERROR_CODE;
+        }
+    };
+    let last = match options.arguments.first().map(|count| count.parse::<usize>()) {
+        None => usize::MAX,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            outln!(io.stderr, "history: {}: numeric argument required", options.arguments[0]);
+            return ERROR_CODE;
+        }
+    };
+    let pattern = options.value("grep").map(|pattern| format!("*{pattern}*"));
+    let entries = history.entries();
+    let shown: Vec<(usize, &HistoryEntry)> = entries
+        .iter()
+        .enumerate()
+        .skip(entries.len().saturating_sub(last))
+        .filter(|(_, entry)| pattern.as_ref().is_none_or(|pattern| pattern::matches(pattern, &entry.line)))
+        .collect();
+    if json {
+        print_history_json(&shown, io);
+        return SUCCESS_CODE;
+    }
+    for (i, entry) in shown {
+        if !options.is_set("verbose") {
+            outln!(io.stdout, "{} {}", i + 1, entry.line);
+            continue;
+        }
+        let time = entry
+            .time
+            .and_then(|time| Local.timestamp_opt(time, 0).single())
+            .map_or_else(|| String::from("-"), |time| time.format("%Y-%m-%d %H:%M:%S").to_string());
+        let status = entry.status.map_or_else(|| String::from("-"), |status| status.to_string());
+        let cwd = entry.cwd.as_deref().unwrap_or("-");
+        outln!(io.stdout, "{} {time} {status:>3} {cwd} {}", i + 1, entry.line);
     }
     SUCCESS_CODE
 }
 
-fn builtin_pwd(command: Command) -> i32 {
-    if command.options.contains(&String::from("--help")) {
-        println!("pwd:\n\
+/// Index of the entry numbered `position` by `history`, a negative position
+/// counting from the end.
+fn history_index(position: &str, count: usize) -> Option<usize> {
+    let position: i64 = position.parse().ok()?;
+    let index = match position {
+        1.. => position - 1,
+        ..0 => count as i64 + position,
+        0 => return None,
+    };
+    usize::try_from(index).ok().filter(|&index| index < count)
+}
+
+/// Prints the entries as a JSON array with an object per line. Details not
+/// known are `null`.
+fn print_history_json(entries: &[(usize, &HistoryEntry)], io: &mut IoStreams) {
+    outln!(io.stdout, "[");
+    for (position, (i, entry)) in entries.iter().enumerate() {
+        let time = entry
+            .time
+            .and_then(|time| Local.timestamp_opt(time, 0).single())
+            .map_or_else(|| String::from("null"), |time| json_string(&time.to_rfc3339()));
+        let status = entry.status.map_or_else(|| String::from("null"), |status| status.to_string());
+        let cwd = entry.cwd.as_deref().map_or_else(|| String::from("null"), json_string);
+        let separator = if position + 1 < entries.len() { "," } else { "" };
+        outln!(io.stdout, "  {{\"number\": {}, \"command\": {}, \"time\": {time}, \"cwd\": {cwd}, \"status\": {status}}}{separator}",
+               i + 1, json_string(&entry.line));
+    }
+    outln!(io.stdout, "]");
+}
+
+fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if u32::from(c) < 0x20 => quoted.push_str(&format!("\\u{:04x}", u32::from(c))),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn builtin_pwd(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "pwd:\n\
                      Display you currrent/working directory");
         return SUCCESS_CODE;
     }
-    println!("{}", env::current_dir().unwrap().to_str().unwrap());
+    outln!(io.stdout, "{}", shell.current_dir().unwrap().to_str().unwrap());
     SUCCESS_CODE
 }
 
-fn builtin_cd(command: Command) -> i32 {
-    if command.arguments.len() + command.options.len() > 1 {
-        println!("Too many arguments");
+fn builtin_cd(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.arguments.len() > 1 {
+        outln!(io.stderr, "Too many arguments");
         return ERROR_CODE;
     }
 
-    if command.options.contains(&String::from("--help")) {
-        println!("cd:\n\
+    if options.is_set("help") {
+        outln!(io.stdout, "cd:\n\
                   Change the current directory to user specified one.\n\
-                  If destination directory is not specified, the default will be 'HOMEDRIVE + HOMEPATH' shell variable");
+                  If destination directory is not specified, the default will be the user's home directory.\n\
+                  cd - goes back to the previous directory, kept in $OLDPWD");
         return SUCCESS_CODE;
     }
 
-    let env_vars: HashMap<String, String> = env::vars().collect();
-    let system_drive = &env_vars["HOMEDRIVE"];
-    let full_home_path = system_drive.to_owned() + &env_vars["HOMEPATH"];
- 
-    let path = if command.arguments.len() == 0 || command.arguments[0] == "~" { 
-        Path::new(&full_home_path) 
-    } else { 
-        Path::new(&command.arguments[0])
+    let path = match options.arguments.first().map(String::as_str) {
+        None => match shell.variables.get("HOME").map(PathBuf::from).or_else(platform::home_dir) {
+            Some(home_dir) => home_dir,
+            None => {
+                outln!(io.stderr, "Home directory is not set");
+                return ERROR_CODE;
+            }
+        },
+        Some("-") => match shell.variables.get("OLDPWD") {
+            Some(previous_dir) => {
+                outln!(io.stdout, "{previous_dir}");
+                PathBuf::from(previous_dir)
+            }
+            None => {
+                outln!(io.stderr, "cd: OLDPWD not set");
+                return ERROR_CODE;
+            }
+        },
+        Some(path) => PathBuf::from(path),
     };
-    if !path.exists() {
-        println!("This folder does not exist");
+    if !shell.resolve(&path).exists() {
+        outln!(io.stderr, "This folder does not exist");
         return ERROR_CODE;
     }
-    match env::set_current_dir(path) {
-        Ok(_) => SUCCESS_CODE,
+    let previous_dir = shell.current_dir();
+    let changed = match &shell.dir {
+        // A copy of the shell only changes its own idea of the directory,
+        // the one of the process belongs to the shell.
+        Some(dir) => match fs::canonicalize(dir.join(&path)) {
+            Ok(path) if path.is_dir() => {
+                shell.dir = Some(path);
+                Ok(())
+            }
+            Ok(_) => Err(io::ErrorKind::NotADirectory.into()),
+            Err(err) => Err(err),
+        },
+        None => env::set_current_dir(&path),
+    };
+    match changed {
+        Ok(_) => {
+            if let Ok(previous_dir) = previous_dir {
+                shell.variables.set("OLDPWD", &previous_dir.to_string_lossy());
+            }
+            if let Ok(current_dir) = shell.current_dir() {
+                shell.variables.set("PWD", &current_dir.to_string_lossy());
+            }
+            SUCCESS_CODE
+        }
         Err(err) => {
-            println!("Error - {err}");
+            outln!(io.stderr, "Error - {err}");
             ERROR_CODE
         },
     }
@@ -272,33 +737,43 @@ struct FileDisplayInfo {
     permissions: Permissions,
 }
 
-fn builtin_ls(command: Command) -> i32 { 
-    if command.options.contains(&String::from("--help")) {
-        println!("ls:\n\
+const LS_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("long", Some('l'), None),
+];
+
+fn builtin_ls(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, LS_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "ls:\n\
                   Print list of files in specified directory(default directory is current directory).\n\
                   -l        display folder contens as a list with size, last modified time, permissions");
         return SUCCESS_CODE;
     }
-    let mut dirs_list = command.arguments.clone();
+    let mut dirs_list = options.arguments.clone();
 
     let current_dir = String::from("./");
 
-    if dirs_list.len() == 0 {
+    if dirs_list.is_empty() {
         dirs_list.push(current_dir);
     }
 
-    let mut dir_files_map: HashMap<String, Vec<FileDisplayInfo>> = HashMap::new();
+    let mut dir_files_list: Vec<(String, Vec<FileDisplayInfo>)> = Vec::new();
+    let mut return_code = SUCCESS_CODE;
 
     for dir in dirs_list {
 
-        let paths = fs::read_dir(&dir);
+        let paths = fs::read_dir(shell.resolve(&dir));
         if let Err(err) = paths {
-            println!("Error: {err}");
+            outln!(io.stderr, "Error: {err}");
+            return_code = ERROR_CODE;
             continue;
         }
-        let paths: Vec<Result<DirEntry, std::io::Error>> = fs::read_dir(&dir).unwrap().collect();
+        let paths: Vec<Result<DirEntry, std::io::Error>> = fs::read_dir(shell.resolve(&dir)).unwrap().collect();
 
-        if paths.len() == 0 { 
+        if paths.is_empty() { 
             return ERROR_CODE;
         }
         let mut files = Vec::new();
@@ -331,13 +806,13 @@ fn builtin_ls(command: Command) -> i32 {
             }
         }
 
-        dir_files_map.insert(dir, files);
+        dir_files_list.push((dir, files));
     }
 
-    if command.options.contains(&String::from("-l")) {
-        for (dir_name, files) in dir_files_map {
-            if command.arguments.len() > 1 {
-                println!("{dir_name}:");
+    if options.is_set("long") {
+        for (dir_name, files) in dir_files_list {
+            if options.arguments.len() > 1 {
+                outln!(io.stdout, "{dir_name}:");
             }
             for file in &files {
                 let longest_filesize = files
@@ -369,14 +844,14 @@ fn builtin_ls(command: Command) -> i32 {
                 } else {
                     file_info_str.push_str(&file.filename);
                 }
-                println!("{}", file_info_str);
+                outln!(io.stdout, "{}", file_info_str);
             }                  
-            println!();
+            outln!(io.stdout);
         }
     } else {
-        for (dir_name, files) in dir_files_map {
-            if command.arguments.len() > 1 {
-                println!("{dir_name}:");
+        for (dir_name, files) in dir_files_list {
+            if options.arguments.len() > 1 {
+                outln!(io.stdout, "{dir_name}:");
             }
             for file in files {
                 let mut file_info_str = String::new();
@@ -390,133 +865,242 @@ fn builtin_ls(command: Command) -> i32 {
                 } else {
                     file_info_str.push_str(&file.filename);
                 }
-                println!("{}", file_info_str);
+                outln!(io.stdout, "{}", file_info_str);
             }
-            println!();
+            outln!(io.stdout);
         }
     }
-    SUCCESS_CODE
+    return_code
 }
 
-fn builtin_clear(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("clear:\n\
+fn builtin_clear(command: Command, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "clear:\n\
                   Clears the terminal screen.");
         return SUCCESS_CODE;
     }
-    print!("\x1b[2J\x1b[1;1H");
+    out!(io.stdout, "{CLEAR_SCREEN}");
     SUCCESS_CODE
 }
 
-fn builtin_rm(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("rm:\n\
+const RM_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("recursive", Some('r'), Some("recursive")),
+    OptionSpec::flag("recursive", Some('R'), None),
+    // Only accepted so `rm -rf` works: missing files are skipped anyway.
+    OptionSpec::flag("force", Some('f'), Some("force")),
+];
+
+fn builtin_rm(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, RM_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "rm:\n\
                  Removes files and directories in current directory.\n\
-                 -r         Remove recursively(for removing directories)");
+                 -r, -R     Remove recursively(for removing directories)");
         return SUCCESS_CODE;
     }
-    for arg in command.arguments {
-        let arg_path = Path::new(&arg);
-        if arg_path.is_file() {
+    let arguments = &options.arguments;
+    for arg in arguments {
+        let arg_path = &shell.resolve(arg);
+        // Not following links: a link to a directory is removed, not what is
+        // in the directory.
+        let Ok(metadata) = fs::symlink_metadata(arg_path) else {
+            continue;
+        };
+        if !metadata.is_dir() {
             match fs::remove_file(arg_path) {
                 Ok(()) => (),
                 Err(e) => {
-                    println!("Error occurred when removing file - {}", e);
+                    outln!(io.stderr, "Error occurred when removing file - {}", e);
                     return ERROR_CODE;
                 },
             }
-        } else if arg_path.is_dir() {
-            if command.options.contains(&String::from("-r")) {
-                match fs::remove_dir_all(arg_path) {
-                    Ok(()) => (),
-                    Err(e) => {
-                        println!("Error occurred when removing directory - {}", e);
-                        return ERROR_CODE;
-                    },
-                }
-            } else {
-                println!("Cannot remove directory - {}", arg);
+        } else if options.is_set("recursive") {
+            match remove_tree(arg_path, &platform::interrupted) {
+                Ok(()) => (),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => return platform::INTERRUPTED_STATUS,
+                Err(e) => {
+                    outln!(io.stderr, "Error occurred when removing directory - {}", e);
+                    return ERROR_CODE;
+                },
             }
+        } else {
+            outln!(io.stderr, "Cannot remove directory - {}", arg);
         }
     }
 
     SUCCESS_CODE
 }
 
-fn builtin_cp(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("cp:\n\
+/// Removes a directory with everything in it like `fs::remove_dir_all`, but
+/// stops with an `Interrupted` error once `interrupted` tells of Ctrl-C.
+fn remove_tree(path: &Path, interrupted: &dyn Fn() -> bool) -> io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        if interrupted() {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let entry = entry?;
+        // Links to directories are removed, not what they point to.
+        if entry.file_type()?.is_dir() {
+            remove_tree(&entry.path(), interrupted)?;
+        } else {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    fs::remove_dir(path)
+}
+
+/// Files are copied by pieces of this size, looking for Ctrl-C in between.
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Copies everything `reader` gives to `writer` like `io::copy`, but stops
+/// with an `Interrupted` error once `interrupted` tells of Ctrl-C.
+fn copy_interruptible(reader: &mut impl Read, writer: &mut impl Write, interrupted: &dyn Fn() -> bool)
+    -> io::Result<u64> {
+    let mut buffer = vec![0; COPY_BUFFER_SIZE];
+    let mut copied = 0;
+    loop {
+        if interrupted() {
+            return Err(io::ErrorKind::Interrupted.into());
+        }
+        let count = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(copied),
+            Ok(count) => count,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        writer.write_all(&buffer[..count])?;
+        copied += count as u64;
+    }
+}
+
+/// Copies a file with its permissions like `fs::copy`. Stopped by Ctrl-C,
+/// the unfinished copy is removed.
+fn copy_file(from: &Path, to: &Path, interrupted: &dyn Fn() -> bool) -> io::Result<u64> {
+    let mut source = File::open(from)?;
+    let metadata = source.metadata()?;
+    if !metadata.is_file() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "the source path is not a file"));
+    }
+    let mut target = File::create(to)?;
+    match copy_interruptible(&mut source, &mut target, interrupted) {
+        Ok(copied) => {
+            target.set_permissions(metadata.permissions())?;
+            Ok(copied)
+        }
+        Err(err) => {
+            drop(target);
+            if err.kind() == io::ErrorKind::Interrupted {
+                let _ = fs::remove_file(to);
+            }
+            Err(err)
+        }
+    }
+}
+
+fn builtin_cp(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "cp:\n\
                   Copy file or files into another file or given folder");
         return SUCCESS_CODE;
     }
-    if command.arguments.len() >= 2 {
-        let last_item = command.arguments.last().unwrap();
-        let path_to = Path::new(&last_item);
+    let arguments = options.arguments;
+    if arguments.len() >= 2 {
+        let last_item = arguments.last().unwrap();
+        let path_to = &shell.resolve(last_item);
         if !path_to.exists() {
-            println!("Destination file doesn't exist");
+            outln!(io.stderr, "Destination file doesn't exist");
             return ERROR_CODE;
         }
         if path_to.is_dir() {
-            for i in 0..command.arguments.len() - 1 {
-                let file_from = Path::new(&command.arguments[i]);
+            let mut return_code = SUCCESS_CODE;
+            for source in &arguments[..arguments.len() - 1] {
+                let file_from = &shell.resolve(source);
                 let mut new_path_to = path_to.to_path_buf();
-                new_path_to.push(&command.arguments[i]);
+                new_path_to.push(source);
 
-                match fs::copy(file_from, new_path_to) {
+                match copy_file(file_from, &new_path_to, &platform::interrupted) {
                     Ok(_) => (),
+                    Err(err) if err.kind() == io::ErrorKind::Interrupted => return platform::INTERRUPTED_STATUS,
                     Err(err) => {
-                        println!("Error occurred during copying - {err}");
+                        outln!(io.stderr, "Error occurred during copying - {err}");
+                        return_code = ERROR_CODE;
                     }
                 }
             }
-            return SUCCESS_CODE;
-        } else if command.arguments.len() == 2 {
-            let file_from = Path::new(&command.arguments[0]);
-            let file_to = Path::new(&command.arguments[1]);
-            match fs::copy(file_from, file_to) {
+            return_code
+        } else if arguments.len() == 2 {
+            let file_from = &shell.resolve(&arguments[0]);
+            let file_to = &shell.resolve(&arguments[1]);
+            match copy_file(file_from, file_to, &platform::interrupted) {
                 Ok(_) => {
                     SUCCESS_CODE
                 },
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => platform::INTERRUPTED_STATUS,
                 Err(err) => {
-                    println!("Error occurred during copying - {err}");
+                    outln!(io.stderr, "Error occurred during copying - {err}");
                     ERROR_CODE
                 }
             }
         } else {
-            println!("Erroc occurred - {} is not a dir", command.arguments.last().unwrap());
+            outln!(io.stderr, "Erroc occurred - {} is not a dir", arguments.last().unwrap());
             ERROR_CODE
         }
     } else {
-        println!("Wrong number and types of arguments");
+        outln!(io.stderr, "Wrong number and types of arguments");
         ERROR_CODE
     } 
 }
 
-fn builtin_mv(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("mv:\n\
+fn builtin_mv(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "mv:\n\
                   Moves one file to another place.\n\
                   If the files already exists, changes his name");
         return SUCCESS_CODE;
     }
-    if command.arguments.len() >= 2 {
-        let last_item = command.arguments.last().unwrap();
-        let path_to = Path::new(&last_item);
+    let arguments = options.arguments;
+    if arguments.len() >= 2 {
+        let last_item = arguments.last().unwrap();
+        let path_to = shell.resolve(last_item);
         if path_to.is_dir() {
-            if builtin_cp(command.clone()) == ERROR_CODE {
-                return ERROR_CODE;
+            // `--` keeps file names starting with a dash from being read as options.
+            let cp_command = Command {
+                keyword: String::from("cp"),
+                arguments: [String::from("--")].into_iter().chain(arguments.iter().cloned()).collect(),
+            };
+            // Nothing is removed unless all was copied.
+            let copied = builtin_cp(cp_command, shell, io);
+            if copied != SUCCESS_CODE {
+                return copied;
             }
-            let cmd = Command { keyword: command.keyword, arguments: command.arguments[..command.arguments.len() - 1].to_vec(), 
-                options: command.options, plain_command_input: command.plain_command_input.clone() };
-            if builtin_rm(cmd) == ERROR_CODE {
+            let rm_command = Command {
+                keyword: String::from("rm"),
+                arguments: [String::from("--")].into_iter().chain(arguments[..arguments.len() - 1].iter().cloned()).collect(),
+            };
+            if builtin_rm(rm_command, shell, io) == ERROR_CODE {
                 return ERROR_CODE;
             }
-        } else if command.arguments.len() == 2 {
-            let file_from = Path::new(&command.arguments[0]);
-            let last_item = command.arguments.last().unwrap();
-            let file_to = Path::new(&last_item);
+        } else if arguments.len() == 2 {
+            let file_from = &shell.resolve(&arguments[0]);
+            let last_item = arguments.last().unwrap();
+            let file_to = &shell.resolve(last_item);
             if file_from.is_dir() && file_to.is_file() {
-                println!("Cannot rename non-directory {} with directory {}", last_item, command.arguments[0]);
+                outln!(io.stderr, "Cannot rename non-directory {} with directory {}", last_item, arguments[0]);
                 return ERROR_CODE;
             }
             
@@ -525,212 +1109,1117 @@ fn builtin_mv(command: Command) -> i32 {
                     return SUCCESS_CODE;
                 },
                 Err(e) => {
-                    println!("Error occurred during moving _ {e}");
+                    outln!(io.stderr, "Error occurred during moving _ {e}");
                     return ERROR_CODE;
                 }
             }
         } else {
-            println!("Erroc occurred - {} is not a dir", command.arguments.last().unwrap());
+            outln!(io.stderr, "Erroc occurred - {} is not a dir", arguments.last().unwrap());
             return ERROR_CODE;
         }
         return SUCCESS_CODE;
-    } else if command.arguments.len() < 2 {
-        println!("Wrong number and types of arguments");
+    } else if arguments.len() < 2 {
+        outln!(io.stderr, "Wrong number and types of arguments");
         return ERROR_CODE;
     } 
     SUCCESS_CODE
 }
 
-fn builtin_touch(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("touch:\n\
+const TOUCH_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("access", Some('a'), None),
+    OptionSpec::flag("modification", Some('m'), None),
+];
+
+fn builtin_touch(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, TOUCH_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "touch:\n\
                   Update modified and accessed time on the file or directory to the current time.\n\
                   If the file does not exist, it will be created.\n\
                   -a            Change only accessed time.\n\
                   -m            Change only modified time.");
         return SUCCESS_CODE;
     }
-    if command.arguments.len() == 0 {
-        println!("Not enough arguments");
+    let arguments = &options.arguments;
+    if arguments.is_empty() {
+        outln!(io.stderr, "Not enough arguments");
         return ERROR_CODE;
     }
 
     let mut set_accces_time = true;
     let mut set_mod_time = true;
-    if command.options.contains(&String::from("-a")) && !command.options.contains(&String::from("-m")) {
+    if options.is_set("access") && !options.is_set("modification") {
         set_mod_time = false;
-    } else if !command.options.contains(&String::from("-a")) && command.options.contains(&String::from("-m")) {
+    } else if !options.is_set("access") && options.is_set("modification") {
         set_accces_time = false;
     }
 
-    for arg in command.arguments {
-        let arg_path = Path::new(&arg);
+    let mut return_code = SUCCESS_CODE;
+    for arg in arguments {
+        let arg_path = &shell.resolve(arg);
         if !arg_path.exists() {
             if let Err(err) = File::create(arg_path) {
-                println!("Couldn't create a new file - {err}");
+                outln!(io.stderr, "Couldn't create a new file - {err}");
+                return_code = ERROR_CODE;
             }
         } else {
-            if set_accces_time {
-                if let Err(_) = set_file_atime(arg_path, FileTime::now()) {
-                    println!("Error while setting access time");
-                }
+            if set_accces_time && set_file_atime(arg_path, FileTime::now()).is_err() {
+                outln!(io.stderr, "Error while setting access time");
+                return_code = ERROR_CODE;
             }
-            if set_mod_time {
-                if let Err(_) = set_file_mtime(arg_path, FileTime::now()) {
-                    println!("Error while setting modification time");
-                }
+            if set_mod_time && set_file_mtime(arg_path, FileTime::now()).is_err() {
+                outln!(io.stderr, "Error while setting modification time");
+                return_code = ERROR_CODE;
             }
         }
     }
-    SUCCESS_CODE
+    return_code
 }
 
-fn builtin_mkdir(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("mkdir:\n\
+fn builtin_mkdir(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "mkdir:\n\
                   Creates a folder, if it does not exist");
         return SUCCESS_CODE;
     }
-    if command.arguments.len() == 0 {
-        println!("Not enough arguments");
+    let arguments = options.arguments;
+    if arguments.is_empty() {
+        outln!(io.stderr, "Not enough arguments");
         return ERROR_CODE;
     }
 
-    for arg in command.arguments {
-        let arg_path = Path::new(&arg);
+    let mut return_code = SUCCESS_CODE;
+    for arg in arguments {
+        let arg_path = &shell.resolve(&arg);
         if arg_path.exists() {
-            println!("Cannot create directory: file {} exists", arg);
+            outln!(io.stderr, "Cannot create directory: file {} exists", arg);
+            return_code = ERROR_CODE;
             continue;
         }
         if let Err(e) = fs::create_dir(arg_path) {
-            println!("Could not create a directory - {}", e);
+            outln!(io.stderr, "Could not create a directory - {}", e);
+            return_code = ERROR_CODE;
         }
     }
 
-    SUCCESS_CODE
+    return_code
 }
 
-fn builtin_cat(mut command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("cat:\n\
-                  Concatenate files into another file or prints it to standard output.\n\
+fn builtin_cat(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "cat:\n\
+                  Concatenate files and print them to standard output.\n\
+                  Without files, or when a file is '-', standard input is read.\n\
                   The result can be redirected via '>' to file.");
         return SUCCESS_CODE;
     }
-    if command.arguments.len() == 0 {
-        println!("Not enough arguments");
-        return ERROR_CODE;
+    let mut arguments = options.arguments;
+    if arguments.is_empty() {
+        arguments.push(String::from("-"));
     }
 
-     
-    let redirection_sign = String::from(">");
-    let mut file_string = String::new();
-
-    let mut dest_file = String::new();
-    let mut contains_redirection = false;
-    if command.arguments.contains(&redirection_sign) {
-        if command.arguments[command.arguments.len() - 2] != redirection_sign {
-            println!("Wrong position of arguments");
-            return ERROR_CODE;
-        } else {
-            dest_file = command.arguments.pop().unwrap();
-            command.arguments.pop();
-            contains_redirection = true;
+    let mut return_code = SUCCESS_CODE;
+    for arg in &arguments {
+        if arg == "-" {
+            match copy_interruptible(&mut io.stdin, &mut io.stdout, &platform::interrupted) {
+                Ok(_) => (),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => return platform::INTERRUPTED_STATUS,
+                Err(err) => {
+                    outln!(io.stderr, "Error occurred while reading standard input: {err}");
+                    return_code = ERROR_CODE;
+                }
+            }
+            continue;
         }
-    }
-
-    for arg in &command.arguments { 
-        let file_path = Path::new(&arg);
+        let file_path = &shell.resolve(arg);
         if file_path.is_dir() {
-            println!("Error occurred - {} is directory", arg);
+            outln!(io.stderr, "Error occurred - {} is directory", arg);
+            return_code = ERROR_CODE;
             continue;
         }
-        let file_contents = fs::read_to_string(file_path);
-        match file_contents {
-            Ok(file_contents) => file_string.push_str(&file_contents),
+        let copied = File::open(file_path).and_then(|mut file| copy_interruptible(&mut file, &mut io.stdout, &platform::interrupted));
+        match copied {
+            Ok(_) => (),
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => return platform::INTERRUPTED_STATUS,
             Err(err) => {
-                println!("Error occurred while reading file: {}", err);
+                outln!(io.stderr, "Error occurred while reading file: {}", err);
+                return_code = ERROR_CODE;
             }
         }
     }
+    return_code
+}
 
-    if contains_redirection && !file_string.is_empty() {
-        let file_path = Path::new(&dest_file);
-        if file_path.is_dir() {
-            println!("Cannot redirect output to directory - {}", dest_file);
-            return ERROR_CODE;
+fn builtin_help(command: Command, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "help:\n\
+                  Display information aboult all available buitlin commands");
+        return SUCCESS_CODE;
+    }
+    outln!(io.stdout, "{}", HELP_TEXT);
+    SUCCESS_CODE
+}
+
+
+fn list_shell_options(shell_options: &ShellOptions, io: &mut IoStreams) -> i32 {
+    for name in ShellOptions::NAMES {
+        let state = if shell_options.get(name) == Some(true) { "on" } else { "off" };
+        outln!(io.stdout, "{name:<15}{state}");
+    }
+    SUCCESS_CODE
+}
+
+fn builtin_set(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    match command.arguments.as_slice() {
+        [] => {
+            for (name, variable) in shell.variables.sorted() {
+                outln!(io.stdout, "{name}={}", variables::quote(&variable.value));
+            }
+            SUCCESS_CODE
+        }
+        [flag] if flag == "-o" => list_shell_options(&shell.options, io),
+        [flag] if flag == "--help" => {
+            outln!(io.stdout, "set:\n\
+                      Change or display shell options.\n\
+                      -o NAME       Enable the option NAME.\n\
+                      +o NAME       Disable the option NAME.\n\
+                      -o            Display all options.\n\
+                      Without arguments all shell variables are displayed.\n\
+                      Options: {}", ShellOptions::NAMES.join(", "));
+            SUCCESS_CODE
         }
-        let file = File::create(file_path);
-        match file {
-            Ok(mut file) => {
-                if let Err(err) = file.write_all(file_string.as_bytes()) {
-                    println!("Error occurred while redirecting output: {err}");
+        [flag, name] if flag == "-o" || flag == "+o" => {
+            if shell.options.set(name, flag == "-o") {
+                SUCCESS_CODE
+            } else {
+                outln!(io.stderr, "set: unknown option '{name}'");
+                ERROR_CODE
+            }
+        }
+        _ => {
+            outln!(io.stderr, "Wrong number and types of arguments");
+            ERROR_CODE
+        }
+    }
+}
+
+const EXPORT_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("unexport", Some('n'), None),
+    OptionSpec::flag("print", Some('p'), None),
+    OptionSpec::flag("function", Some('f'), None),
+];
+
+fn builtin_export(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, EXPORT_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "export:\n\
+                  Mark variables to be passed to the programs started by the shell.\n\
+                  export NAME=VALUE    Set and export a variable.\n\
+                  export NAME          Export an existing variable.\n\
+                  -f NAME              Export the function NAME to the shells started from this one.\n\
+                  -n                   Stop exporting the variables instead.\n\
+                  -p                   Display exported variables and functions, also done without arguments.");
+        return SUCCESS_CODE;
+    }
+    if options.arguments.is_empty() {
+        for (name, variable) in shell.variables.sorted() {
+            if variable.exported {
+                outln!(io.stdout, "export {name}={}", variables::quote(&variable.value));
+            }
+        }
+        for (name, function) in shell.functions.iter() {
+            if function.exported {
+                outln!(io.stdout, "export -f {name}");
+            }
+        }
+        return SUCCESS_CODE;
+    }
+
+    let exported = !options.is_set("unexport");
+    let mut return_code = SUCCESS_CODE;
+    if options.is_set("function") {
+        for name in &options.arguments {
+            match Arc::make_mut(&mut shell.functions).get_mut(name) {
+                Some(function) => function.exported = exported,
+                None => {
+                    outln!(io.stderr, "export: {name}: not a function");
+                    return_code = ERROR_CODE;
+                }
+            }
+        }
+        return return_code;
+    }
+    let shell_variables = &mut shell.variables;
+    for argument in &options.arguments {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (argument.as_str(), None),
+        };
+        if !variables::is_valid_name(name) {
+            outln!(io.stderr, "export: '{argument}': not a valid identifier");
+            return_code = ERROR_CODE;
+            continue;
+        }
+        if let Some(value) = value {
+            shell_variables.set(name, value);
+        }
+        shell_variables.export(name, exported);
+    }
+    return_code
+}
+
+const UNSET_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("function", Some('f'), None)];
+
+fn builtin_unset(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, UNSET_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "unset:\n\
+                  Remove each variable NAME from the shell and the environment of started programs.\n\
+                  -f    Remove the functions NAME instead.");
+        return SUCCESS_CODE;
+    }
+    let mut return_code = SUCCESS_CODE;
+    for name in &options.arguments {
+        if options.is_set("function") {
+            Arc::make_mut(&mut shell.functions).remove(name);
+        } else if variables::is_valid_name(name) {
+            shell.variables.unset(name);
+        } else {
+            outln!(io.stderr, "unset: '{name}': not a valid identifier");
+            return_code = ERROR_CODE;
+        }
+    }
+    return_code
+}
+
+/// `env [-i] [-u NAME]... [NAME=VALUE]... [COMMAND [ARGUMENT]...]`. Options
+/// are read by hand, since everything after the command belongs to it.
+fn builtin_env(command: Command, shell: &mut Shell, mut io: IoStreams) -> i32 {
+    let mut environment = shell.subshell();
+    let mut arguments = command.arguments.into_iter().peekable();
+    while let Some(argument) = arguments.next_if(|argument| argument.starts_with('-')) {
+        match argument.as_str() {
+            "-i" | "--ignore-environment" => {
+                // Unexported variables are still there, `PATH` included, so
+                // the command can be found.
+                let names: Vec<String> = environment.variables.environment()
+                    .into_iter()
+                    .map(|(name, _)| name.to_string())
+                    .collect();
+                for name in names {
+                    environment.variables.export(&name, false);
+                }
+            }
+            "-u" => match arguments.next() {
+                Some(name) => environment.variables.unset(&name),
+                None => {
+                    outln!(io.stderr, "env: option requires an argument -- 'u'");
                     return ERROR_CODE;
                 }
             },
+            "--help" => {
+                outln!(io.stdout, "env:\n\
+                          Run a program in a modified environment, or display the environment.\n\
+                          -i            Start with an empty environment.\n\
+                          -u NAME       Remove NAME from the environment.\n\
+                          NAME=VALUE    Set NAME in the environment.");
+                return SUCCESS_CODE;
+            }
+            "--" => break,
+            _ => {
+                outln!(io.stderr, "env: unknown option '{argument}'");
+                return ERROR_CODE;
+            }
+        }
+    }
+    while let Some(argument) = arguments.next_if(|argument| variables::split_assignment(argument).is_some()) {
+        if let Some((name, value)) = variables::split_assignment(&argument) {
+            environment.variables.set(name, value);
+            environment.variables.export(name, true);
+        }
+    }
+
+    let words: Vec<String> = arguments.collect();
+    if words.is_empty() {
+        for (name, value) in environment.variables.environment() {
+            outln!(io.stdout, "{name}={value}");
+        }
+        return SUCCESS_CODE;
+    }
+    exec::run_external(Command::new(words), &mut environment, io)
+}
+
+/// Ends the shell. The status defaults to the one of the last command.
+fn builtin_exit(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    match command.arguments.as_slice() {
+        [] => EXIT_CODE,
+        [flag] if flag == "--help" => {
+            outln!(io.stdout, "exit:\n\
+                      Exit the shell with the status N, or the status of the last command.");
+            SUCCESS_CODE
+        }
+        [status] => match status.parse::<i32>() {
+            Ok(status) => {
+                shell.last_status = status & 0xff;
+                EXIT_CODE
+            }
+            Err(_) => {
+                outln!(io.stderr, "exit: {status}: numeric argument required");
+                shell.last_status = SYNTAX_ERROR_CODE;
+                EXIT_CODE
+            }
+        },
+        _ => {
+            outln!(io.stderr, "exit: too many arguments");
+            ERROR_CODE
+        }
+    }
+}
+
+/// `break [N]` and `continue [N]`: leave N loops, or go on with the next
+/// round of the N-th one.
+fn builtin_loop_control(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let keyword = command.keyword.as_str();
+    let count = match command.arguments.as_slice() {
+        [] => 1,
+        [flag] if flag == "--help" => {
+            match keyword {
+                "break" => {
+                    outln!(io.stdout, "break:\n\
+                              Leave the N innermost for, while or until loops, 1 by default.");
+                }
+                _ => {
+                    outln!(io.stdout, "continue:\n\
+                              Go on with the next round of the N-th innermost for, while or until loop, 1 by default.");
+                }
+            }
+            return SUCCESS_CODE;
+        }
+        [count] => match count.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                outln!(io.stderr, "{keyword}: {count}: loop count out of range");
+                return ERROR_CODE;
+            }
+        },
+        _ => {
+            outln!(io.stderr, "{keyword}: too many arguments");
+            return ERROR_CODE;
+        }
+    };
+    if shell.loop_depth == 0 {
+        outln!(io.stderr, "{keyword}: only meaningful in a for, while or until loop");
+        return ERROR_CODE;
+    }
+    // Asking for more loops than there are leaves the outermost one.
+    let count = count.min(shell.loop_depth);
+    shell.flow = Some(if keyword == "break" { Flow::Break(count) } else { Flow::Continue(count) });
+    SUCCESS_CODE
+}
+
+/// `shift [N]`: drops the first N positional parameters, `$N+1` becomes
+/// `$1`.
+fn builtin_shift(command: Command, positional: &mut Vec<String>, io: &mut IoStreams) -> i32 {
+    let count = match command.arguments.as_slice() {
+        [] => 1,
+        [flag] if flag == "--help" => {
+            outln!(io.stdout, "shift:\n\
+                      Drop the first N positional parameters, 1 by default: $2 becomes $1 and so on.");
+            return SUCCESS_CODE;
+        }
+        [count] => match count.parse::<usize>() {
+            Ok(count) => count,
+            Err(_) => {
+                outln!(io.stderr, "shift: {count}: numeric argument required");
+                return ERROR_CODE;
+            }
+        },
+        _ => {
+            outln!(io.stderr, "shift: too many arguments");
+            return ERROR_CODE;
+        }
+    };
+    // Fails quietly when there are not enough parameters, so that
+    // `while shift; do` stops at the end.
+    if count > positional.len() {
+        return ERROR_CODE;
+    }
+    positional.drain(..count);
+    SUCCESS_CODE
+}
+
+/// `local NAME[=VALUE]...`: variables of the running function. Whatever the
+/// names held before comes back when the function returns.
+fn builtin_local(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, &[], io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "local:\n\
+                  Make each variable NAME local to the running function, set to VALUE if given.");
+        return SUCCESS_CODE;
+    }
+    let Some(saved) = shell.locals.last_mut() else {
+        outln!(io.stderr, "local: can only be used in a function");
+        return ERROR_CODE;
+    };
+    let mut return_code = SUCCESS_CODE;
+    for argument in &options.arguments {
+        let (name, value) = match argument.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (argument.as_str(), None),
+        };
+        if !variables::is_valid_name(name) {
+            outln!(io.stderr, "local: '{argument}': not a valid identifier");
+            return_code = ERROR_CODE;
+            continue;
+        }
+        // A new local starts out unset and unexported, hiding the variable
+        // of the caller.
+        if !saved.iter().any(|(saved_name, _)| saved_name == name) {
+            saved.push((name.to_string(), shell.variables.get_variable(name).cloned()));
+            shell.variables.unset(name);
+        }
+        if let Some(value) = value {
+            shell.variables.set(name, value);
+        }
+    }
+    return_code
+}
+
+/// `return [N]`: leaves the running function or sourced file with the
+/// status N, or the status of the last command.
+fn builtin_return(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let status = match command.arguments.as_slice() {
+        [] => shell.last_status,
+        [flag] if flag == "--help" => {
+            outln!(io.stdout, "return:\n\
+                      Leave the running function or sourced file with the status N, or the status of the last command.");
+            return SUCCESS_CODE;
+        }
+        [status] => match status.parse::<i32>() {
+            Ok(status) => status & 0xff,
+            Err(_) => {
+                outln!(io.stderr, "return: {status}: numeric argument required");
+                SYNTAX_ERROR_CODE
+            }
+        },
+        _ => {
+            outln!(io.stderr, "return: too many arguments");
+            return ERROR_CODE;
+        }
+    };
+    if shell.call_depth == 0 {
+        outln!(io.stderr, "return: can only be used in a function or sourced file");
+        return ERROR_CODE;
+    }
+    shell.flow = Some(Flow::Return(status));
+    status
+}
+
+const TYPE_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("type", Some('t'), None)];
+
+/// `type NAME...`: tells what runs for each name used as a command, in the
+/// order the shell looks for it.
+fn builtin_type(command: Command, shell: &Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, TYPE_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "type:\n\
+                  Display whether each NAME is an alias, a keyword, a builtin, a function or a program.\n\
+                  -t    Display only one of alias, keyword, builtin, function or file.");
+        return SUCCESS_CODE;
+    }
+    let mut return_code = SUCCESS_CODE;
+    for name in &options.arguments {
+        let command = Command { keyword: platform::executable_name(name), arguments: Vec::new() };
+        let found = if let Some(value) = shell.aliases.get(name) {
+            Some(("alias", format!("{name} is aliased to '{value}'")))
+        } else if parser::RESERVED_WORDS.contains(&name.as_str()) {
+            Some(("keyword", format!("{name} is a shell keyword")))
+        } else if BuiltinCommand::from_str(name).is_ok() {
+            Some(("builtin", format!("{name} is a shell builtin")))
+        } else if let Some(function) = shell.functions.get(name) {
+            Some(("function", format!("{name} is a function\n{}", function.definition)))
+        } else {
+            find_executable(&command, shell).ok().map(|path| ("file", format!("{name} is {}", path.display())))
+        };
+        match found {
+            Some((kind, _)) if options.is_set("type") => {
+                outln!(io.stdout, "{kind}");
+            }
+            Some((_, description)) => {
+                outln!(io.stdout, "{description}");
+            }
+            None => {
+                if !options.is_set("type") {
+                    outln!(io.stderr, "type: {name}: not found");
+                }
+                return_code = ERROR_CODE;
+            }
+        }
+    }
+    return_code
+}
+
+const JOBS_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("pids", Some('p'), None)];
+
+/// Lists the jobs, or the ones given by `%` specs.
+fn builtin_jobs(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, JOBS_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "jobs:\n\
+                  Display the jobs started with '&' or stopped with Ctrl-Z, or the ones given by JOBSPEC.\n\
+                  -p    Display only the process group ID of each job.");
+        return SUCCESS_CODE;
+    }
+    let mut selected = Vec::new();
+    for spec in &options.arguments {
+        match shell.jobs.find(Some(spec)) {
+            Ok(job) => selected.push(job.id),
             Err(err) => {
-                println!("Error occurred when opening file: {err}");
+                outln!(io.stderr, "jobs: {err}");
                 return ERROR_CODE;
             }
         }
-    } else {
-        println!("{file_string}");
     }
+    for (job, line) in shell.jobs.describe_all() {
+        if !selected.is_empty() && !selected.contains(&job.id) {
+            continue;
+        }
+        if !options.is_set("pids") {
+            outln!(io.stdout, "{line}");
+        } else if let Some(group) = job.group() {
+            outln!(io.stdout, "{group}");
+        }
+    }
+    SUCCESS_CODE
+}
 
+/// Finds the job of the only argument of `fg` and `bg`, the current job
+/// without one. Both need job control.
+fn job_argument(command: &Command, shell: &Shell, io: &mut IoStreams) -> Option<Arc<jobs::Job>> {
+    if !shell.job_control {
+        outln!(io.stderr, "{}: no job control", command.keyword);
+        return None;
+    }
+    if command.arguments.len() > 1 {
+        outln!(io.stderr, "{}: too many arguments", command.keyword);
+        return None;
+    }
+    match shell.jobs.find(command.arguments.first().map(String::as_str)) {
+        Ok(job) => Some(job),
+        Err(err) => {
+            outln!(io.stderr, "{}: {err}", command.keyword);
+            None
+        }
+    }
+}
+
+/// Brings a job to the foreground, continuing it if stopped, and waits for
+/// it like for a command typed at the prompt.
+fn builtin_fg(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    if command.arguments.first().is_some_and(|argument| argument == "--help") {
+        outln!(io.stdout, "fg:\n\
+                  Continue JOBSPEC, or the current job, in the foreground.");
+        return SUCCESS_CODE;
+    }
+    let Some(job) = job_argument(&command, shell, io) else {
+        return ERROR_CODE;
+    };
+    outln!(io.stdout, "{}", job.command);
+    shell.jobs.make_current(&job);
+    if let Some(group) = job.group() {
+        platform::give_terminal(group);
+    }
+    job.resume();
+    let state = job.wait();
+    platform::take_terminal();
+    match state {
+        JobState::Done(status) => {
+            shell.jobs.remove(&job);
+            // Ctrl-C stops the commands after it, as for any foreground program.
+            if status == platform::INTERRUPTED_STATUS {
+                platform::interrupt();
+            }
+            status
+        }
+        JobState::Stopped => {
+            outln!(io.stderr, "\n{}", jobs::describe(&job, '+', JobState::Stopped));
+            platform::STOPPED_STATUS
+        }
+        // Ctrl-C reached the shell, the job goes on in the background.
+        JobState::Running => platform::INTERRUPTED_STATUS,
+    }
+}
 
+/// Continues a stopped job in the background.
+fn builtin_bg(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    if command.arguments.first().is_some_and(|argument| argument == "--help") {
+        outln!(io.stdout, "bg:\n\
+                  Continue the stopped JOBSPEC, or the current job, in the background.");
+        return SUCCESS_CODE;
+    }
+    let Some(job) = job_argument(&command, shell, io) else {
+        return ERROR_CODE;
+    };
+    if job.state() != JobState::Stopped {
+        outln!(io.stderr, "bg: job {} already in background", job.id);
+        return SUCCESS_CODE;
+    }
+    job.resume();
+    outln!(io.stdout, "[{}] {} &", job.id, job.command);
     SUCCESS_CODE
 }
 
-fn builtin_help(command: Command) -> i32 {
-    if command.arguments.contains(&String::from("--help")) {
-        println!("help:\n\
-                  Display information aboult all available buitlin commands");
+/// Waits for the jobs given by `%` spec or process ID, or for every running
+/// job, and gives the status of the last one.
+fn builtin_wait(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    if command.arguments.first().is_some_and(|argument| argument == "--help") {
+        outln!(io.stdout, "wait:\n\
+                  Wait for the jobs given by JOBSPEC or process ID, or for all running jobs, and return the \
+                  status of the last one.");
+        return SUCCESS_CODE;
+    }
+    if command.arguments.is_empty() {
+        for (job, _) in shell.jobs.list() {
+            match job.wait() {
+                JobState::Done(_) => shell.jobs.remove(&job),
+                JobState::Stopped => (),
+                JobState::Running => return platform::INTERRUPTED_STATUS,
+            }
+        }
         return SUCCESS_CODE;
     }
-    let help_info = fs::read_to_string(HELP_FILE_INFO_NAME);
-    match help_info {
-        Ok(help_info_content) => {
-            println!("{}", help_info_content);
+    let mut return_code = SUCCESS_CODE;
+    for argument in &command.arguments {
+        let job = match argument.parse::<u32>() {
+            Ok(pid) => shell.jobs.find_pid(pid).ok_or_else(|| format!("pid {pid} is not a child of this shell")),
+            Err(_) if argument.starts_with('%') => shell.jobs.find(Some(argument)),
+            Err(_) => Err(format!("'{argument}': not a pid or valid job spec")),
+        };
+        return_code = match job {
+            Ok(job) => match job.wait() {
+                JobState::Done(status) => {
+                    shell.jobs.remove(&job);
+                    status
+                }
+                JobState::Stopped => platform::STOPPED_STATUS,
+                JobState::Running => return platform::INTERRUPTED_STATUS,
+            },
+            Err(err) => {
+                outln!(io.stderr, "wait: {err}");
+                exec::NOT_FOUND_CODE
+            }
+        };
+    }
+    return_code
+}
+
+const DISOWN_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("all", Some('a'), None)];
+
+/// Forgets jobs, which go on running but are no longer listed.
+fn builtin_disown(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, DISOWN_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "disown:\n\
+                  Remove JOBSPEC, or the current job, from the job table.\n\
+                  -a    Remove all jobs.");
+        return SUCCESS_CODE;
+    }
+    if options.is_set("all") {
+        shell.jobs.clear();
+        return SUCCESS_CODE;
+    }
+    let specs: Vec<Option<&str>> = match options.arguments.is_empty() {
+        true => vec![None],
+        false => options.arguments.iter().map(|spec| Some(spec.as_str())).collect(),
+    };
+    let mut return_code = SUCCESS_CODE;
+    for spec in specs {
+        match shell.jobs.find(spec) {
+            Ok(job) => shell.jobs.remove(&job),
+            Err(err) => {
+                outln!(io.stderr, "disown: {err}");
+                return_code = ERROR_CODE;
+            }
+        }
+    }
+    return_code
+}
+
+/// Runs the commands of a file in the current shell, so the variables and
+/// directory it sets stay in effect.
+fn builtin_source(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    match command.arguments.first() {
+        Some(flag) if flag == "--help" => {
+            outln!(io.stdout, "source:\n\
+                      Read and run the commands of FILE in the current shell. '.' is the same command.");
             SUCCESS_CODE
         }
-        Err(err) => {
-            println!("Could not print help info - {err}");
-            ERROR_CODE
+        Some(path) => {
+            let path = shell.resolve(path);
+            shell.call_depth += 1;
+            let status = script::run_file(&path, shell, io);
+            shell.call_depth -= 1;
+            match shell.flow {
+                Some(Flow::Return(status)) => {
+                    shell.flow = None;
+                    status
+                }
+                _ => status,
+            }
+        }
+        None => {
+            outln!(io.stderr, "{}: filename argument required", command.keyword);
+            SYNTAX_ERROR_CODE
         }
     }
 }
 
+const ALIAS_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("print", Some('p'), None)];
+
+/// Sets or shows aliases. The listing is made of `alias` commands, so it can
+/// be saved to the startup file as it is.
+fn builtin_alias(command: Command, aliases: &mut BTreeMap<String, String>, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, ALIAS_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "alias:\n\
+                  Define or display aliases, names standing for the start of a command.\n\
+                  alias NAME=VALUE    Make NAME run VALUE. A VALUE ending with a space lets the next word be an alias too.\n\
+                  alias NAME          Display the alias NAME.\n\
+                  -p                  Display all aliases, also done without arguments.");
+        return SUCCESS_CODE;
+    }
+    if options.arguments.is_empty() || options.is_set("print") {
+        for (name, value) in aliases.iter() {
+            outln!(io.stdout, "alias {name}={}", variables::quote(value));
+        }
+    }
+    let mut return_code = SUCCESS_CODE;
+    for argument in &options.arguments {
+        match argument.split_once('=') {
+            Some((name, _)) if !is_valid_alias_name(name) => {
+                outln!(io.stderr, "alias: '{name}': invalid alias name");
+                return_code = ERROR_CODE;
+            }
+            Some((name, value)) => {
+                aliases.insert(name.to_string(), value.to_string());
+            }
+            None => match aliases.get(argument) {
+                Some(value) => {
+                    outln!(io.stdout, "alias {argument}={}", variables::quote(value));
+                }
+                None => {
+                    outln!(io.stderr, "alias: {argument}: not found");
+                    return_code = ERROR_CODE;
+                }
+            },
+        }
+    }
+    return_code
+}
+
+/// Alias names can't hold characters that end a word or start a quote or
+/// an expansion.
+fn is_valid_alias_name(name: &str) -> bool {
+    !name.is_empty() && !name.chars().any(|c| c.is_whitespace() || "|&;<>()$`\\\"'=".contains(c))
+}
+
+const UNALIAS_OPTIONS: &[OptionSpec] = &[OptionSpec::flag("all", Some('a'), None)];
+
+fn builtin_unalias(command: Command, aliases: &mut BTreeMap<String, String>, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, UNALIAS_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "unalias:\n\
+                  Remove each alias NAME.\n\
+                  -a    Remove all aliases.");
+        return SUCCESS_CODE;
+    }
+    if options.is_set("all") {
+        aliases.clear();
+        return SUCCESS_CODE;
+    }
+    if options.arguments.is_empty() {
+        outln!(io.stderr, "unalias: usage: unalias [-a] NAME...");
+        return SYNTAX_ERROR_CODE;
+    }
+    let mut return_code = SUCCESS_CODE;
+    for name in &options.arguments {
+        if aliases.remove(name).is_none() {
+            outln!(io.stderr, "unalias: {name}: not found");
+            return_code = ERROR_CODE;
+        }
+    }
+    return_code
+}
+
+const COMPLETE_OPTIONS: &[OptionSpec] = &[
+    OptionSpec::flag("commands", Some('c'), None),
+    OptionSpec::flag("directories", Some('d'), None),
+    OptionSpec::flag("files", Some('f'), None),
+    OptionSpec::value("words", Some('W'), None),
+    OptionSpec::flag("remove", Some('r'), None),
+    OptionSpec::flag("print", Some('p'), None),
+];
+
+/// Sets how Tab completes the arguments of commands, so tools the shell
+/// knows nothing about can offer their subcommands.
+fn builtin_complete(command: Command, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let options = match parse_options(&command, COMPLETE_OPTIONS, io) {
+        Some(options) => options,
+        None => return ERROR_CODE,
+    };
+    if options.is_set("help") {
+        outln!(io.stdout, "complete:\n\
+                  complete [-cdf] [-W WORDS] NAME... - complete the arguments of the commands with commands (-c),\n\
+                  \x20 directories (-d), files (-f) or the space separated WORDS;\n\
+                  complete -r NAME... - go back to completing file names;\n\
+                  complete [-p] [NAME...] - print the completions as complete commands.");
+        return SUCCESS_CODE;
+    }
+    let names = &options.arguments;
+    if options.is_set("remove") {
+        for name in names {
+            Arc::make_mut(&mut shell.completions).remove(name);
+        }
+        return SUCCESS_CODE;
+    }
+    let spec = CompletionSpec {
+        words: options.value("words").unwrap_or_default().split_whitespace().map(String::from).collect(),
+        commands: options.is_set("commands"),
+        directories: options.is_set("directories"),
+        files: options.is_set("files"),
+    };
+    if names.is_empty() || options.is_set("print") || spec == CompletionSpec::default() {
+        let mut status = SUCCESS_CODE;
+        if names.is_empty() {
+            for (name, spec) in shell.completions.iter() {
+                outln!(io.stdout, "{}", spec.to_command(name));
+            }
+        }
+        for name in names {
+            match shell.completions.get(name) {
+                Some(spec) => {
+                    outln!(io.stdout, "{}", spec.to_command(name));
+                }
+                None => {
+                    outln!(io.stderr, "complete: {name}: no completion specification");
+                    status = ERROR_CODE;
+                }
+            }
+        }
+        return status;
+    }
+    for name in names {
+        Arc::make_mut(&mut shell.completions).insert(name.clone(), spec.clone());
+    }
+    SUCCESS_CODE
+}
 
 #[cfg(test)]
 mod tokenizing_tests {
     use super::*;
+    use lexer::ParseError;
+
+    /// Parses a line holding one simple command into a `Command`.
+    pub(super) fn tokenize_command(command: String) -> Result<Command, ParseError> {
+        let list = parser::parse(&command)?.expect("command line is blank");
+        let parser::PipelineCommand::Simple(simple_command) = &list.items[0].first.commands[0] else {
+            panic!("not a simple command")
+        };
+        Ok(Command::new(expand::expand_words(&simple_command.words, &mut Shell::new()).unwrap()))
+    }
 
     #[test]
     fn only_keyword() {
-        let tokenized_command = tokenize_command(String::from("shell"));
+        let tokenized_command = tokenize_command(String::from("shell")).unwrap();
         assert_eq!("shell", tokenized_command.keyword);
         assert_eq!(Vec::<String>::new(), tokenized_command.arguments);
     }
 
     #[test]
     fn keyword_and_one_argument() {
-        let tokenized_command = tokenize_command(String::from("cat arg1 "));
+        let tokenized_command = tokenize_command(String::from("cat arg1 ")).unwrap();
         assert_eq!("cat", tokenized_command.keyword);
         assert_eq!(vec![String::from("arg1")], tokenized_command.arguments);
     }
 
     #[test]
     fn keyword_and_two_arguments() {
-        let tokenized_command = tokenize_command(String::from("cat arg1 arg2"));
+        let tokenized_command = tokenize_command(String::from("cat arg1 arg2")).unwrap();
         assert_eq!("cat", tokenized_command.keyword);
         assert_eq!(vec![String::from("arg1"), String::from("arg2")], tokenized_command.arguments);
     }
 
     #[test]
     fn keyword_and_many_arguments() {
-        let tokenized_command = tokenize_command(String::from("cat arg1 arg2 arg3 blabla sth"));
+        let tokenized_command = tokenize_command(String::from("cat arg1 arg2 arg3 blabla sth")).unwrap();
         assert_eq!("cat", tokenized_command.keyword);
         assert_eq!(vec![String::from("arg1"), String::from("arg2"), String::from("arg3"), String::from("blabla"), String::from("sth")], 
                    tokenized_command.arguments);
     }
 }
+
+#[cfg(test)]
+mod quoting_tests {
+    use super::tokenizing_tests::tokenize_command;
+    use crate::lexer::ParseError;
+
+    #[test]
+    fn double_quotes_keep_spaces() {
+        let tokenized_command = tokenize_command(String::from("cat \"my file.txt\"")).unwrap();
+        assert_eq!("cat", tokenized_command.keyword);
+        assert_eq!(vec![String::from("my file.txt")], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn single_quotes_are_literal() {
+        let tokenized_command = tokenize_command(String::from("echo 'a \\ \"b\"  c'")).unwrap();
+        assert_eq!(vec![String::from("a \\ \"b\"  c")], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn backslash_escapes() {
+        let tokenized_command = tokenize_command(String::from("cat my\\ file \\\"q\\\"")).unwrap();
+        assert_eq!(vec![String::from("my file"), String::from("\"q\"")], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn backslash_inside_double_quotes() {
+        let tokenized_command = tokenize_command(String::from("echo \"a\\\"b \\n \\\\\"")).unwrap();
+        assert_eq!(vec![String::from("a\"b \\n \\")], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn adjacent_quoted_pieces_form_one_word() {
+        let tokenized_command = tokenize_command(String::from("echo a\"b c\"d'e f'")).unwrap();
+        assert_eq!(vec![String::from("ab cde f")], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn empty_quotes_are_an_argument() {
+        let tokenized_command = tokenize_command(String::from("touch '' \"\"")).unwrap();
+        assert_eq!(vec![String::new(), String::new()], tokenized_command.arguments);
+    }
+
+    #[test]
+    fn unterminated_quotes_are_errors() {
+        assert_eq!(Some(ParseError::UnterminatedQuote('"')), tokenize_command(String::from("cat \"file")).err());
+        assert_eq!(Some(ParseError::UnterminatedQuote('\'')), tokenize_command(String::from("cat 'file")).err());
+        assert_eq!(Some(ParseError::TrailingBackslash), tokenize_command(String::from("cat file\\")).err());
+    }
+}
+
+#[cfg(test)]
+mod history_builtin_tests {
+    use super::*;
+
+    #[test]
+    fn positions() {
+        assert_eq!(Some(0), history_index("1", 3));
+        assert_eq!(Some(2), history_index("-1", 3));
+        assert_eq!(None, history_index("4", 3));
+        assert_eq!(None, history_index("0", 3));
+        assert_eq!(None, history_index("-4", 3));
+    }
+
+    #[test]
+    fn json_strings() {
+        assert_eq!(r#""echo \"a\\b\"\n\u0007""#, json_string("echo \"a\\b\"\n\x07"));
+    }
+}
+
+#[cfg(test)]
+mod file_builtin_tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("file_builtin_tests_{name}_{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rm_removes_links_not_their_directory() {
+        let dir = test_dir("links");
+        fs::create_dir(dir.join("target")).unwrap();
+        File::create(dir.join("target").join("kept")).unwrap();
+        std::os::unix::fs::symlink(dir.join("target"), dir.join("link")).unwrap();
+        let link = dir.join("link").to_string_lossy().into_owned();
+        let command = Command { keyword: String::from("rm"), arguments: vec![String::from("-r"), link] };
+        assert_eq!(SUCCESS_CODE, builtin_rm(command, &Shell::default(), &mut IoStreams::inherit()));
+        assert!(fs::symlink_metadata(dir.join("link")).is_err());
+        assert!(dir.join("target").join("kept").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_removal_stops() {
+        let dir = test_dir("remove");
+        fs::create_dir_all(dir.join("tree").join("sub")).unwrap();
+        File::create(dir.join("tree").join("file")).unwrap();
+        let error = remove_tree(&dir.join("tree"), &|| true).unwrap_err();
+        assert_eq!(io::ErrorKind::Interrupted, error.kind());
+        assert!(dir.join("tree").join("file").exists());
+        remove_tree(&dir.join("tree"), &|| false).unwrap();
+        assert!(!dir.join("tree").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn interrupted_copy_is_removed() {
+        let dir = test_dir("copy");
+        fs::write(dir.join("source"), vec![b'x'; COPY_BUFFER_SIZE * 3]).unwrap();
+        // Ctrl-C after the first piece.
+        let checks = Cell::new(0);
+        let interrupted = || {
+            checks.set(checks.get() + 1);
+            checks.get() > 1
+        };
+        let error = copy_file(&dir.join("source"), &dir.join("copy"), &interrupted).unwrap_err();
+        assert_eq!(io::ErrorKind::Interrupted, error.kind());
+        assert!(!dir.join("copy").exists());
+        assert_eq!(COPY_BUFFER_SIZE as u64 * 3, copy_file(&dir.join("source"), &dir.join("copy"), &|| false).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+//! Option parsing for builtin commands.
+//!
+//! Builtins declare the flags they understand as a list of `OptionSpec`s and
+//! get back the matched options plus the remaining positional arguments in
+//! their original order. Supported forms are combined short flags (`-rf`),
+//! short flags with a value (`-n 10`, `-n10`), long flags (`--all`), long
+//! flags with a value (`--sort=time`, `--sort time`) and `--`, which ends
+//! option parsing. A lone `-` is a positional argument.
+
+use std::fmt;
+
+pub struct OptionSpec {
+    /// Key used to look the option up after parsing.
+    pub name: &'static str,
+    pub short: Option<char>,
+    pub long: Option<&'static str>,
+    pub takes_value: bool,
+}
+
+impl OptionSpec {
+    pub const fn flag(name: &'static str, short: Option<char>, long: Option<&'static str>) -> OptionSpec {
+        OptionSpec { name, short, long, takes_value: false }
+    }
+
+    pub const fn value(name: &'static str, short: Option<char>, long: Option<&'static str>) -> OptionSpec {
+        OptionSpec { name, short, long, takes_value: true }
+    }
+}
+
+/// Every builtin understands `--help`.
+const HELP: OptionSpec = OptionSpec::flag("help", None, Some("help"));
+
+#[derive(Debug, Default)]
+pub struct ParsedOptions {
+    options: Vec<(&'static str, Option<String>)>,
+    pub arguments: Vec<String>,
+}
+
+impl ParsedOptions {
+    pub fn is_set(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| *option == name)
+    }
+
+    /// Value of the last occurrence of an option.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| *option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum OptionError {
+    Unknown(String),
+    MissingValue(String),
+    UnexpectedValue(String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptionError::Unknown(option) => write!(f, "unknown option '{option}'"),
+            OptionError::MissingValue(option) => write!(f, "option '{option}' requires a value"),
+            OptionError::UnexpectedValue(option) => write!(f, "option '{option}' doesn't take a value"),
+        }
+    }
+}
+
+pub fn parse(specs: &[OptionSpec], args: &[String]) -> Result<ParsedOptions, OptionError> {
+    let find_short = |c: char| specs.iter().chain([&HELP]).find(|spec| spec.short == Some(c));
+    let find_long = |name: &str| specs.iter().chain([&HELP]).find(|spec| spec.long == Some(name));
+
+    let mut parsed = ParsedOptions::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.arguments.extend(args.cloned());
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, inline_value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = find_long(name).ok_or_else(|| OptionError::Unknown(format!("--{name}")))?;
+            let value = match (spec.takes_value, inline_value) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(args.next().cloned().ok_or_else(|| OptionError::MissingValue(format!("--{name}")))?),
+                (false, Some(_)) => return Err(OptionError::UnexpectedValue(format!("--{name}"))),
+                (false, None) => None,
+            };
+            parsed.options.push((spec.name, value));
+        } else if arg.len() > 1 && arg.starts_with('-') {
+            let flags = &arg[1..];
+            for (index, c) in flags.char_indices() {
+                let spec = find_short(c).ok_or_else(|| OptionError::Unknown(format!("-{c}")))?;
+                if !spec.takes_value {
+                    parsed.options.push((spec.name, None));
+                    continue;
+                }
+                // The rest of the word is the value, otherwise the next argument is.
+                let rest = &flags[index + c.len_utf8()..];
+                let value = if rest.is_empty() {
+                    args.next().cloned().ok_or_else(|| OptionError::MissingValue(format!("-{c}")))?
+                } else {
+                    rest.to_string()
+                };
+                parsed.options.push((spec.name, Some(value)));
+                break;
+            }
+        } else {
+            parsed.arguments.push(arg.clone());
+        }
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod option_parsing_tests {
+    use super::*;
+
+    const SPECS: &[OptionSpec] = &[
+        OptionSpec::flag("recursive", Some('r'), Some("recursive")),
+        OptionSpec::flag("force", Some('f'), Some("force")),
+        OptionSpec::value("count", Some('n'), None),
+        OptionSpec::value("sort", None, Some("sort")),
+    ];
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn combined_short_flags() {
+        let options = parse(SPECS, &args(&["-rf", "dir"])).unwrap();
+        assert!(options.is_set("recursive"));
+        assert!(options.is_set("force"));
+        assert_eq!(args(&["dir"]), options.arguments);
+    }
+
+    #[test]
+    fn option_values() {
+        let options = parse(SPECS, &args(&["-n", "10", "--sort=time"])).unwrap();
+        assert_eq!(Some("10"), options.value("count"));
+        assert_eq!(Some("time"), options.value("sort"));
+
+        let options = parse(SPECS, &args(&["-rn5", "--sort", "size"])).unwrap();
+        assert!(options.is_set("recursive"));
+        assert_eq!(Some("5"), options.value("count"));
+        assert_eq!(Some("size"), options.value("sort"));
+    }
+
+    #[test]
+    fn double_dash_ends_options() {
+        let options = parse(SPECS, &args(&["-r", "--", "-weird-file", "--force"])).unwrap();
+        assert!(options.is_set("recursive"));
+        assert!(!options.is_set("force"));
+        assert_eq!(args(&["-weird-file", "--force"]), options.arguments);
+    }
+
+    #[test]
+    fn positional_order_is_kept() {
+        let options = parse(SPECS, &args(&["b", "-r", "a", "-", "c"])).unwrap();
+        assert_eq!(args(&["b", "a", "-", "c"]), options.arguments);
+    }
+
+    #[test]
+    fn help_is_always_known() {
+        assert!(parse(&[], &args(&["--help"])).unwrap().is_set("help"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(OptionError::Unknown(String::from("-x")), parse(SPECS, &args(&["-rx"])).unwrap_err());
+        assert_eq!(OptionError::Unknown(String::from("--all")), parse(SPECS, &args(&["--all"])).unwrap_err());
+        assert_eq!(OptionError::MissingValue(String::from("-n")), parse(SPECS, &args(&["-n"])).unwrap_err());
+        assert_eq!(OptionError::UnexpectedValue(String::from("--force")),
+                   parse(SPECS, &args(&["--force=yes"])).unwrap_err());
+    }
+}
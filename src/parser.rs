@@ -0,0 +1,990 @@
+//! Builds commands out of the tokens produced by the lexer.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::lexer::{self, Operator, ParseError, RedirectKind, Token, Word, WordPart};
+use crate::variables;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Redirect {
+    /// Descriptor being redirected: 0, 1 or 2.
+    pub fd: u32,
+    pub kind: RedirectKind,
+    /// File name, or descriptor number for `>&`.
+    pub target: Word,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleCommand {
+    /// `NAME=value` words in front of the command.
+    pub assignments: Vec<Assignment>,
+    pub words: Vec<Word>,
+    /// Applied from left to right before the command runs.
+    pub redirects: Vec<Redirect>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub name: String,
+    pub value: Word,
+}
+
+/// `if`, loops, `case` and `{ list; }`, holding lists of commands.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CompoundCommand {
+    /// `{ list; }`, run in the shell itself.
+    Group(CommandList),
+    /// `if` and `elif` conditions with the list run when they succeed, and
+    /// the `else` list.
+    If { branches: Vec<(CommandList, CommandList)>, otherwise: Option<CommandList> },
+    /// `while`, or `until` which runs the body while the condition fails.
+    While { condition: CommandList, body: CommandList, until: bool },
+    /// `for name in words`. Without `in` the words are the positional
+    /// parameters.
+    For { variable: String, words: Option<Vec<Word>>, body: CommandList },
+    Case { word: Word, arms: Vec<CaseArm> },
+}
+
+/// `pattern | pattern) commands ;;`, a branch of `case`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseArm {
+    pub patterns: Vec<Word>,
+    pub body: CommandList,
+}
+
+/// `name() compound-command`, or `function name compound-command`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub name: String,
+    pub body: CompoundCommand,
+    /// Applied every time the function is called.
+    pub redirects: Vec<Redirect>,
+}
+
+/// A stage of a pipeline.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PipelineCommand {
+    Simple(SimpleCommand),
+    /// A compound command with the redirections after its end, which apply
+    /// to all the commands in it.
+    Compound(CompoundCommand, Vec<Redirect>),
+    /// Defines a function when run. Shared, as the shell keeps it after the
+    /// command is gone.
+    Function(Arc<FunctionDefinition>),
+}
+
+/// Commands connected with `|`, the output of each one feeds the next.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pipeline {
+    pub commands: Vec<PipelineCommand>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Connector {
+    /// `&&`, run the next pipeline if the previous one succeeded.
+    And,
+    /// `||`, run the next pipeline if the previous one failed.
+    Or,
+}
+
+/// Pipelines joined with `&&` and `||`, evaluated from left to right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AndOrList {
+    pub first: Pipeline,
+    pub rest: Vec<(Connector, Pipeline)>,
+    /// Ended with `&`: runs as a job while the next commands go on.
+    pub background: bool,
+}
+
+/// `;` and `&` separated lists, run one after another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandList {
+    pub items: Vec<AndOrList>,
+}
+
+/// Parses a command line. Blank input gives `None`.
+pub fn parse(input: &str) -> Result<Option<CommandList>, ParseError> {
+    parse_with_aliases(input, &BTreeMap::new())
+}
+
+/// Parses a command line, replacing command names by their alias.
+pub fn parse_with_aliases(input: &str, aliases: &BTreeMap<String, String>)
+    -> Result<Option<CommandList>, ParseError> {
+    let tokens = lexer::tokenize(input)?;
+    let origins = vec![AliasOrigin::default(); tokens.len()];
+    let mut parser = Parser { tokens, origins, aliases, position: 0 };
+    parser.skip_newlines();
+    if parser.peek().is_none() {
+        return Ok(None);
+    }
+    let list = parser.parse_command_list()?;
+    match parser.peek() {
+        Some(token) => Err(unexpected(token)),
+        None => Ok(Some(list)),
+    }
+}
+
+/// Where a token comes from when aliases were expanded.
+#[derive(Clone, Default)]
+struct AliasOrigin {
+    /// Aliases whose value holds the token, which are not expanded again in
+    /// it.
+    aliases: Vec<String>,
+    /// The token follows an alias ending with a blank, so it may be an alias
+    /// too.
+    after_blank: bool,
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    /// Origin of every token of `tokens`.
+    origins: Vec<AliasOrigin>,
+    aliases: &'a BTreeMap<String, String>,
+    position: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next_is(&self, operator: Operator) -> bool {
+        self.peek() == Some(&Token::Operator(operator))
+    }
+
+    /// Whether the next token is the reserved word `name`. Reserved words
+    /// are only recognized unquoted and where a command starts.
+    fn next_is_reserved(&self, name: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => matches!(word.parts.as_slice(), [WordPart::Literal(text)] if text == name),
+            _ => false,
+        }
+    }
+
+    /// Skips the reserved word `name`, which has to come next.
+    fn expect_reserved(&mut self, name: &str) -> Result<(), ParseError> {
+        if self.next_is_reserved(name) {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => unexpected(token),
+            None => ParseError::UnexpectedEnd,
+        })
+    }
+
+    fn expect_operator(&mut self, operator: Operator) -> Result<(), ParseError> {
+        if self.next_is(operator) {
+            self.position += 1;
+            return Ok(());
+        }
+        Err(match self.peek() {
+            Some(token) => unexpected(token),
+            None => ParseError::UnexpectedEnd,
+        })
+    }
+
+    /// Blank lines may stand between commands and after `&&`, `||` and `|`.
+    fn skip_newlines(&mut self) {
+        while self.next_is(Operator::Newline) {
+            self.position += 1;
+        }
+    }
+
+    /// Whether the next token ends a list: the end of the input, or what
+    /// closes the compound command the list is in.
+    fn at_list_end(&self) -> bool {
+        self.peek().is_none()
+            || self.next_is(Operator::DoubleSemicolon)
+            || self.next_is(Operator::RightParen)
+            || LIST_TERMINATORS.iter().any(|name| self.next_is_reserved(name))
+    }
+
+    fn parse_command_list(&mut self) -> Result<CommandList, ParseError> {
+        let mut items = vec![self.parse_and_or()?];
+        while self.next_is(Operator::Semicolon) || self.next_is(Operator::Newline) || self.next_is(Operator::Background) {
+            if self.next_is(Operator::Background) {
+                if let Some(item) = items.last_mut() {
+                    item.background = true;
+                }
+            }
+            self.position += 1;
+            self.skip_newlines();
+            // A trailing `;` ends the list.
+            if self.at_list_end() {
+                break;
+            }
+            items.push(self.parse_and_or()?);
+        }
+        Ok(CommandList { items })
+    }
+
+    /// The commands inside a compound command, which may start with blank
+    /// lines.
+    fn parse_body(&mut self) -> Result<CommandList, ParseError> {
+        self.skip_newlines();
+        self.parse_command_list()
+    }
+
+    fn parse_and_or(&mut self) -> Result<AndOrList, ParseError> {
+        let first = self.parse_pipeline()?;
+        let mut rest = Vec::new();
+        loop {
+            let connector = if self.next_is(Operator::And) {
+                Connector::And
+            } else if self.next_is(Operator::Or) {
+                Connector::Or
+            } else {
+                break;
+            };
+            self.position += 1;
+            self.skip_newlines();
+            rest.push((connector, self.parse_pipeline()?));
+        }
+        Ok(AndOrList { first, rest, background: false })
+    }
+
+    fn parse_pipeline(&mut self) -> Result<Pipeline, ParseError> {
+        let mut commands = vec![self.parse_pipeline_command()?];
+        while self.next_is(Operator::Pipe) {
+            self.position += 1;
+            self.skip_newlines();
+            commands.push(self.parse_pipeline_command()?);
+        }
+        Ok(Pipeline { commands })
+    }
+
+    fn parse_pipeline_command(&mut self) -> Result<PipelineCommand, ParseError> {
+        // An alias may stand for a reserved word.
+        while self.expand_alias()? {}
+        let defines_function = self.next_is_reserved("function")
+            || matches!(self.tokens.get(self.position + 1), Some(Token::Operator(Operator::LeftParen)));
+        if defines_function {
+            return self.parse_function();
+        }
+        if let Some(name) = LIST_TERMINATORS.iter().find(|name| self.next_is_reserved(name)) {
+            return Err(ParseError::UnexpectedToken(name.to_string()));
+        }
+        match self.parse_compound()? {
+            Some(compound) => Ok(PipelineCommand::Compound(compound, self.parse_trailing_redirects()?)),
+            None => Ok(PipelineCommand::Simple(self.parse_simple_command()?)),
+        }
+    }
+
+    /// The compound command starting at the current position, `None` when
+    /// there is none.
+    fn parse_compound(&mut self) -> Result<Option<CompoundCommand>, ParseError> {
+        let compound = if self.next_is_reserved("if") {
+            self.parse_if()?
+        } else if self.next_is_reserved("while") || self.next_is_reserved("until") {
+            self.parse_while()?
+        } else if self.next_is_reserved("for") {
+            self.parse_for()?
+        } else if self.next_is_reserved("case") {
+            self.parse_case()?
+        } else if self.next_is_reserved("{") {
+            self.position += 1;
+            let body = self.parse_body()?;
+            self.expect_reserved("}")?;
+            CompoundCommand::Group(body)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(compound))
+    }
+
+    /// Redirections after the end of a compound command.
+    fn parse_trailing_redirects(&mut self) -> Result<Vec<Redirect>, ParseError> {
+        let mut redirects = Vec::new();
+        while let Some(Token::Operator(Operator::Redirect(fd, kind))) = self.peek() {
+            let (fd, kind) = (*fd, *kind);
+            self.position += 1;
+            redirects.push(self.parse_redirect(fd, kind)?);
+        }
+        Ok(redirects)
+    }
+
+    /// `name() compound-command` or `function name [()] compound-command`,
+    /// with the redirections of the function after it.
+    fn parse_function(&mut self) -> Result<PipelineCommand, ParseError> {
+        let keyword = self.next_is_reserved("function");
+        if keyword {
+            self.position += 1;
+        }
+        let name = self.parse_name(is_function_name)?;
+        if !keyword || self.next_is(Operator::LeftParen) {
+            self.expect_operator(Operator::LeftParen)?;
+            self.expect_operator(Operator::RightParen)?;
+        }
+        self.skip_newlines();
+        let Some(body) = self.parse_compound()? else {
+            return Err(match self.peek() {
+                Some(token) => unexpected(token),
+                None => ParseError::UnexpectedEnd,
+            });
+        };
+        let redirects = self.parse_trailing_redirects()?;
+        Ok(PipelineCommand::Function(Arc::new(FunctionDefinition { name, body, redirects })))
+    }
+
+    /// A word naming a variable or function, accepted by `is_valid`.
+    fn parse_name(&mut self, is_valid: fn(&str) -> bool) -> Result<String, ParseError> {
+        let name = match self.peek() {
+            Some(Token::Word(word)) => match word.parts.as_slice() {
+                [WordPart::Literal(name)] if is_valid(name) => name.clone(),
+                _ => return Err(ParseError::UnexpectedToken(word.unquote())),
+            },
+            Some(token) => return Err(unexpected(token)),
+            None => return Err(ParseError::UnexpectedEnd),
+        };
+        self.position += 1;
+        Ok(name)
+    }
+
+    /// `if list; then list; [elif list; then list;]... [else list;] fi`
+    fn parse_if(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.position += 1;
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        loop {
+            let condition = self.parse_body()?;
+            self.expect_reserved("then")?;
+            branches.push((condition, self.parse_body()?));
+            if self.next_is_reserved("elif") {
+                self.position += 1;
+                continue;
+            }
+            if self.next_is_reserved("else") {
+                self.position += 1;
+                otherwise = Some(self.parse_body()?);
+            }
+            self.expect_reserved("fi")?;
+            return Ok(CompoundCommand::If { branches, otherwise });
+        }
+    }
+
+    /// `while list; do list; done` and `until list; do list; done`
+    fn parse_while(&mut self) -> Result<CompoundCommand, ParseError> {
+        let until = self.next_is_reserved("until");
+        self.position += 1;
+        let condition = self.parse_body()?;
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::While { condition, body, until })
+    }
+
+    /// `for name [in word...]; do list; done`
+    fn parse_for(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.position += 1;
+        let variable = self.parse_name(variables::is_valid_name)?;
+        self.skip_newlines();
+        let mut words = None;
+        if self.next_is_reserved("in") {
+            self.position += 1;
+            let mut list = Vec::new();
+            while let Some(Token::Word(word)) = self.peek() {
+                list.push(word.clone());
+                self.position += 1;
+            }
+            words = Some(list);
+            if !self.next_is(Operator::Newline) {
+                self.expect_operator(Operator::Semicolon)?;
+            }
+        } else if self.next_is(Operator::Semicolon) {
+            self.position += 1;
+        }
+        let body = self.parse_do_group()?;
+        Ok(CompoundCommand::For { variable, words, body })
+    }
+
+    /// `do list; done`, after blank lines.
+    fn parse_do_group(&mut self) -> Result<CommandList, ParseError> {
+        self.skip_newlines();
+        self.expect_reserved("do")?;
+        let body = self.parse_body()?;
+        self.expect_reserved("done")?;
+        Ok(body)
+    }
+
+    /// `case word in [(]pattern[|pattern]...) list;; ... esac`, the `;;` of
+    /// the last branch being optional.
+    fn parse_case(&mut self) -> Result<CompoundCommand, ParseError> {
+        self.position += 1;
+        let word = match self.peek() {
+            Some(Token::Word(word)) => word.clone(),
+            Some(token) => return Err(unexpected(token)),
+            None => return Err(ParseError::UnexpectedEnd),
+        };
+        self.position += 1;
+        self.skip_newlines();
+        self.expect_reserved("in")?;
+        let mut arms = Vec::new();
+        loop {
+            self.skip_newlines();
+            if self.next_is_reserved("esac") {
+                break;
+            }
+            if self.next_is(Operator::LeftParen) {
+                self.position += 1;
+            }
+            let mut patterns = Vec::new();
+            loop {
+                match self.peek() {
+                    Some(Token::Word(pattern)) => patterns.push(pattern.clone()),
+                    Some(token) => return Err(unexpected(token)),
+                    None => return Err(ParseError::UnexpectedEnd),
+                }
+                self.position += 1;
+                if !self.next_is(Operator::Pipe) {
+                    break;
+                }
+                self.position += 1;
+            }
+            self.expect_operator(Operator::RightParen)?;
+            self.skip_newlines();
+            let body = match self.at_list_end() {
+                true => CommandList { items: Vec::new() },
+                false => self.parse_command_list()?,
+            };
+            arms.push(CaseArm { patterns, body });
+            if !self.next_is(Operator::DoubleSemicolon) {
+                break;
+            }
+            self.position += 1;
+        }
+        self.skip_newlines();
+        self.expect_reserved("esac")?;
+        Ok(CompoundCommand::Case { word, arms })
+    }
+
+    /// Replaces the word at the current position by the tokens of its alias,
+    /// if it has one that is not being expanded already. Returns `true` when
+    /// it did.
+    fn expand_alias(&mut self) -> Result<bool, ParseError> {
+        let Some(Token::Word(word)) = self.peek() else { return Ok(false) };
+        let [WordPart::Literal(name)] = word.parts.as_slice() else { return Ok(false) };
+        let origin = &self.origins[self.position];
+        let Some(value) = self.aliases.get(name).filter(|_| !origin.aliases.contains(name)) else {
+            return Ok(false);
+        };
+        let tokens = lexer::tokenize(value)?;
+        let mut aliases = origin.aliases.clone();
+        aliases.push(name.clone());
+        let count = tokens.len();
+        let mut origins = vec![AliasOrigin { aliases, after_blank: false }; count];
+        // The value starts where the alias was, so its first word can be an
+        // alias as well.
+        if let Some(first) = origins.first_mut() {
+            first.after_blank = origin.after_blank;
+        }
+        self.tokens.splice(self.position..=self.position, tokens);
+        self.origins.splice(self.position..=self.position, origins);
+        if value.ends_with([' ', '\t']) {
+            if let Some(next) = self.origins.get_mut(self.position + count) {
+                next.after_blank = true;
+            }
+        }
+        Ok(true)
+    }
+
+    fn parse_simple_command(&mut self) -> Result<SimpleCommand, ParseError> {
+        let mut assignments = Vec::new();
+        let mut words = Vec::new();
+        let mut redirects = Vec::new();
+        loop {
+            let command_name = words.is_empty() || self.origins.get(self.position).is_some_and(|origin| origin.after_blank);
+            if command_name && self.expand_alias()? {
+                continue;
+            }
+            match self.peek() {
+                Some(Token::Word(word)) => {
+                    match split_assignment(word) {
+                        Some(assignment) if words.is_empty() => assignments.push(assignment),
+                        _ => words.push(word.clone()),
+                    }
+                    self.position += 1;
+                }
+                Some(Token::Operator(Operator::Redirect(fd, kind))) => {
+                    let (fd, kind) = (*fd, *kind);
+                    self.position += 1;
+                    redirects.push(self.parse_redirect(fd, kind)?);
+                }
+                _ => break,
+            }
+        }
+        if assignments.is_empty() && words.is_empty() && redirects.is_empty() {
+            return Err(match self.peek() {
+                Some(token) => unexpected(token),
+                None => ParseError::UnexpectedEnd,
+            });
+        }
+        Ok(SimpleCommand { assignments, words, redirects })
+    }
+
+    /// The target of a redirection operator just read.
+    fn parse_redirect(&mut self, fd: Option<u32>, kind: RedirectKind) -> Result<Redirect, ParseError> {
+        let target = match self.peek() {
+            Some(Token::Word(target)) => target.clone(),
+            Some(token) => return Err(unexpected(token)),
+            None => return Err(ParseError::UnexpectedEnd),
+        };
+        self.position += 1;
+        let default_fd = if kind == RedirectKind::Input { 0 } else { 1 };
+        Ok(Redirect { fd: fd.unwrap_or(default_fd), kind, target })
+    }
+}
+
+/// Reserved words ending the lists inside compound commands.
+const LIST_TERMINATORS: &[&str] = &["then", "elif", "else", "fi", "do", "done", "esac", "}"];
+
+/// Words with a meaning of their own at the start of a command.
+pub const RESERVED_WORDS: &[&str] =
+    &["if", "then", "elif", "else", "fi", "while", "until", "for", "in", "do", "done", "case", "esac", "function", "{", "}"];
+
+/// Names a function can have: letters, digits and `_-.:`, not starting with
+/// a digit.
+pub fn is_function_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(|c: char| c.is_ascii_digit())
+        && name.chars().all(|c| c.is_alphanumeric() || "_-.:".contains(c))
+}
+
+/// Recognizes `NAME=value`, where the name and `=` are written unquoted.
+fn split_assignment(word: &Word) -> Option<Assignment> {
+    let Some(WordPart::Literal(first)) = word.parts.first() else {
+        return None;
+    };
+    let (name, rest) = variables::split_assignment(first)?;
+    let mut value = Word::default();
+    if !rest.is_empty() {
+        value.parts.push(WordPart::Literal(rest.to_string()));
+    }
+    value.parts.extend(word.parts[1..].iter().cloned());
+    Some(Assignment { name: name.to_string(), value })
+}
+
+fn unexpected(token: &Token) -> ParseError {
+    match token {
+        Token::Word(word) => ParseError::UnexpectedToken(word.unquote()),
+        Token::Operator(operator) => ParseError::UnexpectedToken(operator.to_string()),
+    }
+}
+
+/// The commands written back on one line, as jobs are listed. A trailing
+/// `&` is left out.
+impl fmt::Display for AndOrList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = String::new();
+        write_and_or(self, 0, &mut text);
+        f.write_str(&text)
+    }
+}
+
+impl fmt::Display for Pipeline {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = String::new();
+        write_pipeline(self, 0, &mut text);
+        f.write_str(&text)
+    }
+}
+
+/// The definition written back as commands, the way `type` shows it and
+/// functions are exported.
+impl fmt::Display for FunctionDefinition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut text = format!("{} ()\n", self.name);
+        write_compound(&self.body, 0, &mut text);
+        write_redirects(&self.redirects, &mut text);
+        f.write_str(&text)
+    }
+}
+
+const INDENT: &str = "    ";
+
+/// Writes every command of the list on a line of its own.
+fn write_list(list: &CommandList, indent: usize, text: &mut String) {
+    for item in &list.items {
+        text.push_str(&INDENT.repeat(indent));
+        write_and_or(item, indent, text);
+        if item.background {
+            text.push_str(" &");
+        }
+        text.push('\n');
+    }
+}
+
+/// Writes the list on one line, as the conditions of `if` and loops.
+fn write_inline_list(list: &CommandList, indent: usize, text: &mut String) {
+    for (index, item) in list.items.iter().enumerate() {
+        if index > 0 {
+            text.push_str(if list.items[index - 1].background { " " } else { "; " });
+        }
+        write_and_or(item, indent, text);
+        if item.background {
+            text.push_str(" &");
+        }
+    }
+}
+
+fn write_and_or(list: &AndOrList, indent: usize, text: &mut String) {
+    write_pipeline(&list.first, indent, text);
+    for (connector, pipeline) in &list.rest {
+        text.push_str(match connector {
+            Connector::And => " && ",
+            Connector::Or => " || ",
+        });
+        write_pipeline(pipeline, indent, text);
+    }
+}
+
+fn write_pipeline(pipeline: &Pipeline, indent: usize, text: &mut String) {
+    for (index, command) in pipeline.commands.iter().enumerate() {
+        if index > 0 {
+            text.push_str(" | ");
+        }
+        match command {
+            PipelineCommand::Simple(simple) => {
+                let assignments = simple.assignments.iter().map(|assignment| {
+                    format!("{}={}", assignment.name, assignment.value.source())
+                });
+                let words = simple.words.iter().map(Word::source);
+                text.push_str(&assignments.chain(words).collect::<Vec<_>>().join(" "));
+                write_redirects(&simple.redirects, text);
+            }
+            PipelineCommand::Compound(compound, redirects) => {
+                write_compound(compound, indent, text);
+                write_redirects(redirects, text);
+            }
+            PipelineCommand::Function(function) => {
+                text.push_str(&function.name);
+                text.push_str(" () ");
+                write_compound(&function.body, indent, text);
+                write_redirects(&function.redirects, text);
+            }
+        }
+    }
+}
+
+fn write_compound(compound: &CompoundCommand, indent: usize, text: &mut String) {
+    let padding = INDENT.repeat(indent);
+    match compound {
+        CompoundCommand::Group(body) => {
+            text.push_str("{\n");
+            write_list(body, indent + 1, text);
+            text.push_str(&padding);
+            text.push('}');
+        }
+        CompoundCommand::If { branches, otherwise } => {
+            for (index, (condition, body)) in branches.iter().enumerate() {
+                if index == 0 {
+                    text.push_str("if ");
+                } else {
+                    text.push_str(&padding);
+                    text.push_str("elif ");
+                }
+                write_inline_list(condition, indent, text);
+                text.push_str("; then\n");
+                write_list(body, indent + 1, text);
+            }
+            if let Some(body) = otherwise {
+                text.push_str(&padding);
+                text.push_str("else\n");
+                write_list(body, indent + 1, text);
+            }
+            text.push_str(&padding);
+            text.push_str("fi");
+        }
+        CompoundCommand::While { condition, body, until } => {
+            text.push_str(if *until { "until " } else { "while " });
+            write_inline_list(condition, indent, text);
+            text.push_str("; do\n");
+            write_list(body, indent + 1, text);
+            text.push_str(&padding);
+            text.push_str("done");
+        }
+        CompoundCommand::For { variable, words, body } => {
+            text.push_str("for ");
+            text.push_str(variable);
+            if let Some(words) = words {
+                text.push_str(" in");
+                for word in words {
+                    text.push(' ');
+                    text.push_str(&word.source());
+                }
+            }
+            text.push_str("; do\n");
+            write_list(body, indent + 1, text);
+            text.push_str(&padding);
+            text.push_str("done");
+        }
+        CompoundCommand::Case { word, arms } => {
+            text.push_str(&format!("case {} in\n", word.source()));
+            for arm in arms {
+                let patterns: Vec<String> = arm.patterns.iter().map(Word::source).collect();
+                text.push_str(&format!("{padding}{INDENT}{})\n", patterns.join(" | ")));
+                write_list(&arm.body, indent + 2, text);
+                text.push_str(&format!("{padding}{INDENT};;\n"));
+            }
+            text.push_str(&padding);
+            text.push_str("esac");
+        }
+    }
+}
+
+fn write_redirects(redirects: &[Redirect], text: &mut String) {
+    for redirect in redirects {
+        let default_fd = if redirect.kind == RedirectKind::Input { 0 } else { 1 };
+        let fd = Some(redirect.fd).filter(|&fd| fd != default_fd);
+        text.push_str(&format!(" {}{}", Operator::Redirect(fd, redirect.kind), redirect.target.source()));
+    }
+}
+
+#[cfg(test)]
+mod parser_tests {
+    use super::*;
+
+    fn simple(command: &PipelineCommand) -> &SimpleCommand {
+        match command {
+            PipelineCommand::Simple(command) => command,
+            _ => panic!("not a simple command"),
+        }
+    }
+
+    fn command_names(pipeline: &Pipeline) -> Vec<String> {
+        pipeline.commands.iter().map(|command| simple(command).words[0].unquote()).collect()
+    }
+
+    /// Parses a line made of a single pipeline of simple commands.
+    fn parse_pipeline(input: &str) -> Result<Vec<SimpleCommand>, ParseError> {
+        let mut list = parse(input)?.expect("input is blank");
+        assert_eq!(1, list.items.len());
+        let and_or = list.items.remove(0);
+        assert!(and_or.rest.is_empty());
+        Ok(and_or.first.commands.iter().map(|command| simple(command).clone()).collect())
+    }
+
+    /// Parses a line made of a single compound command.
+    fn parse_compound(input: &str) -> CompoundCommand {
+        let list = parse(input).unwrap().expect("input is blank");
+        match &list.items[0].first.commands[0] {
+            PipelineCommand::Compound(compound, _) => compound.clone(),
+            _ => panic!("not a compound command"),
+        }
+    }
+
+    #[test]
+    fn blank_input() {
+        assert_eq!(None, parse("  \n").unwrap());
+    }
+
+    #[test]
+    fn lines_and_comments() {
+        let list = parse("# setup\necho a # note\n\necho b &&\n  echo c |\n wc\n").unwrap().unwrap();
+        assert_eq!(2, list.items.len());
+        assert_eq!(1, simple(&list.items[0].first.commands[0]).words.len() - 1);
+        assert_eq!(2, list.items[1].rest[0].1.commands.len());
+        assert_eq!(None, parse("# only a comment\n").unwrap());
+        assert!(parse("echo 'open\n").unwrap_err().is_incomplete());
+        assert!(parse("echo a &&\n").unwrap_err().is_incomplete());
+        assert!(!parse("echo a ;;\n").unwrap_err().is_incomplete());
+    }
+
+    #[test]
+    fn pipeline_stages() {
+        let pipeline = parse_pipeline("cat file|grep x | wc -l").unwrap();
+        assert_eq!(vec!["cat", "grep", "wc"], pipeline.iter().map(|command| command.words[0].unquote()).collect::<Vec<_>>());
+        assert_eq!(2, pipeline[2].words.len());
+    }
+
+    #[test]
+    fn quoted_pipe_is_a_word() {
+        let pipeline = parse_pipeline("echo 'a|b' \\|").unwrap();
+        assert_eq!(1, pipeline.len());
+        assert_eq!(3, pipeline[0].words.len());
+    }
+
+    #[test]
+    fn redirections() {
+        let pipeline = parse_pipeline("sort <in 2>>log >out 2>&1 x &> all").unwrap();
+        let command = &pipeline[0];
+        assert_eq!(2, command.words.len());
+        let redirects: Vec<(u32, RedirectKind, String)> = command
+            .redirects
+            .iter()
+            .map(|redirect| (redirect.fd, redirect.kind, redirect.target.unquote()))
+            .collect();
+        assert_eq!(vec![
+            (0, RedirectKind::Input, String::from("in")),
+            (2, RedirectKind::Append, String::from("log")),
+            (1, RedirectKind::Output, String::from("out")),
+            (2, RedirectKind::Duplicate, String::from("1")),
+            (1, RedirectKind::OutputAll, String::from("all")),
+        ], redirects);
+    }
+
+    #[test]
+    fn quoted_digits_are_not_descriptors() {
+        let pipeline = parse_pipeline("echo '2'>file a2>b").unwrap();
+        let command = &pipeline[0];
+        assert_eq!(vec!["echo", "2", "a2"], command.words.iter().map(Word::unquote).collect::<Vec<_>>());
+        assert_eq!(vec![1, 1], command.redirects.iter().map(|redirect| redirect.fd).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn redirection_needs_a_target() {
+        assert_eq!(Err(ParseError::UnexpectedEnd), parse("echo >"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("|"))), parse("echo > | cat"));
+    }
+
+    #[test]
+    fn empty_stages_are_errors() {
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("|"))), parse("| cat"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("|"))), parse("ls | | cat"));
+        assert_eq!(Err(ParseError::UnexpectedEnd), parse("ls |"));
+    }
+
+    #[test]
+    fn and_or_lists() {
+        let list = parse("make && make test || echo failed; ls ;").unwrap().unwrap();
+        assert_eq!(2, list.items.len());
+        let and_or = &list.items[0];
+        assert_eq!(vec!["make"], command_names(&and_or.first));
+        let connectors: Vec<Connector> = and_or.rest.iter().map(|(connector, _)| *connector).collect();
+        assert_eq!(vec![Connector::And, Connector::Or], connectors);
+        assert_eq!(vec!["echo"], command_names(&and_or.rest[1].1));
+        assert_eq!(vec!["ls"], command_names(&list.items[1].first));
+    }
+
+    #[test]
+    fn background_lists() {
+        let list = parse("make && make test & ls\nsleep 1 &").unwrap().unwrap();
+        assert_eq!(vec![true, false, true], list.items.iter().map(|item| item.background).collect::<Vec<_>>());
+        assert_eq!("make && make test", list.items[0].to_string());
+        assert_eq!(vec!["echo", "a&b"], words_of("echo 'a&b'"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("&"))), parse("& ls"));
+    }
+
+    #[test]
+    fn pipes_bind_tighter_than_and_or() {
+        let list = parse("a | b && c | d").unwrap().unwrap();
+        let and_or = &list.items[0];
+        assert_eq!(vec!["a", "b"], command_names(&and_or.first));
+        assert_eq!(vec!["c", "d"], command_names(&and_or.rest[0].1));
+    }
+
+    #[test]
+    fn list_errors() {
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from(";"))), parse("; ls"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from(";;"))), parse("ls ;; ls"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("&&"))), parse("ls && && ls"));
+        assert_eq!(Err(ParseError::UnexpectedEnd), parse("ls ||"));
+    }
+
+    #[test]
+    fn assignments_before_the_command() {
+        let pipeline = parse_pipeline("A=1 B='x y' env C=2").unwrap();
+        let command = &pipeline[0];
+        let assignments: Vec<(&str, String)> = command
+            .assignments
+            .iter()
+            .map(|assignment| (assignment.name.as_str(), assignment.value.unquote()))
+            .collect();
+        assert_eq!(vec![("A", String::from("1")), ("B", String::from("x y"))], assignments);
+        assert_eq!(vec!["env", "C=2"], command.words.iter().map(Word::unquote).collect::<Vec<_>>());
+        assert_eq!(1, parse_pipeline("X=").unwrap()[0].assignments.len());
+    }
+
+    #[test]
+    fn compound_commands() {
+        let CompoundCommand::If { branches, otherwise } =
+            parse_compound("if a; then b; elif c\nthen d; e\nelse f; fi > out")
+        else {
+            panic!("not an if")
+        };
+        assert_eq!(2, branches.len());
+        assert_eq!(2, branches[1].1.items.len());
+        assert!(otherwise.is_some());
+        let CompoundCommand::For { variable, words, body } = parse_compound("for x in a 'b c'\ndo\n  echo $x\ndone")
+        else {
+            panic!("not a for")
+        };
+        assert_eq!(("x", 2, 1), (variable.as_str(), words.unwrap().len(), body.items.len()));
+        let CompoundCommand::Case { arms, .. } = parse_compound("case $1 in (a|b) x;; *.rs) ;; *) y\nesac") else {
+            panic!("not a case")
+        };
+        assert_eq!(vec![2, 1, 1], arms.iter().map(|arm| arm.patterns.len()).collect::<Vec<_>>());
+        assert!(arms[1].body.items.is_empty());
+        assert!(matches!(parse_compound("until a; do b; done"), CompoundCommand::While { until: true, .. }));
+    }
+
+    #[test]
+    fn compound_command_errors() {
+        assert!(parse("if a; then b").unwrap_err().is_incomplete());
+        assert!(parse("while a\ndo\n").unwrap_err().is_incomplete());
+        assert!(parse("case x in a)").unwrap_err().is_incomplete());
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("fi"))), parse("if a; then fi"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("done"))), parse("echo; done"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("1x"))), parse("for 1x in a; do b; done"));
+        assert_eq!(vec!["echo", "if", "done"], words_of("echo if done"));
+    }
+
+    fn parse_function(input: &str) -> Arc<FunctionDefinition> {
+        let list = parse(input).unwrap().expect("input is blank");
+        match &list.items[0].first.commands[0] {
+            PipelineCommand::Function(function) => Arc::clone(function),
+            _ => panic!("not a function definition"),
+        }
+    }
+
+    #[test]
+    fn functions() {
+        let greet = parse_function("greet() { echo \"hi $1\"; }");
+        assert_eq!("greet", greet.name);
+        assert!(matches!(&greet.body, CompoundCommand::Group(body) if body.items.len() == 1));
+        let quiet = parse_function("function quiet\n{\n  ls\n} 2> /dev/null");
+        assert_eq!(("quiet", 1), (quiet.name.as_str(), quiet.redirects.len()));
+        assert!(matches!(parse_function("f () if a; then b; fi").body, CompoundCommand::If { .. }));
+        assert!(parse("f() {").unwrap_err().is_incomplete());
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("echo"))), parse("f() echo"));
+        assert_eq!(Err(ParseError::UnexpectedToken(String::from("}"))), parse("}"));
+    }
+
+    #[test]
+    fn functions_read_back() {
+        let source = "f() { for x; do case \"$x\" in a|'b c') echo \"${x%.rs}\" $(pwd) >&2;; esac; done; \
+                      while ! true && [ -f x ]; do :; done; if a; then b; elif c; then d; else e; fi | wc -l > 'o u'; }";
+        let function = parse_function(source);
+        assert_eq!(function, parse_function(&function.to_string()));
+    }
+
+    fn words_of(input: &str) -> Vec<String> {
+        parse_pipeline(input).unwrap()[0].words.iter().map(Word::unquote).collect()
+    }
+
+    #[test]
+    fn aliases() {
+        let aliases: BTreeMap<String, String> = [
+            ("ll", "ls -l"),
+            ("ls", "ls --color"),
+            ("loop", "loop2 x"),
+            ("loop2", "loop"),
+            ("sudo", "sudo "),
+            ("both", "echo a; echo b |"),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+        let words = |input: &str| {
+            let list = parse_with_aliases(input, &aliases).unwrap().unwrap();
+            let commands = &list.items.last().unwrap().first.commands;
+            commands.iter().map(|command| simple(command).words.iter().map(Word::unquote).collect::<Vec<_>>().join(" ")).collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["ls --color -l /tmp"], words("ll /tmp"));
+        assert_eq!(vec!["loop x"], words("loop"));
+        assert_eq!(vec!["sudo ls --color -l"], words("sudo ll"));
+        assert_eq!(vec!["echo ll"], words("echo ll"));
+        assert_eq!(vec!["ll"], words("'ll'"));
+        assert_eq!(vec!["echo b", "wc"], words("both wc"));
+    }
+}
@@ -0,0 +1,172 @@
+//! Shell pattern matching: `*` matches any string, `?` any character and
+//! `[...]` one character out of a set. A backslash makes the next character
+//! match literally.
+
+/// Checks if the whole text matches the pattern.
+pub fn matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches_from(&pattern, &text)
+}
+
+fn matches_from(pattern: &[char], text: &[char]) -> bool {
+    // Position to go back to after the last `*`, for backtracking.
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+                continue;
+            }
+            Some('?') => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            Some('[') => {
+                if let Some((matched, length)) = match_bracket(&pattern[p..], text[t]) {
+                    if matched {
+                        p += length;
+                        t += 1;
+                        continue;
+                    }
+                } else if text[t] == '[' {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+            }
+            Some('\\') if p + 1 < pattern.len() && pattern[p + 1] == text[t] => {
+                p += 2;
+                t += 1;
+                continue;
+            }
+            Some('\\') if p + 1 < pattern.len() => (),
+            Some(&c) if c == text[t] => {
+                p += 1;
+                t += 1;
+                continue;
+            }
+            _ => (),
+        }
+        match star {
+            Some((star_p, star_t)) => {
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+            None => return false,
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Matches a character against a bracket expression at the start of the
+/// pattern. Gives whether it matched and the length of the expression, or
+/// `None` if the bracket is not closed.
+fn match_bracket(pattern: &[char], c: char) -> Option<(bool, usize)> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!') | Some('^'));
+    if negated {
+        i += 1;
+    }
+    let mut matched = false;
+    let mut first = true;
+    loop {
+        let mut low = *pattern.get(i)?;
+        if low == ']' && !first {
+            return Some((matched != negated, i + 1));
+        }
+        first = false;
+        if low == '\\' {
+            i += 1;
+            low = *pattern.get(i)?;
+        }
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&high| high != ']') {
+            let high = pattern[i + 2];
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= low == c;
+            i += 1;
+        }
+    }
+}
+
+/// True if the pattern has unescaped special characters.
+pub fn has_wildcards(pattern: &str) -> bool {
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Removes the backslashes of a pattern without wildcards, giving the text
+/// it matches.
+pub fn unescape(pattern: &str) -> String {
+    let mut text = String::with_capacity(pattern.len());
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.extend(chars.next()),
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+/// Escapes the special characters of a text so it matches only itself.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod pattern_tests {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(!matches("*.rs", "main.rs.bak"));
+        assert!(matches("a*b*c", "aXXbYYc"));
+        assert!(matches("?at", "cat"));
+        assert!(!matches("?at", "at"));
+        assert!(matches("*", ""));
+    }
+
+    #[test]
+    fn brackets() {
+        assert!(matches("[a-c]x", "bx"));
+        assert!(!matches("[a-c]x", "dx"));
+        assert!(matches("[!a-c]x", "dx"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("file[0-9][0-9]", "file42"));
+        assert!(matches("[ab", "[ab"));
+    }
+
+    #[test]
+    fn escapes() {
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "x"));
+        assert!(matches(&escape("a*[b]"), "a*[b]"));
+        assert!(!has_wildcards(&escape("a*?[b]")));
+        assert!(has_wildcards("src/*.rs"));
+        assert_eq!("a*[b]", unescape(&escape("a*[b]")));
+    }
+}
@@ -0,0 +1,45 @@
+//! Operating system specific pieces of the shell: user lookup, home directory,
+//! executable search and terminal control. Every backend exposes the same set
+//! of functions.
+
+use std::cell::Cell;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use unix::*;
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use windows::*;
+
+/// What happened to a program being waited for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProcessEvent {
+    /// Ended with this status, 128 plus the signal number when killed.
+    Exited(i32),
+    /// Stopped by a signal, like Ctrl-Z. It can be continued.
+    Stopped,
+}
+
+thread_local! {
+    /// Set on the threads running background jobs, which Ctrl-C doesn't
+    /// reach.
+    static IGNORES_INTERRUPTS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether Ctrl-C was pressed for the commands running on this thread, which
+/// have to stop then.
+pub fn interrupted() -> bool {
+    !IGNORES_INTERRUPTS.get() && interrupt_pending()
+}
+
+/// Keeps Ctrl-C from stopping the commands of this thread.
+pub fn ignore_interrupts() {
+    IGNORES_INTERRUPTS.set(true);
+}
+
+pub fn ignores_interrupts() -> bool {
+    IGNORES_INTERRUPTS.get()
+}
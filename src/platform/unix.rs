@@ -0,0 +1,383 @@
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::{CommandExt, ExitStatusExt};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+use std::{env, fs, io, mem, ptr};
+
+use super::ProcessEvent;
+
+/// Name of the variable holding the executable search path.
+pub const PATH_VARIABLE: &str = "PATH";
+/// File reading as empty and discarding what is written.
+pub const NULL_DEVICE: &str = "/dev/null";
+const PATH_SEPARATOR: char = ':';
+const PASSWD_BUFFER_SIZE: usize = 4096;
+
+/// Unix shells only look up bare command names in `PATH`.
+pub const SEARCH_CURRENT_DIR: bool = false;
+
+/// Runs `f` against the passwd entry of the current user.
+fn with_passwd_entry<T>(f: impl FnOnce(&libc::passwd) -> T) -> Option<T> {
+    lookup_passwd(
+        |passwd, buffer, result| unsafe {
+            libc::getpwuid_r(libc::getuid(), passwd, buffer.as_mut_ptr(), buffer.len(), result)
+        },
+        f,
+    )
+}
+
+/// Runs `f` against the passwd entry of the named user.
+fn with_named_passwd_entry<T>(name: &str, f: impl FnOnce(&libc::passwd) -> T) -> Option<T> {
+    let name = CString::new(name).ok()?;
+    lookup_passwd(
+        |passwd, buffer, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), passwd, buffer.as_mut_ptr(), buffer.len(), result)
+        },
+        f,
+    )
+}
+
+/// Calls one of the reentrant passwd lookups with the buffers it fills.
+fn lookup_passwd<T>(
+    lookup: impl FnOnce(&mut libc::passwd, &mut [c_char], &mut *mut libc::passwd) -> libc::c_int,
+    f: impl FnOnce(&libc::passwd) -> T,
+) -> Option<T> {
+    let mut passwd: libc::passwd = unsafe { mem::zeroed() };
+    let mut buffer = vec![0 as c_char; PASSWD_BUFFER_SIZE];
+    let mut result: *mut libc::passwd = ptr::null_mut();
+    let status = lookup(&mut passwd, &mut buffer, &mut result);
+    if status != 0 || result.is_null() {
+        return None;
+    }
+    Some(f(&passwd))
+}
+
+fn passwd_string(field: *const c_char) -> Option<String> {
+    if field.is_null() {
+        return None;
+    }
+    let value = unsafe { CStr::from_ptr(field) };
+    Some(value.to_string_lossy().into_owned())
+}
+
+pub fn get_username() -> String {
+    with_passwd_entry(|passwd| passwd_string(passwd.pw_name))
+        .flatten()
+        .or_else(|| env::var("USER").ok())
+        .unwrap_or_else(|| String::from("unknown"))
+}
+
+/// Name of the machine, as set by `hostname`.
+pub fn host_name() -> String {
+    let mut buffer = [0 as c_char; 256];
+    if unsafe { libc::gethostname(buffer.as_mut_ptr(), buffer.len()) } != 0 {
+        return String::from("localhost");
+    }
+    // The name may fill the buffer without a terminating zero.
+    buffer[buffer.len() - 1] = 0;
+    unsafe { CStr::from_ptr(buffer.as_ptr()) }.to_string_lossy().into_owned()
+}
+
+/// Whether the shell runs with root privileges.
+pub fn is_superuser() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Home directory from `$HOME`, falling back to the passwd database.
+pub fn home_dir() -> Option<PathBuf> {
+    match env::var("HOME") {
+        Ok(home) if !home.is_empty() => Some(PathBuf::from(home)),
+        _ => with_passwd_entry(|passwd| passwd_string(passwd.pw_dir))
+            .flatten()
+            .map(PathBuf::from),
+    }
+}
+
+/// Home directory of another user, for `~user`.
+pub fn user_home_dir(name: &str) -> Option<PathBuf> {
+    with_named_passwd_entry(name, |passwd| passwd_string(passwd.pw_dir))
+        .flatten()
+        .map(PathBuf::from)
+}
+
+/// Directories listed in the value of `PATH_VARIABLE`.
+pub fn split_path(value: &str) -> Vec<PathBuf> {
+    value
+        .split(PATH_SEPARATOR)
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Executables carry no suffix on Unix.
+pub fn executable_name(keyword: &str) -> String {
+    keyword.to_string()
+}
+
+/// Accepts regular files (following symbolic links) with an execute bit set.
+pub fn check_executable(path: &Path) -> Result<(), io::Error> {
+    let metadata = fs::metadata(path)?;
+    if !metadata.is_file() {
+        return Err(io::ErrorKind::NotFound.into());
+    }
+    if metadata.permissions().mode() & 0o111 == 0 {
+        return Err(io::ErrorKind::PermissionDenied.into());
+    }
+    Ok(())
+}
+
+/// Terminal settings in place before `enable_raw_mode`, put back on drop.
+pub struct RawMode {
+    original: libc::termios,
+}
+
+/// Switches the terminal to passing single key presses without echoing
+/// them, and without turning Ctrl-C or Ctrl-Z into signals.
+pub fn enable_raw_mode() -> io::Result<RawMode> {
+    let mut original: libc::termios = unsafe { mem::zeroed() };
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut original) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut raw = original;
+    raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+    raw.c_cflag |= libc::CS8;
+    raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+    raw.c_cc[libc::VMIN] = 1;
+    raw.c_cc[libc::VTIME] = 0;
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(RawMode { original })
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+/// Number of columns of the terminal on standard output.
+pub fn terminal_width() -> Option<usize> {
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    if unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } != 0 || size.ws_col == 0 {
+        return None;
+    }
+    Some(usize::from(size.ws_col))
+}
+
+static RESIZED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn on_resize(_signal: libc::c_int) {
+    RESIZED.store(true, Ordering::Relaxed);
+}
+
+/// Starts watching for terminal size changes. A read waiting for a key is
+/// interrupted by one, so the line can be drawn again right away.
+pub fn watch_resize() {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_resize as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        // No `SA_RESTART`, reads have to return.
+        action.sa_flags = 0;
+        libc::sigaction(libc::SIGWINCH, &action, ptr::null_mut());
+    }
+}
+
+/// True once after every change of the terminal size.
+pub fn take_resize() -> bool {
+    RESIZED.swap(false, Ordering::Relaxed)
+}
+
+/// Added to the signal number for programs killed by a signal.
+const SIGNAL_EXIT_BASE: i32 = 128;
+
+/// Status of a command stopped with Ctrl-Z, as if killed by `SIGTSTP`.
+pub const STOPPED_STATUS: i32 = SIGNAL_EXIT_BASE + libc::SIGTSTP;
+
+/// Status of a command interrupted with Ctrl-C, as if killed by `SIGINT`.
+pub const INTERRUPTED_STATUS: i32 = SIGNAL_EXIT_BASE + libc::SIGINT;
+
+/// Signals of terminal job control, which the shell ignores and its
+/// programs get again.
+const JOB_CONTROL_SIGNALS: [libc::c_int; 3] = [libc::SIGTSTP, libc::SIGTTIN, libc::SIGTTOU];
+
+/// Set once the shell ignores the job control signals.
+static JOB_CONTROL: AtomicBool = AtomicBool::new(false);
+/// Set once the shell catches Ctrl-C and ignores Ctrl-\.
+static WATCHING_INTERRUPTS: AtomicBool = AtomicBool::new(false);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Terminal settings of the shell, put back whenever it takes the terminal
+/// back from a job.
+static SHELL_TERMINAL_MODES: OnceLock<libc::termios> = OnceLock::new();
+
+/// Puts the shell in a process group of its own owning the terminal, so that
+/// jobs can be given the terminal and taken out of it. Gives `false` when
+/// standard input is not a terminal.
+pub fn init_job_control() -> bool {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 0 {
+            return false;
+        }
+        for signal in JOB_CONTROL_SIGNALS {
+            libc::signal(signal, libc::SIG_IGN);
+        }
+        JOB_CONTROL.store(true, Ordering::Relaxed);
+        // Fails for a session leader, which has a group of its own already.
+        libc::setpgid(0, 0);
+        if libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp()) != 0 {
+            return false;
+        }
+        let mut modes: libc::termios = mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut modes) == 0 {
+            let _ = SHELL_TERMINAL_MODES.set(modes);
+        }
+    }
+    true
+}
+
+/// Gives the terminal to a process group, which then gets the keys typed
+/// and the signals of Ctrl-C and Ctrl-Z.
+pub fn give_terminal(group: u32) {
+    unsafe {
+        libc::tcsetpgrp(libc::STDIN_FILENO, group as libc::pid_t);
+    }
+}
+
+/// Gives the terminal back to the shell, with the settings it had before a
+/// job possibly changed them.
+pub fn take_terminal() {
+    unsafe {
+        libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+        if let Some(modes) = SHELL_TERMINAL_MODES.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, modes);
+        }
+    }
+}
+
+/// Starts a program in a process group: the one given, or a new one led by
+/// the program.
+pub fn set_process_group(process: &mut process::Command, group: Option<u32>) {
+    process.process_group(group.map_or(0, |group| group as i32));
+}
+
+/// Gives a program back the signals the shell ignores: it quits on Ctrl-\,
+/// and in a process group of its own with `job_control` it stops on Ctrl-Z
+/// and when reading the terminal in the background.
+pub fn reset_signals(process: &mut process::Command, job_control: bool) {
+    let mut signals = Vec::new();
+    if job_control && JOB_CONTROL.load(Ordering::Relaxed) {
+        signals.extend(JOB_CONTROL_SIGNALS);
+    }
+    if WATCHING_INTERRUPTS.load(Ordering::Relaxed) {
+        signals.push(libc::SIGQUIT);
+    }
+    if signals.is_empty() {
+        return;
+    }
+    unsafe {
+        process.pre_exec(move || {
+            for &signal in &signals {
+                libc::signal(signal, libc::SIG_DFL);
+            }
+            Ok(())
+        });
+    }
+}
+
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Makes Ctrl-C stop the commands running in the shell rather than end it,
+/// and Ctrl-\ leave it alone. A read waiting for input is interrupted by
+/// Ctrl-C, so a builtin reading the terminal can stop too.
+pub fn watch_interrupts() {
+    unsafe {
+        let mut action: libc::sigaction = mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::sigemptyset(&mut action.sa_mask);
+        // No `SA_RESTART`, reads have to return.
+        action.sa_flags = 0;
+        libc::sigaction(libc::SIGINT, &action, ptr::null_mut());
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+    WATCHING_INTERRUPTS.store(true, Ordering::Relaxed);
+}
+
+/// Records a Ctrl-C the shell didn't get itself, as one that ended the
+/// program it was given to.
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+}
+
+/// Whether Ctrl-C was pressed since the last `take_interrupt`.
+pub fn interrupt_pending() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// True once after Ctrl-C was pressed, for the next prompt to start afresh.
+pub fn take_interrupt() -> bool {
+    INTERRUPTED.swap(false, Ordering::Relaxed)
+}
+
+/// Continues the stopped programs of a process group.
+pub fn continue_group(group: u32) {
+    unsafe {
+        libc::kill(-(group as libc::pid_t), libc::SIGCONT);
+    }
+}
+
+/// Waits until the program ends, or is stopped when `stops` is set.
+pub fn wait_process(child: &mut Child, stops: bool) -> io::Result<ProcessEvent> {
+    let flags = if stops { libc::WUNTRACED } else { 0 };
+    loop {
+        match wait_pid(child, flags) {
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Ok(event) => return Ok(event.unwrap_or(ProcessEvent::Exited(0))),
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// What happened to the program if it ended or stopped, without waiting.
+pub fn poll_process(child: &mut Child) -> io::Result<Option<ProcessEvent>> {
+    wait_pid(child, libc::WNOHANG | libc::WUNTRACED)
+}
+
+fn wait_pid(child: &mut Child, flags: libc::c_int) -> io::Result<Option<ProcessEvent>> {
+    let mut status = 0;
+    match unsafe { libc::waitpid(child.id() as libc::pid_t, &mut status, flags) } {
+        -1 => Err(io::Error::last_os_error()),
+        0 => Ok(None),
+        _ if libc::WIFSTOPPED(status) => Ok(Some(ProcessEvent::Stopped)),
+        _ => {
+            let status = process::ExitStatus::from_raw(status);
+            let code = status.code().or(status.signal().map(|signal| SIGNAL_EXIT_BASE + signal));
+            Ok(Some(ProcessEvent::Exited(code.unwrap_or(1))))
+        }
+    }
+}
+
+#[cfg(test)]
+mod unix_platform_tests {
+    use super::*;
+
+    #[test]
+    fn path_is_split_on_colons() {
+        assert_eq!(vec![PathBuf::from("/usr/local/bin"), PathBuf::from("/usr/bin")],
+                   split_path("/usr/local/bin::/usr/bin:"));
+    }
+
+    #[test]
+    fn directories_are_not_executable() {
+        assert!(check_executable(Path::new("/")).is_err());
+    }
+}
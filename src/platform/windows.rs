@@ -0,0 +1,258 @@
+use std::os::raw::{c_char, c_int, c_short, c_ulong, c_ushort, c_void};
+use std::ptr;
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::{env, fs, io};
+use std::path::{Path, PathBuf};
+use std::process::{self, Child};
+
+use super::ProcessEvent;
+
+/// Name of the environment variable holding the executable search path.
+pub const PATH_VARIABLE: &str = "Path";
+/// File reading as empty and discarding what is written.
+pub const NULL_DEVICE: &str = "NUL";
+const PATH_SEPARATOR: char = ';';
+const EXECUTABLE_SUFFIX: &str = ".exe";
+
+/// Windows looks up executables in the current directory before `Path`.
+pub const SEARCH_CURRENT_DIR: bool = true;
+
+#[link(name = "secur32")]
+extern "system" {
+    fn GetUserNameW(lpBuffer: *mut c_char, pcbBuffer: *mut c_ulong) -> c_int;
+}
+
+type Handle = *mut c_void;
+
+const STD_INPUT_HANDLE: c_ulong = -10i32 as c_ulong;
+const STD_OUTPUT_HANDLE: c_ulong = -11i32 as c_ulong;
+const ENABLE_PROCESSED_INPUT: c_ulong = 0x0001;
+const ENABLE_LINE_INPUT: c_ulong = 0x0002;
+const ENABLE_ECHO_INPUT: c_ulong = 0x0004;
+const ENABLE_VIRTUAL_TERMINAL_INPUT: c_ulong = 0x0200;
+const ENABLE_VIRTUAL_TERMINAL_PROCESSING: c_ulong = 0x0004;
+
+#[repr(C)]
+#[derive(Default)]
+struct Coord {
+    x: c_short,
+    y: c_short,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct SmallRect {
+    left: c_short,
+    top: c_short,
+    right: c_short,
+    bottom: c_short,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ConsoleScreenBufferInfo {
+    size: Coord,
+    cursor_position: Coord,
+    attributes: c_ushort,
+    window: SmallRect,
+    maximum_window_size: Coord,
+}
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn GetStdHandle(nStdHandle: c_ulong) -> Handle;
+    fn GetConsoleMode(hConsoleHandle: Handle, lpMode: *mut c_ulong) -> c_int;
+    fn SetConsoleMode(hConsoleHandle: Handle, dwMode: c_ulong) -> c_int;
+    fn GetConsoleScreenBufferInfo(hConsoleOutput: Handle, lpConsoleScreenBufferInfo: *mut ConsoleScreenBufferInfo) -> c_int;
+}
+
+pub fn get_username() -> String {
+    let mut size = 0;
+    let success = unsafe { GetUserNameW(ptr::null_mut(), &mut size) == 0 };
+    assert!(success);
+
+    // Step 2. Allocate memory to put the Windows (UTF-16) string.
+    let mut name: Vec<u16> = Vec::with_capacity(size.try_into().unwrap_or(std::usize::MAX));
+    size = name.capacity().try_into().unwrap_or(std::u32::MAX);
+    let orig_size = size;
+    let fail = unsafe {
+        GetUserNameW(name.as_mut_ptr().cast(), &mut size) == 0
+    };
+    if fail {
+        return String::from("unknown");
+    }
+    assert_eq!(orig_size, size);
+
+    unsafe {
+        name.set_len(size.try_into().unwrap_or(std::usize::MAX));
+    }
+    let terminator = name.pop();
+    assert_eq!(terminator, Some(0u16));
+
+    // Step 3. Convert to Rust String
+    let name = OsString::from_wide(&name);
+    name.into_string().unwrap()
+}
+
+/// Name of the machine from `COMPUTERNAME`.
+pub fn host_name() -> String {
+    env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("localhost"))
+}
+
+/// Elevated sessions are not told apart.
+pub fn is_superuser() -> bool {
+    false
+}
+
+/// Home directory made of `HOMEDRIVE` and `HOMEPATH`.
+pub fn home_dir() -> Option<PathBuf> {
+    let drive = env::var("HOMEDRIVE").ok()?;
+    let path = env::var("HOMEPATH").ok()?;
+    Some(PathBuf::from(drive + &path))
+}
+
+/// Home directory of another user, for `~user`. Profiles of other users
+/// can't be looked up without extra privileges, so only the current user is
+/// known.
+pub fn user_home_dir(name: &str) -> Option<PathBuf> {
+    if name == get_username() {
+        home_dir()
+    } else {
+        None
+    }
+}
+
+/// Directories listed in the value of `PATH_VARIABLE`.
+pub fn split_path(value: &str) -> Vec<PathBuf> {
+    value
+        .split(PATH_SEPARATOR)
+        .filter(|entry| !entry.is_empty())
+        .map(PathBuf::from)
+        .collect()
+}
+
+/// Appends `.exe` unless the keyword already names an executable.
+pub fn executable_name(keyword: &str) -> String {
+    if keyword.contains(EXECUTABLE_SUFFIX) {
+        keyword.to_string()
+    } else {
+        format!("{keyword}{EXECUTABLE_SUFFIX}")
+    }
+}
+
+/// Console modes in place before `enable_raw_mode`, put back on drop.
+pub struct RawMode {
+    input_mode: c_ulong,
+    output_mode: c_ulong,
+}
+
+fn console_mode(handle: Handle) -> io::Result<c_ulong> {
+    let mut mode = 0;
+    if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(mode)
+}
+
+/// Switches the console to passing single key presses as the same escape
+/// sequences Unix terminals send, without echoing them.
+pub fn enable_raw_mode() -> io::Result<RawMode> {
+    let (input, output) = unsafe { (GetStdHandle(STD_INPUT_HANDLE), GetStdHandle(STD_OUTPUT_HANDLE)) };
+    let input_mode = console_mode(input)?;
+    let output_mode = console_mode(output)?;
+    let raw_input = (input_mode & !(ENABLE_PROCESSED_INPUT | ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT))
+        | ENABLE_VIRTUAL_TERMINAL_INPUT;
+    unsafe {
+        if SetConsoleMode(input, raw_input) == 0 || SetConsoleMode(output, output_mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(RawMode { input_mode, output_mode })
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        unsafe {
+            SetConsoleMode(GetStdHandle(STD_INPUT_HANDLE), self.input_mode);
+            SetConsoleMode(GetStdHandle(STD_OUTPUT_HANDLE), self.output_mode);
+        }
+    }
+}
+
+/// Number of columns of the console window.
+pub fn terminal_width() -> Option<usize> {
+    let mut info = ConsoleScreenBufferInfo::default();
+    if unsafe { GetConsoleScreenBufferInfo(GetStdHandle(STD_OUTPUT_HANDLE), &mut info) } == 0 {
+        return None;
+    }
+    usize::try_from(info.window.right - info.window.left + 1).ok()
+}
+
+/// The console sends no resize signal, the width is read again before
+/// every redraw instead.
+pub fn watch_resize() {}
+
+pub fn take_resize() -> bool {
+    false
+}
+
+/// Symbolic links are not followed, every regular file is accepted.
+pub fn check_executable(path: &Path) -> Result<(), io::Error> {
+    let metadata = fs::symlink_metadata(path)?;
+    if metadata.is_symlink() {
+        println!("It's a symbolic link");
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+    if metadata.is_file() {
+        Ok(())
+    } else {
+        Err(io::ErrorKind::NotFound.into())
+    }
+}
+
+/// Programs can't be stopped, this status is never seen.
+pub const STOPPED_STATUS: i32 = 148;
+
+/// The console has no process groups to hand over: background jobs run,
+/// but can't be stopped or brought to the foreground while stopped.
+pub fn init_job_control() -> bool {
+    false
+}
+
+pub fn give_terminal(_group: u32) {}
+
+pub fn take_terminal() {}
+
+pub fn set_process_group(_process: &mut process::Command, _group: Option<u32>) {}
+
+pub fn reset_signals(_process: &mut process::Command, _job_control: bool) {}
+
+pub fn continue_group(_group: u32) {}
+
+/// Status of a command interrupted with Ctrl-C.
+pub const INTERRUPTED_STATUS: i32 = 130;
+
+/// Ctrl-C is left to the console, which ends the programs attached to it.
+pub fn watch_interrupts() {}
+
+pub fn interrupt() {}
+
+pub fn interrupt_pending() -> bool {
+    false
+}
+
+pub fn take_interrupt() -> bool {
+    false
+}
+
+/// Waits until the program ends, programs are never stopped.
+pub fn wait_process(child: &mut Child, _stops: bool) -> io::Result<ProcessEvent> {
+    let status = child.wait()?;
+    Ok(ProcessEvent::Exited(status.code().unwrap_or(1)))
+}
+
+/// Whether the program ended, without waiting.
+pub fn poll_process(child: &mut Child) -> io::Result<Option<ProcessEvent>> {
+    Ok(child.try_wait()?.map(|status| ProcessEvent::Exited(status.code().unwrap_or(1))))
+}
@@ -0,0 +1,150 @@
+//! Prompts made from the `PS1`, `PS2` and `RPS1` templates, with backslash
+//! escapes as in bash:
+//!
+//! | Escape     | Replaced by                                             |
+//! |------------|---------------------------------------------------------|
+//! | `\u`       | user name                                               |
+//! | `\h`, `\H` | host name up to the first dot, full host name           |
+//! | `\w`       | current directory, with the home directory shown as `~` |
+//! | `\W`       | last component of the current directory                 |
+//! | `\p`       | full path of the current directory                      |
+//! | `\t`, `\T` | time as 24 hour `HH:MM:SS`, 12 hour `HH:MM:SS`          |
+//! | `\A`, `\@` | time as 24 hour `HH:MM`, 12 hour `HH:MM am`             |
+//! | `\d`       | date as `Sun Oct 18`                                    |
+//! | `\D{fmt}`  | date and time in a `strftime` format                    |
+//! | `\?`       | exit status of the last command                         |
+//! | `\j`       | number of jobs                                          |
+//! | `\g`       | git branch, with a `*` when files changed               |
+//! | `\!`       | history number of the line                              |
+//! | `\$`       | `#` for the superuser, `$` otherwise                    |
+//! | `\s`       | name of the shell                                       |
+//! | `\n`, `\e` | newline, escape character                               |
+//! | `\\`       | backslash                                               |
+//!
+//! `\[` and `\]` are accepted around escape sequences and left out: the
+//! width of the prompt is worked out without them. A `\D{fmt}` with a
+//! format that is not valid is left as it is.
+
+use std::env;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::Local;
+
+use crate::shell::Shell;
+use crate::{git, platform};
+
+/// The look of the prompt when `PS1` is not set.
+pub const DEFAULT_PS1: &str = "\\[\\e[94m\\]\\u\\[\\e[0m\\]🚀:\\[\\e[94m\\]\\p\\[\\e[0m\\] ";
+
+/// Prompt of the lines continuing an unfinished command when `PS2` is not
+/// set.
+pub const DEFAULT_PS2: &str = "> ";
+
+/// Expands the escapes of a prompt template.
+pub fn render(template: &str, shell: &Shell) -> String {
+    let current_dir = env::current_dir().ok();
+    let mut prompt = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            prompt.push(c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            prompt.push('\\');
+            break;
+        };
+        match escape {
+            'u' => prompt.push_str(&platform::get_username()),
+            'h' => prompt.push_str(platform::host_name().split('.').next().unwrap_or_default()),
+            'H' => prompt.push_str(&platform::host_name()),
+            'w' => prompt.push_str(&current_dir.as_deref().map_or("?".into(), |dir| abbreviate_home(dir, &shell.home_dir()))),
+            'W' => prompt.push_str(&current_dir.as_deref().map_or("?".into(), base_name)),
+            'p' => prompt.push_str(&current_dir.as_deref().map_or("?".into(), |dir| dir.to_string_lossy())),
+            't' => prompt.push_str(&Local::now().format("%H:%M:%S").to_string()),
+            'T' => prompt.push_str(&Local::now().format("%I:%M:%S").to_string()),
+            'A' => prompt.push_str(&Local::now().format("%H:%M").to_string()),
+            '@' => prompt.push_str(&Local::now().format("%I:%M %P").to_string()),
+            'd' => prompt.push_str(&Local::now().format("%a %b %d").to_string()),
+            'D' => {
+                let rest = chars.as_str();
+                match rest.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+                    Some((format, after)) => {
+                        let pattern = if format.is_empty() { "%X" } else { format };
+                        // chrono reports a bad specifier as a formatting error,
+                        // which `to_string` would turn into a panic.
+                        let mut date = String::new();
+                        match write!(date, "{}", Local::now().format(pattern)) {
+                            Ok(()) => prompt.push_str(&date),
+                            Err(_) => prompt.push_str(&format!("\\D{{{format}}}")),
+                        }
+                        chars = after.chars();
+                    }
+                    None => prompt.push_str("\\D"),
+                }
+            }
+            '?' => prompt.push_str(&shell.last_status.to_string()),
+            'j' => prompt.push_str(&shell.jobs.len().to_string()),
+            'g' => {
+                if let Some(status) = current_dir.as_deref().and_then(git::status) {
+                    prompt.push_str(&status.branch);
+                    if status.dirty {
+                        prompt.push('*');
+                    }
+                }
+            }
+            '!' => prompt.push_str(&(shell.history.entries().len() + 1).to_string()),
+            '$' => prompt.push(if platform::is_superuser() { '#' } else { '$' }),
+            's' => prompt.push_str(base_name(Path::new(&shell.name)).as_ref()),
+            'n' => prompt.push('\n'),
+            'e' => prompt.push('\x1b'),
+            '\\' => prompt.push('\\'),
+            '[' | ']' => (),
+            other => {
+                prompt.push('\\');
+                prompt.push(other);
+            }
+        }
+    }
+    prompt
+}
+
+/// The directory with the home directory at its start replaced by `~`.
+fn abbreviate_home(dir: &Path, home: &Option<PathBuf>) -> String {
+    match home.as_deref().and_then(|home| dir.strip_prefix(home).ok()) {
+        Some(rest) if rest.as_os_str().is_empty() => String::from("~"),
+        Some(rest) => format!("~{}{}", std::path::MAIN_SEPARATOR, rest.to_string_lossy()),
+        None => dir.to_string_lossy().into_owned(),
+    }
+}
+
+fn base_name(path: &Path) -> String {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => path.to_string_lossy().into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod prompt_tests {
+    use super::*;
+
+    #[test]
+    fn home_abbreviation() {
+        let home = Some(PathBuf::from("/home/ann"));
+        assert_eq!("~", abbreviate_home(Path::new("/home/ann"), &home));
+        assert_eq!("~/src/shell", abbreviate_home(Path::new("/home/ann/src/shell"), &home));
+        assert_eq!("/home/annie", abbreviate_home(Path::new("/home/annie"), &home));
+        assert_eq!("/tmp", abbreviate_home(Path::new("/tmp"), &None));
+    }
+
+    #[test]
+    fn escapes() {
+        let shell = Shell { last_status: 127, name: String::from("/usr/bin/shell"), ..Shell::default() };
+        assert_eq!("[127] shell\n\\x \\x \\", render("\\[[\\?]\\] \\s\\n\\\\x \\x \\", &shell));
+        assert_eq!("\x1b[1m0", render("\\e[1m\\j", &shell));
+        assert_eq!("\\D{%Q} x", render("\\D{%Q} x", &shell));
+        assert_eq!("2026 ", render("\\D{2026} ", &shell));
+    }
+}
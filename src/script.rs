@@ -0,0 +1,219 @@
+//! Runs commands read from a script file, a `-c` string or a non-interactive
+//! standard input. Lines are read one at a time and every complete command
+//! runs before the next line is read, so a command may span several lines.
+
+use std::fs;
+use std::io::{self, Write};
+use std::iter;
+use std::path::{Path, PathBuf};
+
+use crate::exec;
+use crate::lexer::ParseError;
+use crate::parser;
+use crate::shell::Shell;
+use crate::streams::IoStreams;
+use crate::{ERROR_CODE, EXIT_CODE, SUCCESS_CODE, SYNTAX_ERROR_CODE};
+
+/// Exit statuses for scripts that can't be read, like for programs.
+const NOT_READABLE_CODE: i32 = 126;
+const NOT_FOUND_CODE: i32 = 127;
+
+/// Startup file of interactive shells, in the home directory.
+const RC_FILE: &str = ".shellrc";
+/// Startup file of login shells, in the home directory.
+pub const PROFILE_FILE: &str = ".shell_profile";
+
+/// The startup file read by an interactive shell.
+pub enum RcFile {
+    /// `~/.shellrc`
+    Default,
+    /// `--norc`
+    Skipped,
+    /// `--rcfile FILE`
+    File(PathBuf),
+}
+
+impl RcFile {
+    /// The file to run, if any, and whether it has to exist.
+    pub fn startup_file(self, shell: &Shell) -> Option<(PathBuf, bool)> {
+        match self {
+            RcFile::Default => shell.home_dir().map(|home| (home.join(RC_FILE), false)),
+            RcFile::Skipped => None,
+            RcFile::File(path) => Some((path, true)),
+        }
+    }
+}
+
+/// Runs a startup file, giving `EXIT_CODE` if it ran `exit`. Errors in it
+/// are reported with their line and the shell starts anyway. A missing file
+/// is only an error when it was asked for by name.
+pub fn run_startup_file(path: &Path, required: bool, shell: &mut Shell) -> i32 {
+    if !required && !path.exists() {
+        return SUCCESS_CODE;
+    }
+    run_file(path, shell, &mut IoStreams::inherit())
+}
+
+/// Runs a script file, as for `shell script.sh` and `source script.sh`.
+pub fn run_file(path: &Path, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let source = match fs::read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            outln!(io.stderr, "{}: {err}", path.display());
+            return if err.kind() == io::ErrorKind::NotFound { NOT_FOUND_CODE } else { NOT_READABLE_CODE };
+        }
+    };
+    run_string(&source, &path.to_string_lossy(), shell, io)
+}
+
+/// Runs commands held in a string, `name` tells where they come from in
+/// error messages.
+pub fn run_string(source: &str, name: &str, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    let lines = source.split_inclusive('\n').map(|line| Ok(line.to_string()));
+    run_lines(lines, name, shell, io)
+}
+
+/// Runs commands read from standard input until its end.
+pub fn run_stdin(shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    // Lines are read one by one without holding the lock on stdin, so the
+    // commands can read the input that follows them.
+    let lines = iter::from_fn(|| {
+        let mut line = String::new();
+        match io::stdin().read_line(&mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(err) => Some(Err(err)),
+        }
+    });
+    run_lines(lines, "stdin", shell, io)
+}
+
+/// Runs each command as soon as its last line is read. A syntax error stops
+/// the script. Gives the status of the last command, or `EXIT_CODE` when
+/// `exit` was run.
+fn run_lines(lines: impl Iterator<Item = io::Result<String>>, name: &str, shell: &mut Shell, io: &mut IoStreams)
+    -> i32 {
+    // A sourced script reports its own lines, then the outer ones again.
+    let outer_location = shell.location.take();
+    let status = run_commands(lines, name, shell, io);
+    shell.location = outer_location;
+    status
+}
+
+fn run_commands(lines: impl Iterator<Item = io::Result<String>>, name: &str, shell: &mut Shell, io: &mut IoStreams)
+    -> i32 {
+    let mut status = SUCCESS_CODE;
+    let mut command = String::new();
+    let mut first_line = 0;
+    for (index, line) in lines.enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                outln!(io.stderr, "{name}: {err}");
+                return ERROR_CODE;
+            }
+        };
+        if command.is_empty() {
+            first_line = index + 1;
+        }
+        command.push_str(&line);
+        match parser::parse_with_aliases(&command, &shell.aliases) {
+            Ok(Some(list)) => {
+                shell.location = Some((name.to_string(), first_line));
+                status = exec::run_list(&list, shell, io);
+                if status == EXIT_CODE {
+                    return EXIT_CODE;
+                }
+                // `return` leaves a sourced file.
+                if shell.flow.is_some() {
+                    return status;
+                }
+            }
+            Ok(None) => (),
+            Err(err) if err.is_incomplete() => continue,
+            Err(err) => return syntax_error(name, first_line, err, shell, io),
+        }
+        command.clear();
+    }
+    match parser::parse_with_aliases(&command, &shell.aliases) {
+        Err(err) => syntax_error(name, first_line, err, shell, io),
+        Ok(_) => status,
+    }
+}
+
+fn syntax_error(name: &str, line: usize, err: ParseError, shell: &mut Shell, io: &mut IoStreams) -> i32 {
+    outln!(io.stderr, "{name}: line {line}: {err}");
+    shell.last_status = SYNTAX_ERROR_CODE;
+    SYNTAX_ERROR_CODE
+}
+
+#[cfg(test)]
+mod script_tests {
+    use super::*;
+    use std::env;
+    use std::io::Read;
+    use crate::streams::{InputStream, OutputStream};
+
+    /// Runs a script, giving its status and what it wrote to standard error.
+    fn run(source: &str, shell: &mut Shell) -> (i32, String) {
+        let (mut reader, writer) = io::pipe().unwrap();
+        let mut io = IoStreams { stdin: InputStream::Stdin, stdout: OutputStream::Stdout, stderr: OutputStream::Pipe(writer) };
+        let status = run_string(source, "test.sh", shell, &mut io);
+        drop(io);
+        let mut errors = String::new();
+        reader.read_to_string(&mut errors).unwrap();
+        (status, errors)
+    }
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("script_tests_{}_{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn errors_tell_the_line() {
+        let mut shell = Shell::default();
+        let (status, errors) = run("A=1\n\nnosuch\nB=2\n", &mut shell);
+        assert_eq!((SUCCESS_CODE, "test.sh: line 3: Command not found - nosuch\n"), (status, errors.as_str()));
+        assert_eq!(Some("2"), shell.variables.get("B"));
+
+        let (status, errors) = run("A=2\n\n)\nC=3\n", &mut shell);
+        assert_eq!(SYNTAX_ERROR_CODE, status);
+        assert!(errors.starts_with("test.sh: line 3: "), "{errors:?}");
+        assert_eq!(None, shell.variables.get("C"));
+
+        let (status, errors) = run("A=3\nif true; then\n  true\n", &mut shell);
+        assert_eq!(SYNTAX_ERROR_CODE, status);
+        assert!(errors.starts_with("test.sh: line 2: "), "{errors:?}");
+    }
+
+    #[test]
+    fn unfinished_alias_is_an_error() {
+        let mut shell = Shell::default();
+        let (status, errors) = run("alias begin='{'\nbegin true\n", &mut shell);
+        assert_eq!(SYNTAX_ERROR_CODE, status);
+        assert!(errors.starts_with("test.sh: line 2: "), "{errors:?}");
+    }
+
+    #[test]
+    fn startup_files() {
+        let mut shell = Shell::default();
+        shell.variables.set("HOME", "home");
+        assert_eq!(Some((Path::new("home").join(RC_FILE), false)), RcFile::Default.startup_file(&shell));
+        assert_eq!(None, RcFile::Skipped.startup_file(&shell));
+        let rc_file = temp_file("rc", "A=1\n)\nB=2\n");
+        assert_eq!(Some((rc_file.clone(), true)), RcFile::File(rc_file.clone()).startup_file(&shell));
+
+        // A broken file stops at the error, and the shell starts anyway.
+        assert_ne!(EXIT_CODE, run_startup_file(&rc_file, true, &mut shell));
+        assert_eq!((Some("1"), None), (shell.variables.get("A"), shell.variables.get("B")));
+        fs::write(&rc_file, "exit 3\n").unwrap();
+        assert_eq!(EXIT_CODE, run_startup_file(&rc_file, true, &mut shell));
+        fs::remove_file(&rc_file).unwrap();
+
+        // Only a file asked for by name has to exist.
+        assert_eq!(SUCCESS_CODE, run_startup_file(&rc_file, false, &mut shell));
+        assert_eq!(NOT_FOUND_CODE, run_startup_file(&rc_file, true, &mut shell));
+    }
+}
@@ -0,0 +1,239 @@
+//! State shared by the commands of one shell session.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::complete::CompletionSpec;
+use crate::history::History;
+use crate::jobs::{Job, Jobs};
+use crate::parser::{self, FunctionDefinition, PipelineCommand};
+use crate::platform;
+use crate::variables::{Variable, Variables};
+
+/// Exported functions are passed to other shells as environment variables
+/// named `SHELL_FUNC_name%%`, holding the definition. The `%%` keeps them
+/// apart from variables.
+const FUNCTION_PREFIX: &str = "SHELL_FUNC_";
+const FUNCTION_SUFFIX: &str = "%%";
+
+#[derive(Clone, Default)]
+pub struct ShellOptions {
+    /// A pipeline fails when any of its commands fails, not only the last one.
+    pub pipefail: bool,
+    /// A pattern matching no file expands to nothing instead of itself.
+    pub nullglob: bool,
+    /// A pattern matching no file is an error.
+    pub failglob: bool,
+    /// Patterns match file names starting with a dot.
+    pub dotglob: bool,
+    /// End of input (Ctrl-D) doesn't exit an interactive shell.
+    pub ignoreeof: bool,
+    /// `!` references to earlier lines are expanded, on by default at the
+    /// prompt.
+    pub histexpand: bool,
+    /// Ctrl-R at the prompt ranks lines by fuzzy matching.
+    pub fuzzysearch: bool,
+}
+
+impl ShellOptions {
+    /// Names accepted by `set -o`, in the order they are listed.
+    pub const NAMES: &'static [&'static str] = &["pipefail", "nullglob", "failglob", "dotglob", "ignoreeof", "histexpand", "fuzzysearch"];
+
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "pipefail" => Some(self.pipefail),
+            "nullglob" => Some(self.nullglob),
+            "failglob" => Some(self.failglob),
+            "dotglob" => Some(self.dotglob),
+            "ignoreeof" => Some(self.ignoreeof),
+            "histexpand" => Some(self.histexpand),
+            "fuzzysearch" => Some(self.fuzzysearch),
+            _ => None,
+        }
+    }
+
+    /// Changes an option, returns `false` if there is no such option.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        match name {
+            "pipefail" => self.pipefail = value,
+            "nullglob" => self.nullglob = value,
+            "failglob" => self.failglob = value,
+            "dotglob" => self.dotglob = value,
+            "ignoreeof" => self.ignoreeof = value,
+            "histexpand" => self.histexpand = value,
+            "fuzzysearch" => self.fuzzysearch = value,
+            _ => return false,
+        }
+        true
+    }
+}
+
+/// A `break`, `continue` or `return` waiting to reach its loop or function.
+/// The commands on the way are skipped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Flow {
+    /// Leave this many loops.
+    Break(usize),
+    /// Leave this many loops minus one, and go on with the next round of the
+    /// last one.
+    Continue(usize),
+    /// Leave the function or sourced file with this status.
+    Return(i32),
+}
+
+#[derive(Clone)]
+pub struct Function {
+    pub definition: Arc<FunctionDefinition>,
+    /// Passed on to the shells started from this one, with `export -f`.
+    pub exported: bool,
+}
+
+/// The state of a session. The history, aliases, functions and completions
+/// are rarely changed, so copies of the shell share them until one does,
+/// with `Arc::make_mut`.
+#[derive(Clone, Default)]
+pub struct Shell {
+    pub history: Arc<History>,
+    pub options: ShellOptions,
+    pub variables: Variables,
+    /// Exit status of the last pipeline, available as `$?`.
+    pub last_status: i32,
+    /// Exit status of the last command substitution of the command being
+    /// expanded. A command made of assignments only returns it.
+    pub substitution_status: Option<i32>,
+    /// `$0`: the path of the running script, or of the shell itself.
+    pub name: String,
+    /// Values of the aliases set with `alias`, by name.
+    pub aliases: Arc<BTreeMap<String, String>>,
+    /// Functions by name.
+    pub functions: Arc<BTreeMap<String, Function>>,
+    /// `$1`, `$2`... The arguments of the running function, or of the shell.
+    pub positional: Vec<String>,
+    /// Variables hidden by `local` in each running function, put back when
+    /// it returns. The last one is for the innermost function.
+    pub locals: Vec<Vec<(String, Option<Variable>)>>,
+    /// Number of functions and sourced files running, which `return` can
+    /// leave.
+    pub call_depth: usize,
+    pub jobs: Jobs,
+    /// The background job the commands run for, on its own thread.
+    pub job: Option<Arc<Job>>,
+    /// The last job started with `&`, whose process group is `$!`.
+    pub last_background: Option<Arc<Job>>,
+    /// Programs run in process groups of their own and the foreground one
+    /// gets the terminal, as in an interactive shell on Unix.
+    pub job_control: bool,
+    /// Completions set with `complete`, by command name.
+    pub completions: Arc<BTreeMap<String, CompletionSpec>>,
+    /// Number of loops the running command is in.
+    pub loop_depth: usize,
+    pub flow: Option<Flow>,
+    /// Commands are typed at the prompt. Errors that end other shells, like
+    /// `${name:?message}` on an unset parameter, only fail the command then.
+    pub interactive: bool,
+    /// Script and line of the command running, when it comes from a file or
+    /// a `-c` string.
+    pub location: Option<(String, usize)>,
+    /// Current directory of a copy of the shell, which runs on a thread of
+    /// its own and can't change the one of the process without changing it
+    /// for the shell too. `None` in the shell itself, which uses the one of
+    /// the process.
+    pub dir: Option<PathBuf>,
+}
+
+impl Shell {
+    /// A session starting out with the environment of the shell process.
+    pub fn new() -> Shell {
+        let mut variables = Variables::from_env();
+        if let Ok(current_dir) = env::current_dir() {
+            variables.set("PWD", &current_dir.to_string_lossy());
+        }
+        Shell {
+            variables,
+            name: env::args().next().unwrap_or_default(),
+            functions: Arc::new(imported_functions()),
+            ..Shell::default()
+        }
+    }
+
+    /// Environment variables holding the exported functions.
+    pub fn function_environment(&self) -> Vec<(String, String)> {
+        self.functions
+            .iter()
+            .filter(|(_, function)| function.exported)
+            .map(|(name, function)| (format!("{FUNCTION_PREFIX}{name}{FUNCTION_SUFFIX}"), function.definition.to_string()))
+            .collect()
+    }
+
+    /// Copy of the state for a command that runs apart from the shell, like a
+    /// builtin inside a pipeline. Changes made by it, `cd` included, are not
+    /// seen here.
+    pub fn subshell(&self) -> Shell {
+        let mut subshell = self.clone();
+        if subshell.dir.is_none() {
+            subshell.dir = Some(env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+        }
+        subshell
+    }
+
+    /// The directory relative paths start from: empty for the current
+    /// directory of the process.
+    pub fn base_dir(&self) -> &Path {
+        self.dir.as_deref().unwrap_or(Path::new(""))
+    }
+
+    /// Where `path` leads from the current directory of the shell.
+    pub fn resolve(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.base_dir().join(path)
+    }
+
+    pub fn current_dir(&self) -> io::Result<PathBuf> {
+        match &self.dir {
+            Some(dir) => Ok(dir.clone()),
+            None => env::current_dir(),
+        }
+    }
+
+    /// `$HOME`, or the home directory of the user when it is not set.
+    pub fn home_dir(&self) -> Option<PathBuf> {
+        match self.variables.get("HOME") {
+            Some(home) if !home.is_empty() => Some(PathBuf::from(home)),
+            _ => platform::home_dir(),
+        }
+    }
+
+    /// `script: line N: ` put in front of error messages of commands run from
+    /// a script, empty at the prompt.
+    pub fn error_prefix(&self) -> String {
+        match &self.location {
+            Some((name, line)) => format!("{name}: line {line}: "),
+            None => String::new(),
+        }
+    }
+}
+
+/// Functions exported by the shell that started this one. A variable is
+/// only taken when it holds a single definition of the function it is named
+/// after, anything else in it never runs.
+fn imported_functions() -> BTreeMap<String, Function> {
+    let mut functions = BTreeMap::new();
+    for (key, value) in env::vars() {
+        let Some(name) = key.strip_prefix(FUNCTION_PREFIX).and_then(|key| key.strip_suffix(FUNCTION_SUFFIX)) else {
+            continue;
+        };
+        let Ok(Some(list)) = parser::parse(&value) else { continue };
+        let [item] = list.items.as_slice() else { continue };
+        if !item.rest.is_empty() {
+            continue;
+        }
+        if let [PipelineCommand::Function(definition)] = item.first.commands.as_slice() {
+            if definition.name == name {
+                functions.insert(name.to_string(), Function { definition: Arc::clone(definition), exported: true });
+            }
+        }
+    }
+    functions
+}
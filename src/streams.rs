@@ -0,0 +1,135 @@
+//! Standard streams of a running command.
+//!
+//! Builtins read and write through these handles instead of the process
+//! streams, so their input and output can be connected to pipes and files.
+//! External programs get the same handles converted into `Stdio`.
+
+use std::fs::File;
+use std::io::{self, PipeReader, PipeWriter, Read, Write};
+use std::process::Stdio;
+
+/// Writes a line to an output stream. Write errors, such as a pipe closed by
+/// the reading side, are ignored.
+macro_rules! outln {
+    ($stream:expr) => {
+        let _ = writeln!($stream);
+    };
+    ($stream:expr, $($arg:tt)*) => {
+        let _ = writeln!($stream, $($arg)*);
+    };
+}
+
+/// Same as `outln!`, without the trailing newline.
+macro_rules! out {
+    ($stream:expr, $($arg:tt)*) => {
+        let _ = write!($stream, $($arg)*);
+    };
+}
+
+pub enum InputStream {
+    Stdin,
+    Pipe(PipeReader),
+    File(File),
+}
+
+impl InputStream {
+    pub fn into_stdio(self) -> Stdio {
+        match self {
+            InputStream::Stdin => Stdio::inherit(),
+            InputStream::Pipe(reader) => Stdio::from(reader),
+            InputStream::File(file) => Stdio::from(file),
+        }
+    }
+
+    pub fn try_clone(&self) -> io::Result<InputStream> {
+        Ok(match self {
+            InputStream::Stdin => InputStream::Stdin,
+            InputStream::Pipe(reader) => InputStream::Pipe(reader.try_clone()?),
+            InputStream::File(file) => InputStream::File(file.try_clone()?),
+        })
+    }
+}
+
+impl Read for InputStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            InputStream::Stdin => io::stdin().read(buf),
+            InputStream::Pipe(reader) => reader.read(buf),
+            InputStream::File(file) => file.read(buf),
+        }
+    }
+}
+
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+    Pipe(PipeWriter),
+    File(File),
+}
+
+impl OutputStream {
+    pub fn into_stdio(self) -> Stdio {
+        match self {
+            OutputStream::Stdout => Stdio::from(io::stdout()),
+            OutputStream::Stderr => Stdio::from(io::stderr()),
+            OutputStream::Pipe(writer) => Stdio::from(writer),
+            OutputStream::File(file) => Stdio::from(file),
+        }
+    }
+
+    /// A second handle writing to the same place, used for `2>&1`.
+    pub fn try_clone(&self) -> io::Result<OutputStream> {
+        Ok(match self {
+            OutputStream::Stdout => OutputStream::Stdout,
+            OutputStream::Stderr => OutputStream::Stderr,
+            OutputStream::Pipe(writer) => OutputStream::Pipe(writer.try_clone()?),
+            OutputStream::File(file) => OutputStream::File(file.try_clone()?),
+        })
+    }
+}
+
+impl Write for OutputStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            OutputStream::Stdout => io::stdout().write(buf),
+            OutputStream::Stderr => io::stderr().write(buf),
+            OutputStream::Pipe(writer) => writer.write(buf),
+            OutputStream::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            OutputStream::Stdout => io::stdout().flush(),
+            OutputStream::Stderr => io::stderr().flush(),
+            OutputStream::Pipe(writer) => writer.flush(),
+            OutputStream::File(file) => file.flush(),
+        }
+    }
+}
+
+pub struct IoStreams {
+    pub stdin: InputStream,
+    pub stdout: OutputStream,
+    pub stderr: OutputStream,
+}
+
+impl IoStreams {
+    /// Streams of the shell process itself.
+    pub fn inherit() -> IoStreams {
+        IoStreams {
+            stdin: InputStream::Stdin,
+            stdout: OutputStream::Stdout,
+            stderr: OutputStream::Stderr,
+        }
+    }
+
+    /// Handles to the same streams, given to each command of a list.
+    pub fn try_clone(&self) -> io::Result<IoStreams> {
+        Ok(IoStreams {
+            stdin: self.stdin.try_clone()?,
+            stdout: self.stdout.try_clone()?,
+            stderr: self.stderr.try_clone()?,
+        })
+    }
+}
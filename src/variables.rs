@@ -0,0 +1,118 @@
+//! Shell variables. Exported variables make up the environment of the
+//! programs started by the shell.
+
+use std::collections::HashMap;
+use std::env;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub value: String,
+    pub exported: bool,
+}
+
+#[derive(Clone, Default)]
+pub struct Variables {
+    variables: HashMap<String, Variable>,
+}
+
+impl Variables {
+    /// Variables made of the environment of the shell process, all exported.
+    pub fn from_env() -> Variables {
+        let variables = env::vars()
+            .filter(|(name, _)| is_valid_name(name))
+            .map(|(name, value)| (name, Variable { value, exported: true }))
+            .collect();
+        Variables { variables }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(|variable| variable.value.as_str())
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.get(name)
+    }
+
+    /// Sets the value, keeping the exported flag of an existing variable.
+    pub fn set(&mut self, name: &str, value: &str) {
+        match self.variables.get_mut(name) {
+            Some(variable) => variable.value = value.to_string(),
+            None => {
+                let variable = Variable { value: value.to_string(), exported: false };
+                self.variables.insert(name.to_string(), variable);
+            }
+        }
+    }
+
+    /// Marks a variable as exported, creating it empty if it doesn't exist.
+    pub fn export(&mut self, name: &str, exported: bool) {
+        self.variables
+            .entry(name.to_string())
+            .or_insert_with(|| Variable { value: String::new(), exported })
+            .exported = exported;
+    }
+
+    /// Puts back a variable saved with `get_variable`, or removes it.
+    pub fn restore(&mut self, name: &str, saved: Option<Variable>) {
+        match saved {
+            Some(variable) => {
+                self.variables.insert(name.to_string(), variable);
+            }
+            None => self.unset(name),
+        }
+    }
+
+    pub fn unset(&mut self, name: &str) {
+        self.variables.remove(name);
+    }
+
+    /// All variables sorted by name.
+    pub fn sorted(&self) -> Vec<(&str, &Variable)> {
+        let mut variables: Vec<(&str, &Variable)> = self
+            .variables
+            .iter()
+            .map(|(name, variable)| (name.as_str(), variable))
+            .collect();
+        variables.sort_by_key(|(name, _)| *name);
+        variables
+    }
+
+    /// Environment for a program: the exported variables sorted by name.
+    pub fn environment(&self) -> Vec<(&str, &str)> {
+        self.sorted()
+            .into_iter()
+            .filter(|(_, variable)| variable.exported)
+            .map(|(name, variable)| (name, variable.value.as_str()))
+            .collect()
+    }
+}
+
+/// Variable names are made of letters, digits and underscores and don't
+/// start with a digit.
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false,
+    }
+}
+
+/// Splits `NAME=value` into its name and value when the name is valid.
+pub fn split_assignment(word: &str) -> Option<(&str, &str)> {
+    let (name, value) = word.split_once('=')?;
+    if is_valid_name(name) {
+        Some((name, value))
+    } else {
+        None
+    }
+}
+
+/// Quotes a value so it can be read back by the shell.
+pub fn quote(value: &str) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "_-./:@%+,=".contains(c);
+    if !value.is_empty() && value.chars().all(is_plain) {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}